@@ -0,0 +1,143 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Bisection of feature combinations (`cargo hack bisect-features <SUBCOMMAND>`).
+//
+// Instead of running the full powerset, this starts from all features and
+// repeatedly disables halves (and then single features) of the feature set
+// while the command keeps failing, to find a minimal failing feature set.
+
+use std::{collections::HashMap, sync::atomic::Ordering};
+
+use crate::{
+    context::Context, features::Feature, metadata::PackageId, run_with_retry, PackageRuns,
+    ProcessBuilder, Progress, COMMAND_FAILED,
+};
+
+pub(crate) fn run(cx: &Context, packages: &[PackageRuns<'_>]) {
+    let mut line = cx.cargo();
+    line.apply_context(cx);
+    if cx.locked {
+        line.arg("--locked");
+    }
+    for target in &cx.target {
+        line.arg("--target");
+        line.arg(target);
+    }
+    for pkg in packages {
+        bisect_package(cx, pkg.id, &line);
+    }
+}
+
+fn bisect_package(cx: &Context, id: &PackageId, line: &ProcessBuilder<'_>) {
+    let package = cx.packages(id);
+    let mut line = line.clone();
+    line.append_features_from_args(cx, id);
+    if !cx.no_manifest_path {
+        line.arg("--manifest-path");
+        line.arg(
+            package.manifest_path.strip_prefix(&cx.current_dir).unwrap_or(&package.manifest_path),
+        );
+    }
+    line.arg("--no-default-features");
+
+    // `default` only enables other features, and features specified by --features
+    // are always enabled.
+    let features: Vec<&Feature> = cx
+        .pkg_features(id)
+        .normal()
+        .iter()
+        .filter(|f| f.name() != "default" && !cx.exclude_features.iter().any(|e| e == f.name()))
+        .collect();
+
+    // The results of the feature sets that have already been checked.
+    let mut results = HashMap::new();
+    let mut fails = |features: &[&Feature]| -> bool {
+        let mut line = line.clone();
+        line.append_features(features);
+        *results.entry(line.sorted_features()).or_insert_with(|| {
+            info!("running {line} on {} (bisecting)", package.name);
+            match run_with_retry(cx, &mut line, &Progress::default(), None, None) {
+                Ok(()) => false,
+                Err(e) => {
+                    info!("{e:#}");
+                    true
+                }
+            }
+        })
+    };
+
+    if !fails(&features) {
+        info!("package `{}` does not fail with all features; nothing to bisect", package.name);
+        return;
+    }
+    let minimal =
+        if features.is_empty() || fails(&[]) { vec![] } else { minimize(features, fails) };
+    COMMAND_FAILED.store(true, Ordering::Relaxed);
+    let mut line = line.clone();
+    line.append_features(&minimal);
+    error!(
+        "minimal failing feature set of package `{}`: {}",
+        package.name,
+        crate::feature_flags(&line)
+    );
+}
+
+/// Narrows down `set`, for which `fails` returns `true`, to a subset for which
+/// `fails` still returns `true` but does not if any single element is removed.
+///
+/// `fails` is assumed to return `false` for the empty set.
+fn minimize<T: Clone>(mut set: Vec<T>, mut fails: impl FnMut(&[T]) -> bool) -> Vec<T> {
+    while set.len() > 1 {
+        let (first, second) = set.split_at(set.len() / 2);
+        if fails(first) {
+            set = first.to_vec();
+            continue;
+        }
+        if fails(second) {
+            set = second.to_vec();
+            continue;
+        }
+        if set.len() == 2 {
+            // Removing either element gives one of the halves, which do not fail.
+            break;
+        }
+        // The failure needs features from both halves; try to disable them one by one.
+        let reduced = (0..set.len()).find_map(|i| {
+            let mut candidate = set.clone();
+            candidate.remove(i);
+            fails(&candidate).then_some(candidate)
+        });
+        match reduced {
+            Some(reduced) => set = reduced,
+            None => break,
+        }
+    }
+    set
+}
+
+#[cfg(test)]
+mod tests {
+    use super::minimize;
+
+    #[test]
+    fn minimize_features() {
+        let all = vec!["a", "b", "c", "d", "e", "f", "g"];
+
+        // a single feature causes the failure
+        let minimal = minimize(all.clone(), |set| set.contains(&"e"));
+        assert_eq!(minimal, ["e"]);
+
+        // a combination of features from both halves causes the failure
+        let minimal = minimize(all.clone(), |set| set.contains(&"b") && set.contains(&"f"));
+        assert_eq!(minimal, ["b", "f"]);
+
+        let minimal = minimize(all.clone(), |set| {
+            set.contains(&"a") && set.contains(&"d") && set.contains(&"g")
+        });
+        assert_eq!(minimal, ["a", "d", "g"]);
+
+        // either of two features causes the failure
+        let minimal = minimize(all, |set| set.contains(&"c") || set.contains(&"f"));
+        assert_eq!(minimal.len(), 1);
+    }
+}
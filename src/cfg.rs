@@ -0,0 +1,231 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// A minimal evaluator of `cfg(...)` expressions used by `feature-cfg` in
+// `[package.metadata.cargo-hack]` table.
+// https://doc.rust-lang.org/nightly/reference/conditional-compilation.html
+
+use std::{collections::HashSet, fmt, iter::Peekable, str::FromStr};
+
+use anyhow::{bail, format_err, Error, Result};
+
+use crate::ProcessBuilder;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Cfg {
+    /// `name`
+    Name(String),
+    /// `key = "value"`
+    KeyPair(String, String),
+    /// `not(cfg)`
+    Not(Box<Cfg>),
+    /// `all(cfg, ...)`
+    All(Vec<Cfg>),
+    /// `any(cfg, ...)`
+    Any(Vec<Cfg>),
+}
+
+impl Cfg {
+    pub(crate) fn matches(&self, target: &TargetCfg) -> bool {
+        match self {
+            Self::Name(name) => target.values.contains(&(name.clone(), None)),
+            Self::KeyPair(key, value) => {
+                target.values.contains(&(key.clone(), Some(value.clone())))
+            }
+            Self::Not(cfg) => !cfg.matches(target),
+            Self::All(cfgs) => cfgs.iter().all(|cfg| cfg.matches(target)),
+            Self::Any(cfgs) => cfgs.iter().any(|cfg| cfg.matches(target)),
+        }
+    }
+}
+
+impl FromStr for Cfg {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let inner = s
+            .trim()
+            .strip_prefix("cfg")
+            .map(str::trim_start)
+            .and_then(|s| s.strip_prefix('('))
+            .and_then(|s| s.strip_suffix(')'))
+            .ok_or_else(|| format_err!("expected `cfg(...)`, but found `{s}`"))?;
+        let mut tokens = tokenize(inner)?.into_iter().peekable();
+        let cfg = parse_expr(&mut tokens).map_err(|e| format_err!("{e} in `{s}`"))?;
+        if let Some(token) = tokens.next() {
+            bail!("unexpected `{token}` in `{s}`");
+        }
+        Ok(cfg)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    OpenParen,
+    CloseParen,
+    Comma,
+    Equals,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ident(s) => f.write_str(s),
+            Self::Str(s) => write!(f, "\"{s}\""),
+            Self::OpenParen => f.write_str("("),
+            Self::CloseParen => f.write_str(")"),
+            Self::Comma => f.write_str(","),
+            Self::Equals => f.write_str("="),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::OpenParen),
+            ')' => tokens.push(Token::CloseParen),
+            ',' => tokens.push(Token::Comma),
+            '=' => tokens.push(Token::Equals),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => bail!("unterminated string"),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c if c.is_whitespace() => {}
+            c if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut ident = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_ascii_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    ident.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            c => bail!("unexpected character `{c}`"),
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_expr(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Cfg> {
+    let name = match tokens.next() {
+        Some(Token::Ident(name)) => name,
+        Some(token) => bail!("unexpected `{token}`"),
+        None => bail!("unexpected end of expression"),
+    };
+    match tokens.peek() {
+        Some(Token::Equals) => {
+            tokens.next();
+            match tokens.next() {
+                Some(Token::Str(value)) => Ok(Cfg::KeyPair(name, value)),
+                _ => bail!("expected string after `{name} =`"),
+            }
+        }
+        Some(Token::OpenParen) => {
+            tokens.next();
+            let mut cfgs = vec![];
+            loop {
+                if tokens.peek() == Some(&Token::CloseParen) {
+                    tokens.next();
+                    break;
+                }
+                cfgs.push(parse_expr(tokens)?);
+                match tokens.next() {
+                    Some(Token::Comma) => {}
+                    Some(Token::CloseParen) => break,
+                    _ => bail!("expected `,` or `)` in `{name}(...)`"),
+                }
+            }
+            match &*name {
+                "all" => Ok(Cfg::All(cfgs)),
+                "any" => Ok(Cfg::Any(cfgs)),
+                "not" if cfgs.len() == 1 => Ok(Cfg::Not(Box::new(cfgs.pop().unwrap()))),
+                "not" => bail!("`not(...)` takes exactly one predicate"),
+                _ => bail!("unknown predicate `{name}(...)`"),
+            }
+        }
+        _ => Ok(Cfg::Name(name)),
+    }
+}
+
+/// The cfg values of a target, i.e., the output of `rustc --print cfg`.
+pub(crate) struct TargetCfg {
+    values: HashSet<(String, Option<String>)>,
+}
+
+impl TargetCfg {
+    pub(crate) fn new(mut rustc: ProcessBuilder<'_>, target: Option<&str>) -> Result<Self> {
+        rustc.arg("--print");
+        rustc.arg("cfg");
+        if let Some(target) = target {
+            rustc.arg("--target");
+            rustc.arg(target);
+        }
+        Ok(Self::parse(&rustc.read()?))
+    }
+
+    fn parse(s: &str) -> Self {
+        let values = s
+            .lines()
+            .map(|line| match line.split_once('=') {
+                Some((key, value)) => (key.to_owned(), Some(value.trim_matches('"').to_owned())),
+                None => (line.to_owned(), None),
+            })
+            .collect();
+        Self { values }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cfg, TargetCfg};
+
+    #[test]
+    fn parse() {
+        assert_eq!("cfg(unix)".parse::<Cfg>().unwrap(), Cfg::Name("unix".into()));
+        assert_eq!(
+            "cfg(target_os = \"linux\")".parse::<Cfg>().unwrap(),
+            Cfg::KeyPair("target_os".into(), "linux".into())
+        );
+        assert_eq!(
+            "cfg(all(unix, not(target_os = \"macos\")))".parse::<Cfg>().unwrap(),
+            Cfg::All(vec![
+                Cfg::Name("unix".into()),
+                Cfg::Not(Box::new(Cfg::KeyPair("target_os".into(), "macos".into()))),
+            ])
+        );
+        assert!("unix".parse::<Cfg>().is_err());
+        assert!("cfg(not(unix, windows))".parse::<Cfg>().is_err());
+        assert!("cfg(foo(unix))".parse::<Cfg>().is_err());
+        assert!("cfg(target_os = linux)".parse::<Cfg>().is_err());
+        assert!("cfg(unix windows)".parse::<Cfg>().is_err());
+    }
+
+    #[test]
+    fn matches() {
+        let linux = TargetCfg::parse("target_family=\"unix\"\ntarget_os=\"linux\"\nunix\n");
+        let windows =
+            TargetCfg::parse("target_family=\"windows\"\ntarget_os=\"windows\"\nwindows\n");
+        let cfg: Cfg = "cfg(unix)".parse().unwrap();
+        assert!(cfg.matches(&linux));
+        assert!(!cfg.matches(&windows));
+        let cfg: Cfg = "cfg(any(target_os = \"macos\", windows))".parse().unwrap();
+        assert!(!cfg.matches(&linux));
+        assert!(cfg.matches(&windows));
+        let cfg: Cfg = "cfg(not(target_os = \"linux\"))".parse().unwrap();
+        assert!(!cfg.matches(&linux));
+        assert!(cfg.matches(&windows));
+    }
+}
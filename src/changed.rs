@@ -0,0 +1,143 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Detection of the packages changed since a git ref (--changed-since).
+//
+// The files changed since the ref are listed by `git diff --name-only`, and
+// each file is mapped to the workspace member whose manifest directory
+// contains it (the innermost one if packages are nested). Changes to
+// `Cargo.toml` or `Cargo.lock` in the workspace root that do not belong to a
+// package are considered to affect all members, and other files outside of
+// all members are ignored.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+
+use crate::metadata::{Metadata, PackageId};
+
+pub(crate) fn packages(metadata: &Metadata, git_ref: &str) -> Result<HashSet<PackageId>> {
+    let files = changed_files(&metadata.workspace_root, git_ref)
+        .with_context(|| format!("failed to find files changed since `{git_ref}`"))?;
+    let dirs: Vec<(&PackageId, PathBuf)> = metadata
+        .workspace_members
+        .iter()
+        .map(|id| {
+            let dir = metadata.packages[id].manifest_path.parent().unwrap();
+            (id, canonicalize(dir))
+        })
+        .collect();
+    let root = canonicalize(&metadata.workspace_root);
+
+    let mut changed = HashSet::new();
+    for file in &files {
+        match owner(file, &dirs) {
+            Some(id) => {
+                changed.insert(id.clone());
+            }
+            None if file.parent() == Some(&root)
+                && file.file_name().is_some_and(|f| f == "Cargo.toml" || f == "Cargo.lock") =>
+            {
+                return Ok(metadata.workspace_members.iter().cloned().collect());
+            }
+            None => {}
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Returns `changed` and the workspace members that depend on them, directly
+/// or through other workspace members (--include-dependents).
+pub(crate) fn with_dependents<'a>(
+    metadata: &'a Metadata,
+    changed: &'a HashSet<PackageId>,
+) -> HashSet<&'a PackageId> {
+    let dependents = reverse_dependencies(metadata);
+    let mut selected: HashSet<&PackageId> = changed.iter().collect();
+    let mut queue: Vec<&PackageId> = changed.iter().collect();
+    while let Some(id) = queue.pop() {
+        for &dependent in dependents.get(id).into_iter().flatten() {
+            if selected.insert(dependent) {
+                queue.push(dependent);
+            }
+        }
+    }
+    selected
+}
+
+/// Returns the workspace members that directly depend on each workspace member.
+fn reverse_dependencies(metadata: &Metadata) -> HashMap<&PackageId, Vec<&PackageId>> {
+    let members = &metadata.workspace_members;
+    let mut dependents: HashMap<_, Vec<_>> = HashMap::new();
+    for id in members {
+        // The resolved dependency graph is not available if cargo-metadata is
+        // run with --no-deps, so fall back to the dependency declarations.
+        if let Some(node) = metadata.resolve.nodes.get(id) {
+            for dep in node.deps.iter().filter(|dep| members.contains(&dep.pkg)) {
+                dependents.entry(&dep.pkg).or_default().push(id);
+            }
+            continue;
+        }
+        for dep in &metadata.packages[id].dependencies {
+            let dependency = members.iter().find(|&member| {
+                let package = &metadata.packages[member];
+                match &dep.path {
+                    Some(path) => package.manifest_path.parent() == Some(path),
+                    None => package.name == dep.name,
+                }
+            });
+            if let Some(dependency) = dependency {
+                dependents.entry(dependency).or_default().push(id);
+            }
+        }
+    }
+    dependents
+}
+
+/// Returns the absolute paths of the files that differ from `git_ref`,
+/// including uncommitted changes.
+fn changed_files(workspace_root: &Path, git_ref: &str) -> Result<Vec<PathBuf>> {
+    let toplevel = cmd!("git", "-C", workspace_root, "rev-parse", "--show-toplevel").read()?;
+    let toplevel = canonicalize(Path::new(&toplevel));
+    let output =
+        cmd!("git", "-C", workspace_root, "diff", "--name-only", "--no-renames", git_ref, "--")
+            .read()?;
+    Ok(output.lines().filter(|l| !l.is_empty()).map(|l| toplevel.join(l)).collect())
+}
+
+/// Returns the package whose directory contains `file`, preferring the
+/// innermost one.
+fn owner<'a>(file: &Path, dirs: &[(&'a PackageId, PathBuf)]) -> Option<&'a PackageId> {
+    dirs.iter()
+        .filter(|(_, dir)| file.starts_with(dir))
+        .max_by_key(|(_, dir)| dir.components().count())
+        .map(|&(id, _)| id)
+}
+
+// git returns the resolved path of the repository, which may differ from the
+// paths returned by cargo-metadata if the workspace is accessed via a symlink.
+fn canonicalize(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::owner;
+    use crate::metadata::PackageId;
+
+    #[test]
+    fn owner_of_file() {
+        let root = PackageId::from("root".to_owned());
+        let member = PackageId::from("member".to_owned());
+        let dirs = [(&root, PathBuf::from("/ws")), (&member, PathBuf::from("/ws/member"))];
+        assert!(owner(Path::new("/ws/src/lib.rs"), &dirs) == Some(&root));
+        assert!(owner(Path::new("/ws/member/src/lib.rs"), &dirs) == Some(&member));
+        assert!(owner(Path::new("/ws/member2/src/lib.rs"), &dirs) == Some(&root));
+        assert!(owner(Path::new("/other/src/lib.rs"), &dirs).is_none());
+    }
+}
@@ -1,10 +1,12 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
 use std::{
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
     env,
     ffi::{OsStr, OsString},
     fmt, mem,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
 use anyhow::{bail, format_err, Result};
@@ -13,13 +15,19 @@ use lexopt::{
     ValueExt,
 };
 
-use crate::{term, version::VersionRange, Feature, LogGroup, Rustup};
+use crate::{
+    features::Depth, summary, term, version::VersionRange, Feature, LogGroup, Phase, Rustup,
+};
 
 pub(crate) struct Args {
     pub(crate) leading_args: Vec<String>,
     pub(crate) trailing_args: Vec<String>,
 
     pub(crate) subcommand: Option<String>,
+    /// `+<toolchain>` specified before the subcommand.
+    pub(crate) toolchain: Option<String>,
+    /// `bisect-features` specified before the subcommand.
+    pub(crate) bisect_features: bool,
 
     /// --manifest-path <PATH>
     pub(crate) manifest_path: Option<String>,
@@ -31,34 +39,137 @@ pub(crate) struct Args {
     pub(crate) package: Vec<String>,
     /// --exclude <SPEC>...
     pub(crate) exclude: Vec<String>,
+    /// --exclude-current
+    pub(crate) exclude_current: bool,
+    /// --frozen-features
+    pub(crate) frozen_features: bool,
     /// --workspace, (--all)
     pub(crate) workspace: bool,
     /// --each-feature
     pub(crate) each_feature: bool,
     /// --feature-powerset
     pub(crate) feature_powerset: bool,
+    /// --default-only
+    pub(crate) default_only: bool,
+    /// --only-all-features
+    pub(crate) only_all_features: bool,
     /// --no-dev-deps
     pub(crate) no_dev_deps: bool,
+    /// Flags selecting targets that may require dev-dependencies (e.g., `--examples`),
+    /// with the target name if specified. Used together with --no-dev-deps.
+    pub(crate) dev_targets: Vec<(String, Option<String>)>,
+    /// --force
+    pub(crate) force: bool,
     /// --remove-dev-deps
     pub(crate) remove_dev_deps: bool,
+    /// --remove-dev-dep <NAME>...
+    pub(crate) remove_dev_dep: Vec<String>,
+    /// --backup-manifests <DIR>
+    pub(crate) backup_manifests: Option<PathBuf>,
+    /// --keep-backups
+    pub(crate) keep_backups: bool,
     /// --no-private
     pub(crate) no_private: bool,
     /// --ignore-private
     pub(crate) ignore_private: bool,
+    /// --ignore-private=restricted
+    pub(crate) ignore_private_restricted: bool,
+    /// --only-packages-with-feature <FEATURE>
+    pub(crate) only_packages_with_feature: Option<String>,
+    /// --changed-since <REF>
+    pub(crate) changed_since: Option<String>,
+    /// --include-dependents
+    pub(crate) include_dependents: bool,
     /// --ignore-unknown-features
     pub(crate) ignore_unknown_features: bool,
+    /// --no-skip-targetless
+    pub(crate) no_skip_targetless: bool,
+    /// --print-features
+    pub(crate) print_features: bool,
+    /// --check-feature-unification
+    pub(crate) check_feature_unification: bool,
+    /// --print-package-list
+    pub(crate) print_package_list: bool,
+    /// --gh-matrix
+    pub(crate) gh_matrix: bool,
+    /// --exec <COMMAND>
+    pub(crate) exec: Option<String>,
+    /// --command-prefix <COMMAND>, split on whitespace.
+    pub(crate) command_prefix: Vec<String>,
+    /// --env <KEY=VALUE>...
+    pub(crate) env: BTreeMap<String, String>,
+    /// --deny-warnings
+    pub(crate) deny_warnings: bool,
+    /// --clippy-args <ARGS>, split on whitespace.
+    pub(crate) clippy_args: Vec<String>,
+    /// --also <SUBCOMMAND>...
+    /// For each subcommand, the subcommand and `leading_args` with the primary
+    /// subcommand replaced.
+    pub(crate) also: Vec<(String, Vec<String>)>,
+    /// --retry <N>
+    pub(crate) retry: usize,
+    /// --timeout <SECONDS>
+    pub(crate) timeout: Option<Duration>,
+    /// --package-jobs <N>
+    pub(crate) package_jobs: usize,
+    /// --continue-from <INDEX>
+    pub(crate) continue_from: Option<usize>,
+    /// --resume
+    pub(crate) resume: bool,
+    /// --status-file <PATH>
+    pub(crate) status_file: Option<PathBuf>,
+    /// Whether to show the progress bar (disabled by --no-progress).
+    pub(crate) progress_bar: bool,
     /// --clean-per-run
     pub(crate) clean_per_run: bool,
     /// --clean-per-version
     pub(crate) clean_per_version: bool,
-    /// --keep-going
+    /// --target-dir-per-combination
+    pub(crate) target_dir_per_combination: bool,
+    /// --keep-target-dirs
+    pub(crate) keep_target_dirs: bool,
+    /// --keep-going, --no-fail-fast
     pub(crate) keep_going: bool,
+    /// --stop-on-first-success
+    pub(crate) stop_on_first_success: bool,
+    /// --replay-failures
+    pub(crate) replay_failures: bool,
+    /// --log-dir <DIR>
+    pub(crate) log_dir: Option<PathBuf>,
+    /// --summary-format <FORMAT>
+    pub(crate) summary_format: Option<summary::Format>,
+    /// --summary-file <PATH>
+    pub(crate) summary_file: Option<PathBuf>,
+    /// --gh-summary, --no-gh-summary
+    ///
+    /// The file specified by `GITHUB_STEP_SUMMARY`, to which the summary is
+    /// appended on GitHub Actions or if --gh-summary is used.
+    pub(crate) gh_summary: Option<PathBuf>,
+    /// --allow-failure <FEATURES>...
+    pub(crate) allow_failure: Vec<BTreeSet<String>>,
     /// --print-command-list
     pub(crate) print_command_list: bool,
+    /// --count
+    pub(crate) count: bool,
+    /// --check-plan
+    pub(crate) check_plan: bool,
+    /// `leading_args` with the primary subcommand replaced by `check`, used by
+    /// --check-plan.
+    pub(crate) check_plan_args: Vec<String>,
+    /// --dump-features <PATH>
+    pub(crate) dump_features: Option<PathBuf>,
+    /// --run-plan <PATH>
+    pub(crate) run_plan: Option<PathBuf>,
+    /// --combination-warn-limit <N>
+    pub(crate) combination_warn_limit: usize,
     /// --version-range/--rust-version
     pub(crate) version_range: Option<VersionRange>,
     /// --version-step
     pub(crate) version_step: u16,
+    /// --print-toolchains
+    pub(crate) print_toolchains: bool,
+    /// --install-toolchains
+    pub(crate) install_toolchains: bool,
     /// --log-group
     pub(crate) log_group: LogGroup,
 
@@ -78,10 +189,26 @@ pub(crate) struct Args {
     pub(crate) exclude_no_default_features: bool,
     /// --exclude-all-features
     pub(crate) exclude_all_features: bool,
+    /// --order <PHASES>, --all-features-first
+    pub(crate) order: Vec<Phase>,
+    /// --always-include-default-run
+    pub(crate) always_include_default_run: bool,
+    /// --exclude-default-member-features
+    pub(crate) exclude_default_member_features: bool,
+    /// --exclude-features-matching-cfg
+    pub(crate) exclude_features_matching_cfg: bool,
+    /// --include-features-from-default
+    pub(crate) include_features_from_default: bool,
+    /// --max-combinations-per-package <N>
+    pub(crate) max_combinations_per_package: Option<usize>,
+    /// --explain-plan
+    pub(crate) explain_plan: bool,
+    /// --matrix-file <PATH>
+    pub(crate) matrix_file: Option<String>,
 
     // options for --feature-powerset
-    /// --depth <NUM>
-    pub(crate) depth: Option<usize>,
+    /// --depth <NUM|NUM%|max>
+    pub(crate) depth: Option<Depth>,
     /// --group-features <FEATURES>...
     pub(crate) group_features: Vec<Feature>,
     /// `--mutually-exclusive-features <FEATURES>`
@@ -132,10 +259,11 @@ impl Args {
             }
             args.push(arg);
         }
-        let rest = raw_args.collect::<Result<Vec<_>>>()?;
+        let mut rest = raw_args.collect::<Result<Vec<_>>>()?;
 
         let mut cargo_args = vec![];
         let mut subcommand: Option<String> = None;
+        let mut toolchain: Option<String> = None;
 
         let mut manifest_path: Option<String> = None;
         let mut color = None;
@@ -145,23 +273,75 @@ impl Args {
         let mut features = vec![];
 
         let mut workspace = false;
+        let mut exclude_current = false;
+        let mut frozen_features = false;
         let mut no_dev_deps = false;
+        let mut force = false;
         let mut remove_dev_deps = false;
+        let mut remove_dev_dep = vec![];
+        let mut backup_manifests: Option<String> = None;
+        let mut keep_backups = false;
         let mut each_feature = false;
         let mut feature_powerset = false;
+        let mut default_only = false;
+        let mut only_all_features = false;
         let mut no_private = false;
         let mut ignore_private = false;
+        let mut ignore_private_restricted = false;
+        let mut only_packages_with_feature = None;
+        let mut changed_since = None;
+        let mut include_dependents = false;
         let mut ignore_unknown_features = false;
+        let mut no_skip_targetless = false;
+        let mut print_features = false;
+        let mut check_feature_unification = false;
+        let mut print_package_list = false;
+        let mut gh_matrix = false;
+        let mut exec = None;
+        let mut command_prefix: Option<String> = None;
+        let mut env = BTreeMap::new();
+        let mut deny_warnings = false;
+        let mut clippy_args: Option<String> = None;
+        let mut cargo_arg = vec![];
+        let mut also: Vec<String> = vec![];
+        let mut subcommand_index = 0;
+        let mut retry = None;
+        let mut timeout = None;
+        let mut package_jobs = None;
+        let mut no_progress = false;
+        let mut continue_from = None;
+        let mut resume = false;
+        let mut status_format: Option<String> = None;
+        let mut status_file: Option<String> = None;
         let mut clean_per_run = false;
         let mut clean_per_version = false;
+        let mut target_dir_per_combination = false;
+        let mut keep_target_dirs = false;
         let mut keep_going = false;
+        let mut replay_failures = false;
+        let mut log_dir = None;
+        let mut summary_format: Option<String> = None;
+        let mut summary_file = None;
+        let mut gh_summary = false;
+        let mut no_gh_summary = false;
+        let mut no_fail_fast = false;
+        let mut stop_on_first_success = false;
+        let mut allow_failure = vec![];
         let mut print_command_list = false;
+        let mut count = false;
+        let mut check_plan = false;
+        let mut dump_features: Option<String> = None;
+        let mut run_plan: Option<String> = None;
+        let mut combination_warn_limit = None;
         let mut no_manifest_path = false;
         let mut locked = false;
         let mut rust_version = false;
         let mut version_range = None;
         let mut version_step = None;
+        let mut print_toolchains = false;
+        let mut install_toolchains = false;
         let mut log_group: Option<String> = None;
+        let mut log_format: Option<String> = None;
         let mut disable_log_grouping = false;
 
         let mut optional_deps = None;
@@ -172,8 +352,19 @@ impl Args {
         let mut exclude_features = vec![];
         let mut exclude_no_default_features = false;
         let mut exclude_all_features = false;
+        let mut all_features_first = false;
+        let mut only_powerset = false;
+        let mut order: Option<String> = None;
+        let mut always_include_default_run = false;
+        let mut exclude_default_member_features = false;
+        let mut exclude_features_matching_cfg = false;
+        let mut include_features_from_default = false;
+        let mut max_combinations_per_package = None;
+        let mut explain_plan = false;
+        let mut matrix_file = None;
 
         let mut group_features: Vec<String> = vec![];
+        let mut feature_aliases: BTreeMap<String, Vec<String>> = BTreeMap::new();
         let mut mutually_exclusive_features: Vec<String> = vec![];
         let mut depth = None;
 
@@ -190,8 +381,14 @@ impl Args {
         // cargo test -v --target x86_64-apple-darwin --target aarch64-apple-darwin --target x86_64-apple-darwin
         // ```
         let mut target = BTreeSet::new();
-
-        let mut parser = lexopt::Parser::from_args(args);
+        let mut bisect_features = false;
+        // Unstable cargo flags (`-Z <FLAG>`, `-Z<FLAG>`).
+        let mut unstable_flags: Vec<String> = vec![];
+
+        // cargo-hack's own flags seen in the arguments, used for the error message when the
+        // subcommand is missing.
+        let mut hack_flags: Vec<String> = vec![];
+        let mut parser = lexopt::Parser::from_args(args.clone());
         let mut next_flag: Option<OwnedFlag> = None;
         loop {
             let arg = next_flag.take();
@@ -202,6 +399,12 @@ impl Args {
                     None => break,
                 },
             };
+            if let Long(_) | Short(_) = arg {
+                let flag = format_flag(&arg);
+                if get_help(&flag).is_some() && !hack_flags.contains(&flag) {
+                    hack_flags.push(flag);
+                }
+            }
 
             macro_rules! parse_opt {
                 ($opt:ident, $propagate:expr $(,)?) => {{
@@ -221,15 +424,24 @@ impl Args {
 
             macro_rules! parse_multi_opt {
                 ($v:ident $(,)?) => {{
+                    let flag = format_flag(&arg);
                     let val = parser.value()?;
-                    let mut val = val.to_str().unwrap();
+                    let val = val.to_str().unwrap();
+                    // `@env:<VAR>` reads the list from the environment variable.
+                    let env_val;
+                    let mut val = match val.strip_prefix("@env:") {
+                        Some(name) => {
+                            env_val = read_env_list(&flag, name)?;
+                            env_val.as_str()
+                        }
+                        None => val,
+                    };
                     if val.starts_with('\'') && val.ends_with('\'')
                         || val.starts_with('"') && val.ends_with('"')
                     {
                         val = &val[1..val.len() - 1];
                     }
-                    let sep = if val.contains(',') { ',' } else { ' ' };
-                    $v.extend(val.split(sep).filter(|s| !s.is_empty()).map(str::to_owned));
+                    $v.extend(split_features(val).map(str::to_owned));
                 }};
             }
 
@@ -249,14 +461,64 @@ impl Args {
 
                 Long("manifest-path") => parse_opt!(manifest_path, false),
                 Long("depth") => parse_opt!(depth, false),
+                Long("max-combinations-per-package") => {
+                    parse_opt!(max_combinations_per_package, false);
+                }
+                Long("explain-plan") => parse_flag!(explain_plan),
+                Long("matrix-file") => parse_opt!(matrix_file, false),
                 Long("rust-version") => parse_flag!(rust_version),
                 Long("version-range") => parse_opt!(version_range, false),
                 Long("version-step") => parse_opt!(version_step, false),
+                Long("print-toolchains") => parse_flag!(print_toolchains),
+                Long("install-toolchains") => parse_flag!(install_toolchains),
                 Long("log-group") => parse_opt!(log_group, false),
+                Long("log-format") => parse_opt!(log_format, false),
+                Long("exec") => parse_opt!(exec, false),
+                Long("command-prefix") => parse_opt!(command_prefix, false),
+                Long("env") => {
+                    let val = parser.value()?.string()?;
+                    match val.split_once('=') {
+                        Some((key, value)) if !key.is_empty() => {
+                            env.insert(key.to_owned(), value.to_owned());
+                        }
+                        _ => bail!(
+                            "argument for --env must be in the form KEY=VALUE, but found `{val}`"
+                        ),
+                    }
+                }
+                Long("deny-warnings") => parse_flag!(deny_warnings),
+                Long("clippy-args") => parse_opt!(clippy_args, false),
+                Long("cargo-arg") => cargo_arg.push(parser.value()?.string()?),
+                Long("also") => also.push(parser.value()?.string()?),
+                Long("retry") => parse_opt!(retry, false),
+                Long("timeout") => parse_opt!(timeout, false),
+                Long("package-jobs") => parse_opt!(package_jobs, false),
+                Long("no-progress") => parse_flag!(no_progress),
+                Long("continue-from") => parse_opt!(continue_from, false),
+                Long("resume") => parse_flag!(resume),
+                Long("status-format") => parse_opt!(status_format, false),
+                Long("status-file") => parse_opt!(status_file, false),
 
                 Short('p') | Long("package") => package.push(parser.value()?.parse()?),
                 Long("exclude") => exclude.push(parser.value()?.parse()?),
                 Long("group-features") => group_features.push(parser.value()?.parse()?),
+                Long("feature-alias") => {
+                    let val = parser.value()?.string()?;
+                    let Some((name, list)) = val.split_once('=').filter(|(n, _)| !n.is_empty())
+                    else {
+                        bail!(
+                            "argument for --feature-alias must be in the form NAME=FEATURES, but \
+                             found `{val}`"
+                        );
+                    };
+                    let list: Vec<_> = split_features(list).map(str::to_owned).collect();
+                    if list.is_empty() {
+                        bail!("--feature-alias requires at least one feature for alias `{name}`");
+                    }
+                    if feature_aliases.insert(name.to_owned(), list).is_some() {
+                        bail!("feature alias `{name}` specified multiple times");
+                    }
+                }
                 Long("mutually-exclusive-features") => {
                     mutually_exclusive_features.push(parser.value()?.parse()?);
                 }
@@ -264,6 +526,11 @@ impl Args {
                 Short('F') | Long("features") => parse_multi_opt!(features),
                 Long("skip" | "exclude-features") => parse_multi_opt!(exclude_features),
                 Long("include-features") => parse_multi_opt!(include_features),
+                Long("allow-failure") => {
+                    let mut set: Vec<String> = vec![];
+                    parse_multi_opt!(set);
+                    allow_failure.push(set.into_iter().collect());
+                }
 
                 Long("optional-deps") => {
                     if optional_deps.is_some() {
@@ -287,31 +554,90 @@ impl Args {
                     {
                         val = &val[1..val.len() - 1];
                     }
-                    if val.contains(',') {
-                        optional_deps.extend(val.split(',').map(str::to_owned));
+                    if val.is_empty() {
+                        // --optional-deps=
+                        optional_deps.push(String::new());
                     } else {
-                        optional_deps.extend(val.split(' ').map(str::to_owned));
+                        optional_deps.extend(split_features(val).map(str::to_owned));
                     }
                 }
 
                 Long("workspace" | "all") => parse_flag!(workspace),
+                Long("exclude-current") => parse_flag!(exclude_current),
+                Long("frozen-features") => parse_flag!(frozen_features),
                 Long("no-dev-deps") => parse_flag!(no_dev_deps),
+                Long("force") => parse_flag!(force),
                 Long("remove-dev-deps") => parse_flag!(remove_dev_deps),
+                Long("remove-dev-dep") => remove_dev_dep.push(parser.value()?.string()?),
+                Long("backup-manifests") => parse_opt!(backup_manifests, false),
+                Long("keep-backups") => parse_flag!(keep_backups),
                 Long("each-feature") => parse_flag!(each_feature),
                 Long("feature-powerset") => parse_flag!(feature_powerset),
+                Long("default-only") => parse_flag!(default_only),
+                Long("only-all-features") => parse_flag!(only_all_features),
                 Long("at-least-one-of") => at_least_one_of.push(parser.value()?.parse()?),
                 Long("no-private") => parse_flag!(no_private),
-                Long("ignore-private") => parse_flag!(ignore_private),
+                Long("ignore-private") => {
+                    parse_flag!(ignore_private);
+                    if let Some(val) = parser.optional_value() {
+                        let val = val.string()?;
+                        if val != "restricted" {
+                            bail!(
+                                "argument for --ignore-private must be `restricted`, but found \
+                                 `{val}`"
+                            );
+                        }
+                        ignore_private_restricted = true;
+                    }
+                }
+                Long("only-packages-with-feature") => {
+                    parse_opt!(only_packages_with_feature, false);
+                }
+                Long("changed-since") => parse_opt!(changed_since, false),
+                Long("include-dependents") => parse_flag!(include_dependents),
                 Long("exclude-no-default-features") => parse_flag!(exclude_no_default_features),
                 Long("exclude-all-features") => parse_flag!(exclude_all_features),
+                Long("all-features-first") => parse_flag!(all_features_first),
+                Long("only-powerset") => parse_flag!(only_powerset),
+                Long("order") => parse_opt!(order, false),
+                Long("always-include-default-run") => parse_flag!(always_include_default_run),
+                Long("exclude-default-member-features") => {
+                    parse_flag!(exclude_default_member_features);
+                }
+                Long("exclude-features-matching-cfg") => {
+                    parse_flag!(exclude_features_matching_cfg);
+                }
+                Long("include-features-from-default") => {
+                    parse_flag!(include_features_from_default);
+                }
                 Long("include-deps-features") => parse_flag!(include_deps_features),
                 Long("clean-per-run") => parse_flag!(clean_per_run),
                 Long("clean-per-version") => parse_flag!(clean_per_version),
+                Long("target-dir-per-combination") => parse_flag!(target_dir_per_combination),
+                Long("keep-target-dirs") => parse_flag!(keep_target_dirs),
                 Long("keep-going") => parse_flag!(keep_going),
+                Long("replay-failures") => parse_flag!(replay_failures),
+                Long("log-dir") => parse_opt!(log_dir, false),
+                Long("summary-format") => parse_opt!(summary_format, false),
+                Long("summary-file") => parse_opt!(summary_file, false),
+                Long("gh-summary") => parse_flag!(gh_summary),
+                Long("no-gh-summary") => parse_flag!(no_gh_summary),
+                Long("no-fail-fast") => parse_flag!(no_fail_fast),
+                Long("stop-on-first-success") => parse_flag!(stop_on_first_success),
                 Long("print-command-list") => parse_flag!(print_command_list),
+                Long("count") => parse_flag!(count),
+                Long("check-plan") => parse_flag!(check_plan),
+                Long("dump-features") => parse_opt!(dump_features, false),
+                Long("run-plan") => parse_opt!(run_plan, false),
+                Long("combination-warn-limit") => parse_opt!(combination_warn_limit, false),
                 Long("no-manifest-path") => parse_flag!(no_manifest_path),
                 Long("locked") => parse_flag!(locked),
                 Long("ignore-unknown-features") => parse_flag!(ignore_unknown_features),
+                Long("no-skip-targetless") => parse_flag!(no_skip_targetless),
+                Long("print-features") => parse_flag!(print_features),
+                Long("check-feature-unification") => parse_flag!(check_feature_unification),
+                Long("print-package-list") => parse_flag!(print_package_list),
+                Long("gh-matrix") => parse_flag!(gh_matrix),
                 Short('v') | Long("verbose") => verbose += 1,
 
                 // propagated
@@ -352,6 +678,7 @@ impl Args {
                         cargo_args.push(flag);
                     }
                 }
+                Short('Z') => unstable_flags.push(parser.value()?.string()?),
                 Short(flag) => {
                     if matches!(flag, 'n' | 'q' | 'r') {
                         // To handle combined short flags properly, handle known
@@ -366,20 +693,74 @@ impl Args {
                 Value(val) => {
                     let val = val.string()?;
                     if subcommand.is_none() {
+                        if let Some(t) = val.strip_prefix('+') {
+                            if toolchain.is_some() {
+                                bail!("the toolchain (`+<toolchain>`) may not be specified multiple times");
+                            }
+                            toolchain = Some(t.to_owned());
+                            continue;
+                        }
+                        if val == "bisect-features" && !bisect_features {
+                            bisect_features = true;
+                            continue;
+                        }
                         subcommand = Some(val.clone());
+                        subcommand_index = cargo_args.len();
                     }
                     cargo_args.push(val);
                 }
             }
         }
 
+        // If `--` appears before the subcommand (e.g., `cargo hack -- check`), it only
+        // ends the parsing of the flags: the next argument is the subcommand, and
+        // the arguments after the next `--` are the trailing arguments.
+        if subcommand.is_none()
+            && exec.is_none()
+            && rest.first().is_some_and(|a| !a.starts_with('-'))
+        {
+            let mut args = mem::take(&mut rest).into_iter();
+            let val = args.next().unwrap();
+            subcommand = Some(val.clone());
+            subcommand_index = cargo_args.len();
+            cargo_args.push(val);
+            for arg in &mut args {
+                if arg == "--" {
+                    break;
+                }
+                cargo_args.push(arg);
+            }
+            rest = args.collect();
+        }
+
+        // --feature-alias: expand `@<name>` in the lists of features.
+        include_features = expand_feature_aliases(&feature_aliases, &include_features)?;
+        exclude_features = expand_feature_aliases(&feature_aliases, &exclude_features)?;
+        group_features = group_features
+            .iter()
+            .map(|g| {
+                let g: Vec<_> = split_features(g).map(str::to_owned).collect();
+                Ok(expand_feature_aliases(&feature_aliases, &g)?.join(","))
+            })
+            .collect::<Result<_>>()?;
+
         term::set_coloring(color.as_deref())?;
+        match log_format.as_deref() {
+            None | Some("human") => {}
+            Some("short") => term::short_log::set(true),
+            Some(other) => {
+                bail!("argument for --log-format must be human or short, but found `{other}`")
+            }
+        }
 
         if !exclude.is_empty() && !workspace {
             // TODO: This is the same behavior as cargo, but should we allow it to be used
             // in the root of a virtual workspace as well?
             requires("--exclude", &["--workspace"])?;
         }
+        if exclude_current && !workspace {
+            requires("--exclude-current", &["--workspace"])?;
+        }
         if ignore_unknown_features {
             if features.is_empty() && include_features.is_empty() && group_features.is_empty() {
                 requires("--ignore-unknown-features", &[
@@ -408,13 +789,96 @@ impl Args {
                 ])?;
             } else if exclude_all_features {
                 requires("--exclude-all-features", &["--each-feature", "--feature-powerset"])?;
+            } else if all_features_first {
+                requires("--all-features-first", &["--each-feature", "--feature-powerset"])?;
+            } else if only_powerset {
+                requires("--only-powerset", &["--each-feature", "--feature-powerset"])?;
+            } else if order.is_some() {
+                requires("--order", &["--each-feature", "--feature-powerset"])?;
+            } else if always_include_default_run {
+                requires("--always-include-default-run", &[
+                    "--each-feature",
+                    "--feature-powerset",
+                ])?;
+            } else if exclude_default_member_features {
+                requires("--exclude-default-member-features", &[
+                    "--each-feature",
+                    "--feature-powerset",
+                ])?;
+            } else if exclude_features_matching_cfg {
+                requires("--exclude-features-matching-cfg", &[
+                    "--each-feature",
+                    "--feature-powerset",
+                ])?;
+            } else if include_features_from_default {
+                requires("--include-features-from-default", &[
+                    "--each-feature",
+                    "--feature-powerset",
+                ])?;
+            } else if max_combinations_per_package.is_some() {
+                requires("--max-combinations-per-package", &[
+                    "--each-feature",
+                    "--feature-powerset",
+                ])?;
+            } else if explain_plan {
+                requires("--explain-plan", &["--each-feature", "--feature-powerset"])?;
+            } else if matrix_file.is_some() {
+                requires("--matrix-file", &["--each-feature", "--feature-powerset"])?;
             } else if !include_features.is_empty() {
                 requires("--include-features", &["--each-feature", "--feature-powerset"])?;
-            } else if include_deps_features {
-                requires("--include-deps-features", &["--each-feature", "--feature-powerset"])?;
+            } else if include_deps_features && !print_features {
+                requires("--include-deps-features", &[
+                    "--each-feature",
+                    "--feature-powerset",
+                    "--print-features",
+                ])?;
             }
         }
 
+        let order = match order {
+            Some(order) => {
+                if all_features_first {
+                    conflicts("--order", "--all-features-first")?;
+                } else if only_powerset {
+                    conflicts("--order", "--only-powerset")?;
+                }
+                let mut phases: Vec<Phase> = vec![];
+                for phase in order.split(',') {
+                    let phase = phase.trim().parse()?;
+                    if phases.contains(&phase) {
+                        bail!("phase `{phase}` specified multiple times in --order");
+                    }
+                    phases.push(phase);
+                }
+                for (phase, excluded, flag) in [
+                    (
+                        Phase::NoDefault,
+                        exclude_no_default_features,
+                        "--exclude-no-default-features",
+                    ),
+                    (Phase::All, exclude_all_features, "--exclude-all-features"),
+                    (Phase::Default, no_default_features, "--no-default-features"),
+                    (
+                        Phase::Default,
+                        exclude_features.iter().any(|f| f == "default"),
+                        "--exclude-features default",
+                    ),
+                ] {
+                    if excluded && phases.contains(&phase) {
+                        warn!("phase `{phase}` specified by --order is excluded by {flag}");
+                    }
+                }
+                phases
+            }
+            None if only_powerset => {
+                if all_features_first {
+                    conflicts("--only-powerset", "--all-features-first")?;
+                }
+                vec![Phase::Features]
+            }
+            None if all_features_first => vec![Phase::All, Phase::NoDefault, Phase::Features],
+            None => Phase::DEFAULT_ORDER.to_vec(),
+        };
         if !at_least_one_of.is_empty() {
             // there will always be a feature set
             exclude_no_default_features = true;
@@ -432,7 +896,7 @@ impl Args {
             }
         }
 
-        let depth = depth.as_deref().map(str::parse::<usize>).transpose()?;
+        let depth = depth.as_deref().map(str::parse::<Depth>).transpose()?;
         let group_features = parse_grouped_features(&group_features, "group-features")?;
         let mutually_exclusive_features =
             parse_grouped_features(&mutually_exclusive_features, "mutually-exclusive-features")?;
@@ -459,18 +923,25 @@ impl Args {
             }
         }
 
-        if let Some(pos) = cargo_args.iter().position(|a| match &**a {
-            "--example" | "--examples" | "--test" | "--tests" | "--bench" | "--benches"
-            | "--all-targets" => true,
-            _ => {
-                a.starts_with("--example=") || a.starts_with("--test=") || a.starts_with("--bench=")
-            }
-        }) {
+        let mut dev_targets = vec![];
+        for (pos, arg) in cargo_args.iter().enumerate() {
+            let (flag, name) = match arg.split_once('=') {
+                Some((flag, name)) => (flag, Some(name.to_owned())),
+                None => (&**arg, None),
+            };
+            let name = match flag {
+                "--examples" | "--tests" | "--benches" | "--all-targets" => None,
+                "--example" | "--test" | "--bench" => {
+                    name.or_else(|| cargo_args.get(pos + 1).cloned())
+                }
+                _ => continue,
+            };
             if remove_dev_deps {
-                conflicts("--remove-dev-deps", &cargo_args[pos])?;
-            } else if no_dev_deps {
-                conflicts("--no-dev-deps", &cargo_args[pos])?;
+                conflicts("--remove-dev-deps", arg)?;
             }
+            // Whether the targets actually require dev-dependencies is checked after
+            // loading the metadata.
+            dev_targets.push((flag.to_owned(), name));
         }
 
         if !include_features.is_empty() {
@@ -484,6 +955,13 @@ impl Args {
         if no_dev_deps && remove_dev_deps {
             conflicts("--no-dev-deps", "--remove-dev-deps")?;
         }
+        if !remove_dev_dep.is_empty() {
+            if no_dev_deps {
+                conflicts("--remove-dev-dep", "--no-dev-deps")?;
+            } else if remove_dev_deps {
+                conflicts("--remove-dev-dep", "--remove-dev-deps")?;
+            }
+        }
         if each_feature && feature_powerset {
             conflicts("--each-feature", "--feature-powerset")?;
         }
@@ -501,12 +979,44 @@ impl Args {
                 conflicts("--no-default-features", "--feature-powerset")?;
             }
         }
+        if only_all_features {
+            if each_feature {
+                conflicts("--only-all-features", "--each-feature")?;
+            } else if feature_powerset {
+                conflicts("--only-all-features", "--feature-powerset")?;
+            } else if default_only {
+                conflicts("--only-all-features", "--default-only")?;
+            } else if !features.is_empty() {
+                conflicts("--only-all-features", "--features")?;
+            } else if all_features {
+                conflicts("--only-all-features", "--all-features")?;
+            } else if no_default_features {
+                conflicts("--only-all-features", "--no-default-features")?;
+            } else if bisect_features {
+                conflicts("bisect-features", "--only-all-features")?;
+            } else if run_plan.is_some() {
+                conflicts("--only-all-features", "--run-plan")?;
+            }
+        }
+        if default_only {
+            if each_feature {
+                conflicts("--default-only", "--each-feature")?;
+            } else if feature_powerset {
+                conflicts("--default-only", "--feature-powerset")?;
+            } else if !features.is_empty() {
+                conflicts("--default-only", "--features")?;
+            } else if all_features {
+                conflicts("--default-only", "--all-features")?;
+            } else if no_default_features {
+                conflicts("--default-only", "--no-default-features")?;
+            }
+        }
 
         for f in &exclude_features {
             if features.contains(f) {
                 bail!("feature `{f}` specified by both --exclude-features and --features");
             }
-            if optional_deps.as_ref().map_or(false, |d| d.contains(f)) {
+            if optional_deps.as_ref().is_some_and(|d| d.contains(f)) {
                 bail!("feature `{f}` specified by both --exclude-features and --optional-deps");
             }
             if group_features.iter().any(|v| v.matches(f)) {
@@ -515,35 +1025,97 @@ impl Args {
             if mutually_exclusive_features.iter().any(|v| v.matches(f)) {
                 bail!("feature `{f}` specified by both --exclude-features and --mutually-exclusive-features");
             }
-            if include_features.contains(f) {
-                bail!("feature `{f}` specified by both --exclude-features and --include-features");
-            }
         }
 
         if subcommand.is_none() {
             if cargo_args.iter().any(|a| a == "--list") {
                 cmd!(cargo, "--list").run()?;
                 std::process::exit(0);
-            } else if !remove_dev_deps {
-                // TODO: improve this
-                mini_usage("no subcommand or valid flag specified")?;
+            } else if bisect_features {
+                mini_usage(
+                    "bisect-features requires a subcommand, e.g., `cargo hack bisect-features check`",
+                )?;
+            } else if !remove_dev_deps
+                && !print_features
+                && !print_package_list
+                && !check_feature_unification
+                && !gh_matrix
+                && exec.is_none()
+            {
+                if hack_flags.is_empty() {
+                    mini_usage("no subcommand or valid flag specified")?;
+                }
+                let toolchain = toolchain.as_ref().map(|t| format!("+{t} ")).unwrap_or_default();
+                let args: Vec<&str> =
+                    args.iter().map(String::as_str).filter(|a| !a.starts_with('+')).collect();
+                mini_usage(&format!(
+                    "no subcommand specified, but the following flags require a subcommand: {}\n\n\
+                     help: specify a subcommand such as `check`, e.g., `cargo hack {toolchain}check {}`",
+                    hack_flags.join(", "),
+                    args.join(" "),
+                ))?;
             }
         }
 
+        if bisect_features {
+            if each_feature {
+                conflicts("bisect-features", "--each-feature")?;
+            } else if feature_powerset {
+                conflicts("bisect-features", "--feature-powerset")?;
+            } else if exec.is_some() {
+                conflicts("bisect-features", "--exec")?;
+            } else if version_range.is_some() {
+                conflicts("bisect-features", "--version-range")?;
+            } else if rust_version {
+                conflicts("bisect-features", "--rust-version")?;
+            } else if print_command_list {
+                conflicts("bisect-features", "--print-command-list")?;
+            }
+        }
+        if let Some(toolchain) = &toolchain {
+            if version_range.is_some() {
+                conflicts(&format!("+{toolchain}"), "--version-range")?;
+            } else if rust_version {
+                conflicts(&format!("+{toolchain}"), "--rust-version")?;
+            }
+        }
+        if let Some(exec) = &exec {
+            if exec.trim().is_empty() {
+                bail!("--exec requires a non-empty command");
+            }
+            if let Some(subcommand) = &subcommand {
+                conflicts("--exec", &format!("{subcommand} subcommand"))?;
+            } else if version_range.is_some() {
+                conflicts("--exec", "--version-range")?;
+            } else if rust_version {
+                conflicts("--exec", "--rust-version")?;
+            }
+            if !cargo_arg.is_empty() {
+                conflicts("--exec", "--cargo-arg")?;
+            } else if !also.is_empty() {
+                conflicts("--exec", "--also")?;
+            } else if command_prefix.is_some() {
+                conflicts("--exec", "--command-prefix")?;
+            }
+        }
+        if command_prefix.as_ref().is_some_and(|p| p.trim().is_empty()) {
+            bail!("--command-prefix requires a non-empty command");
+        }
+
         let version_range = match (version_range, rust_version) {
             (Some(_), true) => {
                 conflicts("--version-range", "--rust-version")?;
                 unreachable!()
             }
             (Some(version_range), false) => {
-                let rustup = Rustup::new();
+                let rustup = Rustup::new("--version-range")?;
                 if rustup.version < 23 {
                     bail!("--version-range requires rustup 1.23 or later");
                 }
                 Some(version_range.parse()?)
             }
             (None, true) => {
-                let rustup = Rustup::new();
+                let rustup = Rustup::new("--rust-version")?;
                 if rustup.version < 23 {
                     bail!("--rust-version requires rustup 1.23 or later");
                 }
@@ -558,76 +1130,377 @@ impl Args {
             if clean_per_version {
                 requires("--clean-per-version", &["--version-range"])?;
             }
+            if print_toolchains {
+                requires("--print-toolchains", &["--version-range", "--rust-version"])?;
+            }
+            if install_toolchains {
+                requires("--install-toolchains", &["--version-range", "--rust-version"])?;
+            }
+        }
+        if keep_target_dirs && !target_dir_per_combination {
+            requires("--keep-target-dirs", &["--target-dir-per-combination"])?;
+        }
+        if force && !no_dev_deps {
+            requires("--force", &["--no-dev-deps"])?;
+        }
+        if keep_backups && backup_manifests.is_none() {
+            requires("--keep-backups", &["--backup-manifests"])?;
+        }
+        if include_dependents && changed_since.is_none() {
+            requires("--include-dependents", &["--changed-since"])?;
         }
 
+        let manifest_path = manifest_path.map(check_manifest_path).transpose()?;
         let version_step = version_step.as_deref().map(str::parse::<u16>).transpose()?.unwrap_or(1);
         if version_step == 0 {
             bail!("--version-step cannot be zero");
         }
 
+        let retry = retry.as_deref().map(str::parse::<usize>).transpose()?.unwrap_or(0);
+        let timeout = timeout
+            .map(|v| match v.parse::<u64>() {
+                Ok(n) if n > 0 => Ok(Duration::from_secs(n)),
+                _ => bail!("argument for --timeout must be a positive integer, but found `{v}`"),
+            })
+            .transpose()?;
+        let package_jobs = match package_jobs {
+            Some(v) => match v.parse::<usize>() {
+                Ok(n) if n > 0 => n,
+                _ => {
+                    bail!("argument for --package-jobs must be a positive integer, but found `{v}`")
+                }
+            },
+            None => 1,
+        };
+        let max_combinations_per_package = max_combinations_per_package
+            .map(|v| match v.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(n),
+                _ => bail!(
+                    "argument for --max-combinations-per-package must be a positive integer, but \
+                     found `{v}`"
+                ),
+            })
+            .transpose()?;
+        let combination_warn_limit =
+            combination_warn_limit.as_deref().map(str::parse::<usize>).transpose()?.unwrap_or(1000);
+        let continue_from = continue_from.as_deref().map(str::parse::<usize>).transpose()?;
+        if continue_from == Some(0) {
+            bail!("--continue-from cannot be zero");
+        }
+        if let Some(status_format) = &status_format {
+            if status_format != "jsonl" {
+                bail!("argument for --status-format must be jsonl, but found `{status_format}`");
+            }
+            if status_file.is_none() {
+                requires("--status-format", &["--status-file"])?;
+            }
+        }
+        let summary_format = match summary_format {
+            Some(v) => Some(v.parse()?),
+            None => summary_file.is_some().then_some(summary::Format::Text),
+        };
+        if gh_summary && no_gh_summary {
+            conflicts("--gh-summary", "--no-gh-summary")?;
+        }
+        let step_summary =
+            env::var_os("GITHUB_STEP_SUMMARY").filter(|v| !v.is_empty()).map(PathBuf::from);
+        // Written automatically on GitHub Actions (as with --log-group), but not
+        // locally unless --gh-summary is used.
+        let gh_summary = if gh_summary {
+            if step_summary.is_none() {
+                bail!(
+                    "--gh-summary requires the GITHUB_STEP_SUMMARY environment variable to be set"
+                );
+            }
+            step_summary
+        } else if no_gh_summary || env::var_os("GITHUB_ACTIONS").is_none() {
+            None
+        } else {
+            step_summary
+        };
+        if count && print_command_list {
+            conflicts("--count", "--print-command-list")?;
+        }
+        if check_plan {
+            if count {
+                conflicts("--check-plan", "--count")?;
+            } else if print_command_list {
+                conflicts("--check-plan", "--print-command-list")?;
+            } else if gh_matrix {
+                conflicts("--check-plan", "--gh-matrix")?;
+            } else if run_plan.is_some() {
+                conflicts("--check-plan", "--run-plan")?;
+            }
+        }
+        if run_plan.is_some() {
+            if each_feature {
+                conflicts("--run-plan", "--each-feature")?;
+            } else if feature_powerset {
+                conflicts("--run-plan", "--feature-powerset")?;
+            } else if version_range.is_some() {
+                conflicts("--run-plan", "--version-range")?;
+            } else if rust_version {
+                conflicts("--run-plan", "--rust-version")?;
+            } else if resume {
+                conflicts("--run-plan", "--resume")?;
+            } else if bisect_features {
+                conflicts("bisect-features", "--run-plan")?;
+            } else if dump_features.is_some() {
+                conflicts("--run-plan", "--dump-features")?;
+            }
+        }
+        if dump_features.is_some() {
+            if count {
+                conflicts("--dump-features", "--count")?;
+            } else if print_command_list {
+                conflicts("--dump-features", "--print-command-list")?;
+            } else if bisect_features {
+                conflicts("bisect-features", "--dump-features")?;
+            }
+        }
+        if gh_matrix {
+            if count {
+                conflicts("--gh-matrix", "--count")?;
+            } else if print_command_list {
+                conflicts("--gh-matrix", "--print-command-list")?;
+            } else if dump_features.is_some() {
+                conflicts("--gh-matrix", "--dump-features")?;
+            } else if run_plan.is_some() {
+                conflicts("--gh-matrix", "--run-plan")?;
+            } else if bisect_features {
+                conflicts("bisect-features", "--gh-matrix")?;
+            } else if version_range.is_some() {
+                conflicts("--gh-matrix", "--version-range")?;
+            } else if rust_version {
+                conflicts("--gh-matrix", "--rust-version")?;
+            } else if !also.is_empty() {
+                conflicts("--gh-matrix", "--also")?;
+            } else if resume {
+                conflicts("--gh-matrix", "--resume")?;
+            }
+        }
+        if stop_on_first_success {
+            if keep_going {
+                conflicts("--stop-on-first-success", "--keep-going")?;
+            } else if no_fail_fast {
+                conflicts("--stop-on-first-success", "--no-fail-fast")?;
+            } else if print_command_list {
+                conflicts("--stop-on-first-success", "--print-command-list")?;
+            }
+        }
+        if package_jobs > 1 {
+            if resume {
+                conflicts("--package-jobs", "--resume")?;
+            } else if status_file.is_some() {
+                conflicts("--package-jobs", "--status-file")?;
+            } else if stop_on_first_success {
+                conflicts("--package-jobs", "--stop-on-first-success")?;
+            } else if clean_per_run {
+                conflicts("--package-jobs", "--clean-per-run")?;
+            } else if log_group.is_some() {
+                conflicts("--package-jobs", "--log-group")?;
+            }
+        }
+        if resume {
+            if continue_from.is_some() {
+                conflicts("--resume", "--continue-from")?;
+            } else if print_command_list {
+                conflicts("--resume", "--print-command-list")?;
+            }
+        }
+
         let log_group = match log_group {
             Some(v) => v.parse()?,
-            None if disable_log_grouping => LogGroup::None,
+            // The output of the commands run in parallel cannot be grouped.
+            None if disable_log_grouping || package_jobs > 1 => LogGroup::None,
             None => LogGroup::auto(),
         };
 
-        if no_dev_deps || no_private {
-            let flag = if no_dev_deps && no_private {
-                "--no-dev-deps and --no-private modify"
-            } else if no_dev_deps {
-                "--no-dev-deps modifies"
-            } else {
-                "--no-private modifies"
+        let progress_bar = !no_progress
+            && package_jobs == 1
+            && !print_command_list
+            && !gh_matrix
+            && log_group == LogGroup::None
+            && term::progress_bar_supported();
+
+        let dev_deps_flag = if no_dev_deps {
+            Some("--no-dev-deps")
+        } else if !remove_dev_dep.is_empty() {
+            Some("--remove-dev-dep")
+        } else {
+            None
+        };
+        if dev_deps_flag.is_some() || no_private {
+            let flag = match dev_deps_flag {
+                Some(dev_deps_flag) if no_private => {
+                    format!("{dev_deps_flag} and --no-private modify")
+                }
+                Some(dev_deps_flag) => format!("{dev_deps_flag} modifies"),
+                None => "--no-private modifies".to_owned(),
             };
             info!(
                 "{flag} real `Cargo.toml` while cargo-hack is running and restores it when finished"
             );
         }
 
+        // -Z flags are propagated to every cargo command, regardless of whether they
+        // were specified before or after the subcommand.
+        for flag in unstable_flags {
+            cargo_args.push("-Z".to_owned());
+            cargo_args.push(flag);
+        }
+
         // https://github.com/taiki-e/cargo-hack/issues/42
         // https://github.com/rust-lang/cargo/pull/8799
         let namespaced_features = has_z_flag(&cargo_args, "namespaced-features");
         exclude_no_default_features |= !include_features.is_empty();
         exclude_all_features |= !include_features.is_empty()
             || !exclude_features.is_empty()
-            || (feature_powerset && !namespaced_features && depth.is_none());
+            || (feature_powerset
+                && !namespaced_features
+                && depth.map_or(true, |d| d == Depth::Max));
         exclude_features.extend_from_slice(&features);
 
+        if no_fail_fast {
+            // --no-fail-fast is an alias for --keep-going, and is also propagated to cargo
+            // for subcommands that accept it so that a single test failure doesn't abort
+            // the rest of the tests in that run.
+            keep_going = true;
+            if matches!(subcommand.as_deref(), Some("test" | "bench")) {
+                cargo_args.push("--no-fail-fast".to_owned());
+            }
+        }
+
         term::verbose::set(verbose != 0);
         // If `-vv` is passed, propagate `-v` to cargo.
         if verbose > 1 {
             cargo_args.push(format!("-{}", "v".repeat(verbose - 1)));
         }
+        // Arguments passed via --cargo-arg are propagated to cargo as is (before `--`).
+        if each_feature || feature_powerset {
+            if let Some(arg) = cargo_arg
+                .iter()
+                .find(|a| *a == "--features" || a.starts_with("--features=") || a.starts_with("-F"))
+            {
+                bail!(
+                    "`{arg}` passed via --cargo-arg conflicts with the features managed by {}; \
+                     use cargo-hack's --features or --include-features instead",
+                    if each_feature { "--each-feature" } else { "--feature-powerset" }
+                );
+            }
+        }
+        cargo_args.extend(cargo_arg);
+
+        if !also.is_empty() && subcommand.is_none() {
+            requires("--also", &["a subcommand"])?;
+        }
+        if clippy_args.is_some()
+            && subcommand.as_deref() != Some("clippy")
+            && !also.iter().any(|s| s == "clippy")
+        {
+            requires("--clippy-args", &["clippy subcommand", "--also clippy"])?;
+        }
+        let also = also
+            .into_iter()
+            .map(|also| {
+                let mut args = cargo_args.clone();
+                args[subcommand_index].clone_from(&also);
+                (also, args)
+            })
+            .collect();
+        let mut check_plan_args = vec![];
+        if check_plan {
+            check_plan_args.clone_from(&cargo_args);
+            if subcommand.is_some() {
+                "check".clone_into(&mut check_plan_args[subcommand_index]);
+            } else {
+                check_plan_args.insert(0, "check".to_owned());
+            }
+        }
 
         Ok(Self {
             leading_args: cargo_args,
             trailing_args: rest,
 
             subcommand,
+            toolchain,
+            bisect_features,
 
             manifest_path,
             locked,
             package,
             exclude,
+            exclude_current,
+            frozen_features,
             workspace,
             each_feature,
             feature_powerset,
+            default_only,
+            only_all_features,
             no_dev_deps,
+            dev_targets,
+            force,
             remove_dev_deps,
+            remove_dev_dep,
+            backup_manifests: backup_manifests.map(PathBuf::from),
+            keep_backups,
             no_private,
             ignore_private: ignore_private | no_private,
+            ignore_private_restricted,
+            only_packages_with_feature,
+            changed_since,
+            include_dependents,
             ignore_unknown_features,
+            no_skip_targetless,
+            print_features,
+            check_feature_unification,
+            print_package_list,
+            gh_matrix,
+            exec,
+            command_prefix: command_prefix
+                .map(|p| p.split_whitespace().map(str::to_owned).collect())
+                .unwrap_or_default(),
+            env,
+            deny_warnings,
+            clippy_args: clippy_args
+                .map(|a| a.split_whitespace().map(str::to_owned).collect())
+                .unwrap_or_default(),
+            also,
+            retry,
+            timeout,
+            package_jobs,
+            continue_from,
+            resume,
+            status_file: status_file.map(PathBuf::from),
+            dump_features: dump_features.map(PathBuf::from),
+            run_plan: run_plan.map(PathBuf::from),
+            progress_bar,
             optional_deps,
             clean_per_run,
             clean_per_version,
+            target_dir_per_combination,
+            keep_target_dirs,
             keep_going,
+            replay_failures,
+            log_dir: log_dir.map(PathBuf::from),
+            summary_format,
+            summary_file: summary_file.map(PathBuf::from),
+            gh_summary,
+            stop_on_first_success,
+            allow_failure,
             print_command_list,
+            count,
+            check_plan,
+            check_plan_args,
+            combination_warn_limit,
             no_manifest_path,
             include_features: include_features.into_iter().map(Into::into).collect(),
             at_least_one_of,
             include_deps_features,
             version_range,
             version_step,
+            print_toolchains,
+            install_toolchains,
             log_group,
 
             depth,
@@ -637,6 +1510,14 @@ impl Args {
             exclude_features,
             exclude_no_default_features,
             exclude_all_features,
+            order,
+            always_include_default_run,
+            exclude_default_member_features,
+            exclude_features_matching_cfg,
+            include_features_from_default,
+            max_combinations_per_package,
+            explain_plan,
+            matrix_file,
 
             features,
 
@@ -646,23 +1527,38 @@ impl Args {
     }
 }
 
+/// Replaces the references to feature aliases (`@<name>`) defined by
+/// --feature-alias with the features of the alias.
+fn expand_feature_aliases(
+    aliases: &BTreeMap<String, Vec<String>>,
+    features: &[String],
+) -> Result<Vec<String>> {
+    let mut expanded = Vec::with_capacity(features.len());
+    for f in features {
+        match f.strip_prefix('@') {
+            Some(name) => match aliases.get(name) {
+                Some(list) => expanded.extend(list.iter().cloned()),
+                None => bail!("feature alias `@{name}` is not defined by --feature-alias"),
+            },
+            None => expanded.push(f.clone()),
+        }
+    }
+    Ok(expanded)
+}
+
 fn parse_grouped_features(
     group_features: &[String],
     option_name: &str,
 ) -> Result<Vec<Feature>, anyhow::Error> {
     let group_features =
         group_features.iter().try_fold(Vec::with_capacity(group_features.len()), |mut v, g| {
-            let g = if g.contains(',') {
-                g.split(',')
-            } else if g.contains(' ') {
-                g.split(' ')
-            } else {
+            if !g.contains(|c: char| c == ',' || c.is_whitespace()) {
                 bail!(
                     "--{option_name} requires a list of two or more features separated by space \
                          or comma"
                 );
-            };
-            v.push(Feature::group(g));
+            }
+            v.push(Feature::group(split_features(g)));
             Ok(v)
         })?;
     Ok(group_features)
@@ -691,15 +1587,51 @@ fn has_z_flag(args: &[String], name: &str) -> bool {
 type HelpText<'a> = (&'a str, &'a str, &'a str, &'a str, &'a [&'a str]);
 
 const HELP: &[HelpText<'_>] = &[
-    ("-p", "--package", "<SPEC>...", "Package(s) to check", &[]),
+    ("-p", "--package", "<SPEC>...", "Package(s) to check", &[
+        "SPEC can be a glob pattern, where `*` matches any sequence of characters and `?` matches \
+         any single character (e.g., `--package 'api-*'`).",
+        "SPEC can also be of the form `<name>@<version>` (e.g., `--package foo@1.2.3`), where the \
+         version may be partial (e.g., `foo@1.2`).",
+        "If used together with --workspace, only the workspace members that match the specified \
+         package(s) are checked.",
+    ]),
     ("", "--all", "", "Alias for --workspace", &[]),
     ("", "--workspace", "", "Perform command for all packages in the workspace", &[]),
     ("", "--exclude", "<SPEC>...", "Exclude packages from the check", &[
+        "SPEC can be a glob pattern, where `*` matches any sequence of characters and `?` matches \
+         any single character (e.g., `--exclude 'internal-*'`).",
+        "SPEC can also be of the form `<name>@<version>` (e.g., `--exclude foo@1.2.3`).",
+        "This flag can only be used together with --workspace",
+    ]),
+    ("", "--exclude-current", "", "Exclude the current package from the check", &[
+        "The current package is the package whose manifest is found in the current directory \
+         (or specified by --manifest-path).",
         "This flag can only be used together with --workspace",
     ]),
-    ("", "--manifest-path", "<PATH>", "Path to Cargo.toml", &[]),
+    ("", "--manifest-path", "<PATH>", "Path to Cargo.toml", &[
+        "If a directory is given, `Cargo.toml` in the directory is used.",
+    ]),
     ("", "--locked", "", "Require Cargo.lock is up to date", &[]),
-    ("-F", "--features", "<FEATURES>...", "Space or comma separated list of features to activate", &[]),
+    ("-F", "--features", "<FEATURES>...", "Space or comma separated list of features to activate", &[
+        "When this flag is used together with --each-feature or --feature-powerset, the features \
+         are a base set that is enabled in every feature combination, including the runs with \
+         just --no-default-features and --all-features. The features of the base set are not \
+         used as feature combinations on their own.",
+        "If the value is `@env:<VAR>`, the list is read from the environment variable VAR. This \
+         also applies to other flags that take a list of features.",
+    ]),
+    ("", "--default-only", "", "Perform once per package with only the default features", &[
+        "This is the default behavior when neither --each-feature nor --feature-powerset is \
+         used; this flag makes the intent explicit and rejects flags that change the set of \
+         features.",
+        "This flag cannot be used together with --each-feature, --feature-powerset, --features, \
+         --all-features, or --no-default-features.",
+    ]),
+    ("", "--only-all-features", "", "Perform once per package with only --all-features flag", &[
+        "This is a quick check that each package builds with all features enabled.",
+        "This flag cannot be used together with --each-feature, --feature-powerset, \
+         --default-only, --features, --all-features, or --no-default-features.",
+    ]),
     ("", "--each-feature", "", "Perform for each feature of the package", &[
         "This also includes runs with just --no-default-features flag, and default features.",
         "When this flag is not used together with --exclude-features (--skip) and \
@@ -721,10 +1653,17 @@ const HELP: &[HelpText<'_>] = &[
     ]),
     ("", "--skip", "<FEATURES>...", "Alias for --exclude-features", &[]),
     ("", "--exclude-features", "<FEATURES>...", "Space or comma separated list of features to exclude", &[
-        "To exclude run of default feature, using value `--exclude-features default`.",
+        "To exclude run of default feature, using value `--exclude-features default`. This also \
+         excludes the run with default features performed by --order or \
+         --always-include-default-run, even if the package has no `default` feature.",
         "To exclude run of just --no-default-features flag, using --exclude-no-default-features \
          flag.",
         "To exclude run of just --all-features flag, using --exclude-all-features flag.",
+        "When --include-deps-features is used, features of dependencies that enable an excluded \
+         feature are also excluded.",
+        "Features listed in `exclude-features` in the `[package.metadata.cargo-hack]` table of \
+         each package and the `[workspace.metadata.cargo-hack]` table of the workspace root are \
+         excluded in addition to the features specified by this flag.",
         "This flag can only be used together with either --each-feature flag or --feature-powerset \
          flag.",
     ]),
@@ -736,6 +1675,80 @@ const HELP: &[HelpText<'_>] = &[
         "This flag can only be used together with either --each-feature flag or --feature-powerset \
          flag.",
     ]),
+    ("", "--all-features-first", "", "Perform run of just --all-features flag first", &[
+        "By default, the run with just --all-features flag is performed last for each package. \
+         With this flag, it is performed before the other feature combinations, which is useful \
+         to fail fast on the configuration most likely to break.",
+        "This flag can only be used together with either --each-feature flag or --feature-powerset \
+         flag.",
+    ]),
+    ("", "--only-powerset", "", "Perform only runs with the generated feature combinations", &[
+        "The runs with just --no-default-features flag and just --all-features flag are \
+         omitted. This is equivalent to `--order each`.",
+        "This flag can only be used together with either --each-feature flag or --feature-powerset \
+         flag.",
+    ]),
+    ("", "--order", "<PHASES>", "Order of the runs performed on each package", &[
+        "PHASES is a comma-separated list of the following phases: `default` (run with default \
+         features), `none` (run with just --no-default-features flag), `each` (runs with the \
+         feature combinations of --each-feature or --feature-powerset), and `all` (run with just \
+         --all-features flag). Each phase can be specified at most once, and phases that are not \
+         specified are not run.",
+        "Defaults to `none,each,all`.",
+        "This flag can only be used together with either --each-feature flag or --feature-powerset \
+         flag.",
+    ]),
+    (
+        "",
+        "--always-include-default-run",
+        "",
+        "Perform run with default features even if the package has no `default` feature",
+        &[
+            "If the package has no `default` feature, no feature combination corresponds to the \
+             run with default features, so the run without any feature flags is performed first \
+             in addition to the other runs.",
+            "This flag can only be used together with either --each-feature flag or \
+             --feature-powerset flag.",
+        ],
+    ),
+    (
+        "",
+        "--exclude-default-member-features",
+        "",
+        "Exclude features that are enabled by the `default` feature",
+        &[
+            "Features that are directly or transitively enabled by the `default` feature are \
+             already covered by the run with default features.",
+            "This flag can only be used together with either --each-feature flag or \
+             --feature-powerset flag.",
+        ],
+    ),
+    (
+        "",
+        "--exclude-features-matching-cfg",
+        "",
+        "Exclude features whose `feature-cfg` does not match the target",
+        &[
+            "The cfg of each feature is specified by `feature-cfg` in \
+             `[package.metadata.cargo-hack]` table, e.g., `feature-cfg = { foo = \"cfg(unix)\" }`, \
+             and is evaluated against the host or the targets specified by --target.",
+            "This flag can only be used together with either --each-feature flag or \
+             --feature-powerset flag.",
+        ],
+    ),
+    (
+        "",
+        "--include-features-from-default",
+        "",
+        "Always include a run with just the `default` feature",
+        &[
+            "This runs `--no-default-features --features default` for packages that have the \
+             `default` feature, even if it is not otherwise one of the combinations (e.g., due to \
+             --exclude-features, --include-features, or --depth).",
+            "This flag can only be used together with either --each-feature flag or \
+             --feature-powerset flag.",
+        ],
+    ),
     (
         "",
         "--depth",
@@ -743,9 +1756,44 @@ const HELP: &[HelpText<'_>] = &[
         "Specify a max number of simultaneous feature flags of --feature-powerset",
         &[
             "If NUM is set to 1, --feature-powerset is equivalent to --each-feature.",
+            "NUM can also be a percentage of the number of features of each package (e.g., \
+             `50%`, rounded up), or `max` for no limit.",
             "This flag can only be used together with --feature-powerset flag.",
         ],
     ),
+    (
+        "",
+        "--max-combinations-per-package",
+        "<N>",
+        "Limit the number of feature combinations of each package to N",
+        &[
+            "If a package has more feature combinations than N (after applying --depth and other \
+             flags), N combinations evenly spread over them are used. Runs with just \
+             --no-default-features flag and just --all-features flag are not counted.",
+            "This flag can only be used together with either --each-feature flag or \
+             --feature-powerset flag.",
+        ],
+    ),
+    (
+        "",
+        "--matrix-file",
+        "<PATH>",
+        "Path to a TOML file that specifies the feature combinations of each package",
+        &[
+            "The file maps package names to lists of feature combinations, e.g., \
+             `foo = [[\"a\"], [\"a\", \"b\"]]`. The specified combinations are used instead of \
+             the ones generated by --each-feature or --feature-powerset for those packages. Other \
+             packages are not affected.",
+            "This flag can only be used together with either --each-feature flag or \
+             --feature-powerset flag.",
+        ],
+    ),
+    ("", "--explain-plan", "", "Log why each feature was kept or dropped from the combinations", &[
+        "For each package, this logs each candidate feature and whether it was kept or dropped, \
+         and by which flag or configuration.",
+        "This flag can only be used together with either --each-feature flag or \
+         --feature-powerset flag.",
+    ]),
     ("", "--group-features", "<FEATURES>...", "Space or comma separated list of features to group", &[
         "This treats the specified features as if it were a single feature.",
         "To specify multiple groups, use this option multiple times: `--group-features a,b \
@@ -769,14 +1817,46 @@ const HELP: &[HelpText<'_>] = &[
         "Include only the specified features in the feature combinations instead of package \
          features",
         &[
+            "Features also specified by --exclude-features are excluded.",
+            "As only the specified features are combined, this flag implies \
+             --exclude-no-default-features and --exclude-all-features.",
             "This flag can only be used together with either --each-feature flag or \
              --feature-powerset flag.",
         ],
     ),
+    ("", "--feature-alias", "<NAME=FEATURES>...", "Define a named list of features", &[
+        "The alias can be referenced as `@<NAME>` in the lists of features of \
+         --include-features, --exclude-features (--skip), and --group-features, e.g., \
+         `--feature-alias io=fs,net --exclude-features @io`.",
+        "This flag can be specified multiple times.",
+    ]),
+    ("", "--frozen-features", "", "Require optional dependencies to be already activated", &[
+        "Before running commands, checks that no feature combination activates an optional \
+         dependency that is not activated when the workspace is built with the default \
+         features (or without them if --no-default-features is used), and exits with an error \
+         if any does.",
+        "The optional dependencies activated by --features are allowed.",
+    ]),
     ("", "--no-dev-deps", "", "Perform without dev-dependencies", &[
         "Note that this flag removes dev-dependencies from real `Cargo.toml` while cargo-hack is \
          running and restores it when finished.",
+        "When this flag is used together with flags that select examples, tests, or benchmarks \
+         (e.g., --examples), this fails only if a selected package that has such targets has \
+         dev-dependencies.",
     ]),
+    (
+        "",
+        "--force",
+        "",
+        "Run even if the targets selected together with --no-dev-deps may require dev-dependencies",
+        &[
+            "The error about flags that select examples, tests, or benchmarks (e.g., --tests) \
+             being used on a package with dev-dependencies is reported as a warning instead. This \
+             is useful if those targets do not use the dev-dependencies, but otherwise the \
+             targets fail to compile.",
+            "This flag can only be used together with --no-dev-deps flag.",
+        ],
+    ),
     (
         "",
         "--remove-dev-deps",
@@ -785,14 +1865,91 @@ const HELP: &[HelpText<'_>] = &[
          after performed",
         &[],
     ),
+    ("", "--remove-dev-dep", "<NAME>...", "Perform without the specified dev-dependencies", &[
+        "Unlike --no-dev-deps, this removes only the dev-dependencies with the specified names \
+         (including target-specific ones), and keeps the other dev-dependencies.",
+        "This flag can be specified multiple times.",
+        "Note that this flag removes dev-dependencies from real `Cargo.toml` while cargo-hack is \
+         running and restores it when finished.",
+    ]),
+    (
+        "",
+        "--backup-manifests",
+        "<DIR>",
+        "Write the original `Cargo.toml` to <DIR> before modifying it",
+        &[
+            "The original content of each manifest is written to `<DIR>/<path>.bak`, where \
+             <path> is the path of the manifest relative to the workspace root (e.g., \
+             `<DIR>/member/Cargo.toml.bak`), before it is modified by --no-dev-deps, \
+             --remove-dev-dep, --remove-dev-deps, or --no-private.",
+            "The backups are removed when the manifests are restored unless --keep-backups is \
+             also used.",
+        ],
+    ),
+    ("", "--keep-backups", "", "Keep the backups written by --backup-manifests", &[]),
     ("", "--no-private", "", "Perform without `publish = false` crates", &[]),
-    ("", "--ignore-private", "", "Skip to perform on `publish = false` packages", &[]),
+    ("", "--ignore-private", "[=restricted]", "Skip to perform on `publish = false` packages", &[
+        "If `--ignore-private=restricted` is used, packages that can only be published to \
+         specific registries (e.g., `publish = [\"my-registry\"]`) are also skipped.",
+    ]),
+    (
+        "",
+        "--only-packages-with-feature",
+        "<FEATURE>",
+        "Perform only on packages that have the given feature",
+        &[
+            "Packages that do not have a feature (or an optional dependency) with the given name \
+             are skipped. This can be used together with --workspace to run only on the \
+             relevant members of a large workspace.",
+        ],
+    ),
+    (
+        "",
+        "--changed-since",
+        "<REF>",
+        "Perform only on packages changed since the given git ref",
+        &[
+            "The files changed since the ref (including uncommitted changes) are listed by `git \
+             diff`, and each file is mapped to the workspace member that contains it. Changes to \
+             Cargo.toml or Cargo.lock in the workspace root that do not belong to a package affect \
+             all members.",
+            "This can be used in CI to run only on the packages affected by a pull request, e.g., \
+             `--changed-since origin/main`.",
+        ],
+    ),
+    (
+        "",
+        "--include-dependents",
+        "",
+        "Also perform on workspace members that depend on the changed packages",
+        &[
+            "Dependents are selected transitively; e.g., if `a` is changed, `b` depends on `a`, and \
+             `c` depends on `b`, both `b` and `c` are also selected. Both normal and dev \
+             dependencies are considered.",
+            "This flag can only be used together with --changed-since.",
+        ],
+    ),
+    (
+        "",
+        "--no-skip-targetless",
+        "",
+        "Do not skip packages that have no library or binary targets",
+        &[
+            "By default, such packages are skipped when the subcommand is check, build, clippy, or \
+             doc and no target selection flags (e.g., --all-targets, --examples) are specified.",
+        ],
+    ),
     (
         "",
         "--ignore-unknown-features",
         "",
         "Skip passing --features flag to `cargo` if that feature does not exist in the package",
-        &["This flag can be used with --features, --include-features, or --group-features."],
+        &[
+            "This flag can be used with --features, --include-features, or --group-features.",
+            "With --verbose, the features dropped from each package are listed. If no feature \
+             specified by --features is found in any of the selected packages, a warning is \
+             emitted.",
+        ],
     ),
     (
         "",
@@ -823,6 +1980,32 @@ const HELP: &[HelpText<'_>] = &[
         "Specify the version interval of --version-range (default to `1`)",
         &["This flag can only be used together with --version-range flag."],
     ),
+    (
+        "",
+        "--print-toolchains",
+        "",
+        "Print the toolchains that --version-range or --rust-version would use and exit",
+        &[
+            "One toolchain is printed per line, after applying --version-step and skipping the \
+             toolchains older than the rust-version of each package. If not all packages are run \
+             on every toolchain, each line also lists the packages run on that toolchain, e.g., \
+             `1.65: foo, bar`.",
+            "No toolchain is installed and no command is run when this flag is used.",
+        ],
+    ),
+    (
+        "",
+        "--install-toolchains",
+        "",
+        "Install the missing toolchains of --version-range or --rust-version before running",
+        &[
+            "All toolchains that would be used are checked before running any command, and each \
+             one that is not installed is installed by `rustup toolchain install`.",
+            "Without this flag, a missing toolchain is installed right before the commands that \
+             use it, so a failure to install it is reported only after the commands on the \
+             previous toolchains have run.",
+        ],
+    ),
     ("", "--clean-per-run", "", "Remove artifacts for that package before running the command", &[
         "If used this flag with --workspace, --each-feature, or --feature-powerset, artifacts will \
          be removed before each run.",
@@ -832,11 +2015,326 @@ const HELP: &[HelpText<'_>] = &[
         "Note that dependencies artifacts will also be removed.",
         "This flag can only be used together with --version-range flag.",
     ]),
+    (
+        "",
+        "--target-dir-per-combination",
+        "",
+        "Use a separate target directory for each feature combination",
+        &[
+            "CARGO_TARGET_DIR is set to a subdirectory of `<target-dir>/hack-combinations` that \
+             is unique to the feature combination, so that build artifacts do not leak across \
+             feature combinations. This is a lighter alternative to --clean-per-run.",
+            "Workspace members run with the same feature flags share a directory, so that the \
+             build cache of their common dependencies is reused.",
+            "The directories are removed at the end of the run unless --keep-target-dirs is \
+             used.",
+        ],
+    ),
+    ("", "--keep-target-dirs", "", "Keep the directories created by --target-dir-per-combination", &[
+        "This flag can only be used together with --target-dir-per-combination flag.",
+    ]),
     ("", "--keep-going", "", "Keep going on failure", &[]),
+    ("", "--no-fail-fast", "", "Alias for --keep-going", &[
+        "When this flag is used together with test or bench subcommand, this flag is also \
+         propagated to cargo.",
+    ]),
+    (
+        "",
+        "--replay-failures",
+        "",
+        "Capture the output of each command and print it again only for the failed ones",
+        &[
+            "The output of each command is captured instead of being printed as the command \
+             runs, and the output of the failed commands is printed under a header naming the \
+             command after all commands have run (or when the run stops at the first failure).",
+            "This is useful together with --keep-going, so that the output of the failures does \
+             not need to be found among the output of many successful commands.",
+        ],
+    ),
+    (
+        "",
+        "--log-dir",
+        "<DIR>",
+        "Also write the output of each command to a log file in the given directory",
+        &[
+            "The output is still printed as the command runs, and is also written to \
+             `<DIR>/<package>-<features>.log`, e.g., `foo-no-default-features-a+b.log` for \
+             `--no-default-features --features a,b` on package `foo`. Characters that are not \
+             safe in file names are replaced with `_`.",
+            "Output is appended if the file already exists, e.g., when the same feature \
+             combination is run with multiple subcommands by --also.",
+            "Note that as the output is not written to a terminal, cargo does not color it \
+             unless CARGO_TERM_COLOR is set.",
+        ],
+    ),
+    (
+        "",
+        "--summary-format",
+        "<FORMAT>",
+        "Print a summary of the commands run at the end, in the given format",
+        &[
+            "The summary has one row per command, with the package, the feature flags, whether \
+             the command passed or failed, and how long it took.",
+            "Possible values: text, markdown. The markdown format is a table that can be pasted \
+             into issues and pull requests.",
+            "The summary is printed to stdout unless --summary-file is used.",
+        ],
+    ),
+    (
+        "",
+        "--summary-file",
+        "<PATH>",
+        "Write the summary of the commands run to the given file instead of stdout",
+        &["If --summary-format is not used, the summary is written in the text format."],
+    ),
+    (
+        "",
+        "--gh-summary",
+        "",
+        "Append a Markdown summary of the commands run to the GitHub Actions job summary",
+        &[
+            "The summary is appended to the file specified by the GITHUB_STEP_SUMMARY environment \
+             variable. This is done automatically on GitHub Actions (when the GITHUB_ACTIONS \
+             environment variable is set); this flag also enables it elsewhere, and fails if \
+             GITHUB_STEP_SUMMARY is not set.",
+            "This is independent of --summary-format and --summary-file.",
+        ],
+    ),
+    ("", "--no-gh-summary", "", "Do not append the summary to the GitHub Actions job summary", &[
+        "This disables the summary that is written automatically on GitHub Actions.",
+        "This flag cannot be used together with --gh-summary.",
+    ]),
+    ("", "--stop-on-first-success", "", "Stop as soon as a feature combination succeeds", &[
+        "Feature combinations that fail before that are reported but do not stop the run. If no \
+         feature combination succeeds, this fails as with --keep-going.",
+        "This flag cannot be used together with --keep-going (--no-fail-fast) or \
+         --print-command-list.",
+    ]),
+    (
+        "",
+        "--allow-failure",
+        "<FEATURES>...",
+        "Space or comma separated list of features whose combination is allowed to fail",
+        &[
+            "Failure of a run with exactly the specified set of features is reported as a warning \
+             instead of an error.",
+            "To specify multiple combinations, use this option multiple times: `--allow-failure a,b \
+             --allow-failure c`",
+        ],
+    ),
+    ("", "--retry", "<N>", "Retry failed commands up to N times", &[
+        "This is useful for working around spurious failures such as network errors.",
+        "Note that cargo-hack cannot distinguish such failures from compile errors, so all failed \
+         commands are retried.",
+    ]),
+    ("", "--timeout", "<SECONDS>", "Kill commands that run longer than the given seconds", &[
+        "A command that timed out is treated as failed, so this can be used together with \
+         --keep-going to continue with the other feature combinations.",
+        "On Unix, the command is run in its own process group, and the whole group is killed, \
+         so processes spawned by it (e.g., rustc run by cargo) are killed too. On other \
+         platforms, only the command directly run by cargo-hack is killed.",
+    ]),
+    (
+        "",
+        "--package-jobs",
+        "<N>",
+        "Run the feature combinations of up to N packages in parallel",
+        &[
+            "The feature combinations of each package are still run one at a time, in order. \
+             The indices shown in `(<index>/<total>)` of the log are the same as when the \
+             packages are run one at a time, so --continue-from can be used as usual.",
+            "This is independent of cargo's --jobs flag, which is still passed to each command, \
+             so the number of rustc processes may be up to N times the number of cargo jobs.",
+            "As cargo waits for the lock of the build directory, the commands of the second and \
+             subsequent jobs set CARGO_TARGET_DIR to a subdirectory of \
+             `<target-dir>/hack-package-jobs` (unless --target-dir-per-combination is used). \
+             These directories are kept to reuse the build cache in subsequent runs.",
+            "Manifests modified by --no-dev-deps, --remove-dev-deps, or --no-private are \
+             modified once before any package is run, so they are safe to use together with this \
+             flag.",
+            "The output of the commands run in parallel is interleaved, and the progress bar and \
+             log grouping are disabled.",
+            "This flag may not be used together with --resume, --status-file, \
+             --stop-on-first-success, --clean-per-run, or --log-group.",
+        ],
+    ),
+    ("", "--continue-from", "<INDEX>", "Skip commands before the given index", &[
+        "INDEX is the 1-based index shown in `(<index>/<total>)` of the log, so this can be used \
+         to resume an interrupted run.",
+        "The order of commands is deterministic as long as the packages, features, and flags are \
+         unchanged.",
+    ]),
+    ("", "--resume", "", "Resume the last run that did not complete successfully", &[
+        "The state of the run is recorded in `.cargo-hack/last-run.json` in the workspace root \
+         while running, and commands that have already been completed successfully in the last \
+         run are skipped.",
+        "If the packages, features, or flags have changed since the last run, all commands are \
+         run.",
+    ]),
+    ("", "--status-file", "<PATH>", "Write status events of each command to the given file", &[
+        "Events are written in the format specified by --status-format.",
+    ]),
+    ("", "--status-format", "<FORMAT>", "Format of status events: jsonl", &[
+        "jsonl: one JSON object per line: `{\"event\": \"start\"|\"finish\", \"package\": \
+         <name>, \"features\": [<feature>...], \"index\": <index>, \"total\": <total>, \
+         \"status\": null|\"success\"|\"failure\"}`. `status` is null for `start` events.",
+        "Defaults to jsonl.",
+    ]),
+    ("", "--no-progress", "", "Do not show the progress bar", &[
+        "By default, when stderr is a terminal, a progress bar is shown instead of per-command \
+         logs. The output of commands is written above the progress bar.",
+    ]),
     ("", "--log-group", "<KIND>", "Log grouping: none, github-actions", &[
         "If this option is not used, the environment will be automatically detected."
     ]),
+    ("", "--log-format", "<FORMAT>", "Format of messages printed by cargo-hack: human, short", &[
+        "If `short` is used, warnings and errors (and other messages) printed by cargo-hack are \
+         prefixed with `cargo-hack: warning:`, `cargo-hack: error:`, etc., and are never \
+         colored, so that they can be reliably detected by log scrapers.",
+        "This does not affect the output of cargo; use --message-format for that.",
+        "If this option is not used, `human` is used.",
+    ]),
     ("", "--print-command-list", "", "Print commands without run (Unstable)", &[]),
+    ("", "--count", "", "Print the number of commands to be run and exit", &[
+        "The number is calculated in the same way as an actual run, taking into account all \
+         options that affect the feature combinations and packages.",
+    ]),
+    (
+        "",
+        "--check-plan",
+        "",
+        "Run `cargo check --all-features` once on each package before the feature combinations",
+        &[
+            "This reports errors that affect all feature combinations, such as dependencies that \
+             cannot be resolved, before running the full set of feature combinations.",
+            "The same arguments as the feature combinations (e.g., -Z flags, --cargo-arg, and \
+             --features) are passed, except for the arguments after `--`.",
+            "Packages for which the run with --all-features is excluded (e.g., by \
+             --exclude-all-features) are checked with the features of the largest combination \
+             instead.",
+        ],
+    ),
+    (
+        "",
+        "--dump-features",
+        "<PATH>",
+        "Write the features and feature combinations of each package to the given file and exit",
+        &[
+            "The file is a TOML document with a table for each package, listing its features, \
+             optional dependencies, and features of dependencies, whether the runs with default \
+             features, just --no-default-features flag, and just --all-features flag would be \
+             performed, and the feature combinations that would be run.",
+            "No commands are run.",
+        ],
+    ),
+    (
+        "",
+        "--run-plan",
+        "<PATH>",
+        "Run the invocations listed in the given JSON file instead of generating them",
+        &[
+            "The file is a JSON array of objects, each with `package` (package name), `features` \
+             (array of features, defaults to empty), and `no-default-features` and \
+             `all-features` (booleans, default to false). If PATH is `-`, the plan is read from \
+             stdin.",
+            "This flag cannot be used together with --each-feature, --feature-powerset, or \
+             --version-range.",
+        ],
+    ),
+    (
+        "",
+        "--combination-warn-limit",
+        "<N>",
+        "Warn if the number of commands to be run exceeds N",
+        &[
+            "This is a guard against accidentally starting a very long run, e.g., due to \
+             --feature-powerset on a package with many features. Consider using --depth or \
+             --max-combinations-per-package to reduce the number of feature combinations.",
+            "If N is 0, no warning is emitted. Defaults to 1000.",
+        ],
+    ),
+    ("", "--exec", "<COMMAND>", "Run the given command instead of a cargo subcommand", &[
+        "The command is split on whitespace and run once per feature combination with the \
+         following environment variables set: CARGO_HACK_PACKAGE (package name), \
+         CARGO_HACK_FEATURES (comma-separated list of features), CARGO_HACK_NO_DEFAULT_FEATURES \
+         and CARGO_HACK_ALL_FEATURES (`true` or `false`).",
+        "This flag cannot be used together with a subcommand or --version-range.",
+    ]),
+    ("", "--command-prefix", "<COMMAND>", "Run each cargo command through the given command", &[
+        "The command is split on whitespace and prepended to each cargo command run for a \
+         feature combination, e.g., `--command-prefix 'timeout 600'` runs `timeout 600 cargo \
+         check ...`.",
+        "This flag cannot be used together with --exec.",
+    ]),
+    ("", "--env", "<KEY=VALUE>...", "Set the given environment variable for each command", &[
+        "The variable is set only for the commands run for feature combinations, not for \
+         cargo-hack itself or the other commands it runs (e.g., `cargo metadata`).",
+        "This flag can be specified multiple times.",
+    ]),
+    ("", "--deny-warnings", "", "Deny warnings in each command by adding `-D warnings` to rustflags", &[
+        "The flag is appended to the rustflags that cargo would otherwise use (`RUSTFLAGS` or \
+         `build.rustflags` config, etc.), so every feature combination must compile without \
+         warnings.",
+        "Changing rustflags causes cargo to rebuild the dependencies, so the artifacts built \
+         without this flag are not reused.",
+    ]),
+    ("", "--clippy-args", "<ARGS>", "Pass the given arguments to clippy for each command", &[
+        "The arguments are split on whitespace and placed after `--` (and the trailing arguments, \
+         if any) of each `cargo clippy` command, e.g., `cargo hack clippy --each-feature \
+         --clippy-args '-D warnings'` runs `cargo clippy ... -- -D warnings`.",
+        "The arguments are not passed to the subcommands other than clippy, so this can also be \
+         used together with --also clippy.",
+        "This flag can only be used together with either clippy subcommand or --also clippy.",
+    ]),
+    ("", "--also", "<SUBCOMMAND>", "Also run the given subcommand for each feature combination", &[
+        "The subcommand is run with the same arguments right after the primary subcommand, \
+         e.g., `cargo hack check --also clippy --feature-powerset` runs `cargo check` and \
+         `cargo clippy` for each feature combination.",
+        "This flag can be specified multiple times.",
+    ]),
+    ("", "--cargo-arg", "<ARG>", "Pass the given argument to cargo", &[
+        "The argument is passed to cargo before `--` without being interpreted by cargo-hack, \
+         e.g., `--cargo-arg=--timings`.",
+        "--features cannot be passed via this flag when --each-feature or --feature-powerset is \
+         used.",
+        "This flag can be specified multiple times.",
+    ]),
+    ("", "--print-features", "", "Print features discovered for each package and exit", &[
+        "Features are grouped per package into normal features, optional dependencies, and \
+         features of dependencies (only listed when --include-deps-features is specified).",
+        "No subcommand is run when this flag is used.",
+    ]),
+    ("", "--print-package-list", "", "Print the selected packages and exit", &[
+        "Packages are selected in the same way as an actual run, taking into account --workspace, \
+         --package, --exclude, and --only-packages-with-feature. Packages that would be skipped \
+         by --ignore-private are marked as such.",
+        "No subcommand is run when this flag is used.",
+    ]),
+    (
+        "",
+        "--check-feature-unification",
+        "",
+        "Report dependency features that are enabled only when built together with the workspace",
+        &[
+            "For each selected package, the features of its dependencies resolved when the package \
+             is built alone (`-p <package>`) are compared with those resolved when the whole \
+             workspace is built (`--workspace`), and the features enabled only in the latter are \
+             reported. A package with such features may compile as part of the workspace but \
+             fail to compile on its own, e.g., when published.",
+            "The features are read from `cargo tree`, so nothing is built. Dev-dependencies are \
+             not considered.",
+            "This fails if any difference is found. No subcommand is run when this flag is used.",
+        ],
+    ),
+    ("", "--gh-matrix", "", "Print the feature combinations as a GitHub Actions matrix and exit", &[
+        "The matrix is a JSON object printed to stdout that can be used as `matrix` of a job via \
+         `fromJSON`, e.g., `{\"include\":[{\"package\":\"foo\",\"features\":\"a,b\",\
+         \"flags\":\"--no-default-features --features a,b\"}]}`. `features` is the \
+         comma-separated list of enabled features, and `flags` is the feature flags to pass to \
+         cargo.",
+        "The feature combinations are calculated in the same way as an actual run.",
+        "The subcommand can be omitted when this flag is used.",
+    ]),
     ("", "--no-manifest-path", "", "Do not pass --manifest-path option to cargo (Unstable)", &[]),
     ("-v", "--verbose", "", "Use verbose output", &[]),
     ("", "--color", "<WHEN>", "Coloring: auto, always, never", &[
@@ -899,7 +2397,7 @@ impl fmt::Display for Help {
             "\
 {0}{1}\n{2}
 USAGE:
-    cargo hack [OPTIONS] [SUBCOMMAND]\n
+    cargo hack [+toolchain] [OPTIONS] [SUBCOMMAND]\n
 Use -h for short descriptions and --help for more details.\n
 OPTIONS:",
             env!("CARGO_PKG_NAME"),
@@ -943,6 +2441,9 @@ Some common cargo commands are (see all commands with --list):
     check       Analyze the current package and report errors, but don't build object files
     run         Run a binary or example of the local package
     test        Run the tests
+
+To find a minimal set of features with which the subcommand fails, instead of running all
+feature combinations, use `cargo hack bisect-features <SUBCOMMAND>`.
 ",
         )
     }
@@ -981,7 +2482,6 @@ For more information try --help
 fn similar_flags(flag: &str, subcommand: Option<&str>) -> Result<()> {
     let expected = match flag {
         "no-dev-dep" => "--no-dev-deps",
-        "remove-dev-dep" => "--remove-dev-deps",
         "each-features" => "--each-feature",
         "features-powerset" => "--feature-powerset",
         "exclude-no-default-feature" => "--exclude-no-default-features",
@@ -1003,7 +2503,7 @@ fn mini_usage(msg: &str) -> Result<()> {
 {msg}
 
 USAGE:
-    cargo hack [OPTIONS] [SUBCOMMAND]
+    cargo hack [+toolchain] [OPTIONS] [SUBCOMMAND]
 
 For more information try --help",
     )
@@ -1045,11 +2545,27 @@ fn format_flag(flag: &lexopt::Arg<'_>) -> String {
     }
 }
 
+/// Splits a list of features separated by commas and/or whitespace, e.g.,
+/// `a b,c`, as cargo does for `--features`.
+fn split_features(list: &str) -> impl Iterator<Item = &str> {
+    list.split(|c: char| c == ',' || c.is_whitespace()).filter(|s| !s.is_empty())
+}
+
+fn read_env_list(flag: &str, name: &str) -> Result<String> {
+    match env::var(name) {
+        Ok(val) => Ok(val),
+        Err(env::VarError::NotPresent) => {
+            bail!("environment variable `{name}` specified by {flag} is not set")
+        }
+        Err(e) => bail!("failed to read environment variable `{name}` specified by {flag}: {e}"),
+    }
+}
+
 #[cold]
 #[inline(never)]
 fn multi_arg(flag: &lexopt::Arg<'_>, subcommand: Option<&str>) -> Result<()> {
     let flag = &format_flag(flag);
-    let arg = get_help(flag).map_or_else(|| flag.to_string(), |arg| format!("{} {}", arg.1, arg.2));
+    let arg = get_help(flag).map_or_else(|| flag.clone(), |arg| format!("{} {}", arg.1, arg.2));
     bail!(
         "\
 The argument '{flag}' was provided more than once, but cannot be used multiple times
@@ -1063,6 +2579,28 @@ For more information try --help
     )
 }
 
+/// Checks that --manifest-path points to an existing `Cargo.toml`. If a
+/// directory is given, `Cargo.toml` in it is used.
+fn check_manifest_path(path: String) -> Result<String> {
+    let p = Path::new(&path);
+    if p.is_dir() {
+        let manifest_path = p.join("Cargo.toml");
+        if !manifest_path.is_file() {
+            bail!("manifest path `{path}` is a directory but does not contain `Cargo.toml`");
+        }
+        return Ok(manifest_path
+            .to_str()
+            .map_or_else(|| format!("{path}/Cargo.toml"), str::to_owned));
+    }
+    if p.file_name() != Some(OsStr::new("Cargo.toml")) {
+        bail!("the manifest-path must be a path to a Cargo.toml file, but found `{path}`");
+    }
+    if !p.is_file() {
+        bail!("manifest path `{path}` does not exist");
+    }
+    Ok(path)
+}
+
 /// `flag` requires one of `requires`.
 #[cold]
 #[inline(never)]
@@ -2,9 +2,9 @@
 
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     env,
-    ffi::OsString,
+    ffi::{OsStr, OsString},
     ops,
     path::{Path, PathBuf},
 };
@@ -13,11 +13,17 @@ use anyhow::{bail, Context as _, Result};
 
 use crate::{
     cargo,
+    cfg::TargetCfg,
+    changed,
     cli::Args,
     features::Features,
-    manifest::Manifest,
+    manifest::{self, Manifest},
+    matrix::{self, Matrix},
     metadata::{Metadata, Package, PackageId},
-    restore, term, ProcessBuilder,
+    restore,
+    resume::LastRun,
+    runner::Runner,
+    term, ProcessBuilder,
 };
 
 pub(crate) struct Context {
@@ -25,31 +31,56 @@ pub(crate) struct Context {
     pub(crate) metadata: Metadata,
     manifests: HashMap<PackageId, Manifest>,
     pkg_features: HashMap<PackageId, Features>,
+    /// `[workspace.metadata.cargo-hack]` table of the root manifest.
+    pub(crate) workspace_config: manifest::WorkspaceConfig,
     cargo: PathBuf,
     pub(crate) cargo_version: u32,
     pub(crate) restore: restore::Manager,
     pub(crate) current_dir: PathBuf,
     pub(crate) current_package: Option<PackageId>,
+    /// The state of the last run, read if --resume is specified.
+    pub(crate) last_run: Option<LastRun>,
+    /// The cfg values of the targets, read if --exclude-features-matching-cfg is specified.
+    pub(crate) target_cfgs: Vec<TargetCfg>,
+    /// The feature combinations of each package, read if --matrix-file is specified.
+    pub(crate) matrix: Matrix,
+    /// The rustflags used by cargo, encoded as `CARGO_ENCODED_RUSTFLAGS`, read if
+    /// --deny-warnings is specified or any package has `feature-rustflags` in
+    /// `[package.metadata.cargo-hack]`.
+    pub(crate) rustflags: Option<String>,
+    /// The workspace members changed since the git ref specified by --changed-since.
+    pub(crate) changed_packages: Option<HashSet<PackageId>>,
+    /// Runs the cargo commands of the run.
+    pub(crate) runner: Box<dyn Runner>,
 }
 
 impl Context {
-    pub(crate) fn new() -> Result<Self> {
+    pub(crate) fn new(runner: Box<dyn Runner>) -> Result<Self> {
         let cargo = env::var_os("CARGO_HACK_CARGO_SRC")
             .unwrap_or_else(|| env::var_os("CARGO").unwrap_or_else(|| OsString::from("cargo")));
         let args = Args::parse(&cargo)?;
         assert!(
-            args.subcommand.is_some() || args.remove_dev_deps,
+            args.subcommand.is_some()
+                || args.remove_dev_deps
+                || args.print_features
+                || args.print_package_list
+                || args.check_feature_unification
+                || args.gh_matrix
+                || args.exec.is_some(),
             "no subcommand or valid flag specified"
         );
 
         // If failed to determine cargo version, assign 0 to skip all version-dependent decisions.
-        let cargo_version = cargo::version(cmd!(&cargo))
+        let cargo_version = cargo::version(cargo_process(&cargo, args.toolchain.as_deref()))
             .map_err(|e| warn!("unable to determine cargo version: {e:#}"))
-            .map(|v| v.minor)
-            .unwrap_or(0);
+            .map_or(0, |v| v.minor);
 
         // if `--remove-dev-deps` flag is off, restore manifest file.
-        let restore = restore::Manager::new(!args.remove_dev_deps);
+        let restore = restore::Manager::new(
+            !args.remove_dev_deps,
+            args.backup_manifests.clone(),
+            args.keep_backups,
+        );
         let metadata =
             Metadata::new(args.manifest_path.as_deref(), &cargo, cargo_version, &args, &restore)?;
         if metadata.cargo_version < 41 && args.include_deps_features {
@@ -67,6 +98,10 @@ impl Context {
             pkg_features.insert(id.clone(), features);
         }
 
+        let workspace_config =
+            Manifest::new(&metadata.workspace_root.join("Cargo.toml"), metadata.cargo_version)?
+                .workspace_config;
+
         let mut cmd = cmd!(&cargo, "locate-project");
         if let Some(manifest_path) = &args.manifest_path {
             cmd.arg("--manifest-path");
@@ -89,16 +124,67 @@ impl Context {
             }
         }
 
+        let last_run =
+            if args.resume { Some(LastRun::read(&metadata.workspace_root)?) } else { None };
+
+        let target_cfgs =
+            if args.exclude_features_matching_cfg { target_cfgs(&args)? } else { vec![] };
+
+        let rustflags = if args.deny_warnings
+            || manifests.values().any(|m| !m.config.feature_rustflags.is_empty())
+        {
+            Some(rustflags(&args)?)
+        } else {
+            None
+        };
+
+        let changed_packages = args
+            .changed_since
+            .as_deref()
+            .map(|git_ref| changed::packages(&metadata, git_ref))
+            .transpose()?;
+
+        let matrix = match &args.matrix_file {
+            Some(path) => {
+                let matrix = matrix::read(Path::new(path))?;
+                for (name, combinations) in &matrix {
+                    let Some(id) = metadata
+                        .workspace_members
+                        .iter()
+                        .find(|id| metadata.packages[id].name == *name)
+                    else {
+                        bail!("package `{name}` specified in `{path}` not found in workspace");
+                    };
+                    for f in combinations.iter().flatten() {
+                        if !pkg_features[id].contains(f) {
+                            bail!(
+                                "feature `{f}` specified for package `{name}` in `{path}` not found"
+                            );
+                        }
+                    }
+                }
+                matrix
+            }
+            None => Matrix::new(),
+        };
+
         let this = Self {
             args,
             metadata,
             manifests,
             pkg_features,
+            workspace_config,
             cargo: cargo.into(),
             cargo_version,
             restore,
             current_dir: env::current_dir()?,
             current_package,
+            last_run,
+            target_cfgs,
+            matrix,
+            rustflags,
+            changed_packages,
+            runner,
         };
 
         // TODO: Ideally, we should do this, but for now, we allow it as cargo-hack
@@ -126,10 +212,19 @@ impl Context {
         self.current_package.as_ref()
     }
 
+    /// Returns the default members of the workspace, or `None` if unknown.
+    pub(crate) fn workspace_default_members(&self) -> Option<&[PackageId]> {
+        self.metadata.workspace_default_members.as_deref()
+    }
+
     pub(crate) fn workspace_root(&self) -> &Path {
         &self.metadata.workspace_root
     }
 
+    pub(crate) fn target_directory(&self) -> &Path {
+        &self.metadata.target_directory
+    }
+
     pub(crate) fn manifests(&self, id: &PackageId) -> &Manifest {
         &self.manifests[id]
     }
@@ -138,19 +233,37 @@ impl Context {
         &self.pkg_features[id]
     }
 
-    pub(crate) fn is_private(&self, id: &PackageId) -> bool {
+    fn member_manifest_package(&self, id: &PackageId) -> &manifest::Package {
+        // The manifest of a workspace member always has `[package]` table.
+        self.manifests(id).package.as_ref().unwrap()
+    }
+
+    /// Returns the registries to which the package may be published, or `None`
+    /// if publishing is unrestricted.
+    pub(crate) fn publish(&self, id: &PackageId) -> Option<&[String]> {
         if self.metadata.cargo_version >= 39 {
-            !self.packages(id).publish
+            self.packages(id).publish.as_deref()
         } else {
-            !self.manifests(id).package.publish.unwrap()
+            self.member_manifest_package(id).publish.as_ref().unwrap().as_deref()
         }
     }
 
+    pub(crate) fn is_private(&self, id: &PackageId) -> bool {
+        self.publish(id).is_some_and(<[_]>::is_empty)
+    }
+
+    /// Returns `true` if the package is skipped by --ignore-private (or
+    /// --no-private).
+    pub(crate) fn is_ignored_private(&self, id: &PackageId) -> bool {
+        self.ignore_private
+            && (self.is_private(id) || self.ignore_private_restricted && self.publish(id).is_some())
+    }
+
     pub(crate) fn rust_version(&self, id: &PackageId) -> Option<&str> {
         if self.metadata.cargo_version >= 58 {
             self.packages(id).rust_version.as_deref()
         } else {
-            self.manifests(id).package.rust_version.as_ref().unwrap().as_deref()
+            self.member_manifest_package(id).rust_version.as_ref().unwrap().as_deref()
         }
     }
 
@@ -168,7 +281,23 @@ impl Context {
     }
 
     pub(crate) fn cargo(&self) -> ProcessBuilder<'_> {
-        cmd!(&self.cargo)
+        cargo_process(self.cargo.as_os_str(), self.toolchain.as_deref())
+    }
+}
+
+/// Creates a process builder for cargo. If `+<toolchain>` is specified, cargo
+/// of that toolchain is run via rustup.
+fn cargo_process<'a>(cargo: &OsStr, toolchain: Option<&str>) -> ProcessBuilder<'a> {
+    match toolchain {
+        // Do not use `cargo +<toolchain>` due to a rustup bug: https://github.com/rust-lang/rustup/issues/3036
+        Some(toolchain) => {
+            let mut cmd = cmd!("rustup");
+            cmd.leading_arg("run");
+            cmd.leading_arg(toolchain);
+            cmd.leading_arg("cargo");
+            cmd
+        }
+        None => cmd!(cargo),
     }
 }
 
@@ -179,3 +308,35 @@ impl ops::Deref for Context {
         &self.args
     }
 }
+
+/// Reads the cfg values of the targets specified by --target (or `build.target`
+/// config), or the host if no target is specified.
+fn target_cfgs(args: &Args) -> Result<Vec<TargetCfg>> {
+    let config = cargo_config2::Config::load()?;
+    let targets = config.build_target_for_cli(&args.target)?;
+    let rustc = || {
+        let rustc = config.rustc();
+        let mut cmd = cmd!(&rustc.path);
+        cmd.args(&rustc.args);
+        cmd
+    };
+    if targets.is_empty() {
+        return Ok(vec![TargetCfg::new(rustc(), None)?]);
+    }
+    targets.iter().map(|target| TargetCfg::new(rustc(), Some(target))).collect()
+}
+
+/// Reads the rustflags used by cargo for the first target specified by --target
+/// (or `build.target` config), or the host if no target is specified.
+fn rustflags(args: &Args) -> Result<String> {
+    let config = cargo_config2::Config::load()?;
+    let targets = config.build_target_for_cli(&args.target)?;
+    let flags = match targets.first() {
+        Some(target) => config.rustflags(target)?,
+        None => config.rustflags(config.host_triple()?)?,
+    };
+    Ok(match flags {
+        Some(flags) => flags.encode()?,
+        None => String::new(),
+    })
+}
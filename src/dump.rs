@@ -0,0 +1,75 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// The file written by --dump-features, which records the features of each
+// package and the feature combinations that would be run.
+//
+// ```toml
+// [foo]
+// features = ["a", "b", "default"]
+// optional-deps = ["dep"]
+// deps-features = []
+// default = false
+// no-default-features = true
+// all-features = true
+// combinations = [["a"], ["b"], ["default"]]
+// ```
+
+use std::path::Path;
+
+use anyhow::{Context as _, Result};
+use toml_edit::{Array, DocumentMut, Item, Table};
+
+use crate::{
+    context::Context, exclude_all_features, features::Feature, fs, phases, Kind, PackageRuns, Phase,
+};
+
+pub(crate) fn write(cx: &Context, packages: &[PackageRuns<'_>], path: &Path) -> Result<()> {
+    let mut doc = DocumentMut::new();
+    for pkg in packages {
+        let package = cx.packages(pkg.id);
+        let pkg_features = cx.pkg_features(pkg.id);
+        let mut table = Table::new();
+        table.insert("features", names(pkg_features.normal()));
+        table.insert("optional-deps", names(pkg_features.optional_deps()));
+        table.insert("deps-features", names(pkg_features.deps_features()));
+
+        let (default, no_default_features, all_features, combinations) = match &pkg.kind {
+            Kind::Normal => (!cx.only_all_features, false, cx.only_all_features, vec![]),
+            Kind::Each { .. } | Kind::Powerset { .. } => {
+                let phases = phases(cx, pkg.id);
+                let combinations: Vec<Vec<&Feature>> = match &pkg.kind {
+                    _ if !phases.contains(&Phase::Features) => vec![],
+                    Kind::Each { features } => features.iter().map(|&f| vec![f]).collect(),
+                    Kind::Powerset { features } => features.clone(),
+                    Kind::Normal => unreachable!(),
+                };
+                (
+                    phases.contains(&Phase::Default) && !cx.no_default_features,
+                    phases.contains(&Phase::NoDefault) && !cx.exclude_no_default_features,
+                    phases.contains(&Phase::All)
+                        && !exclude_all_features(cx, pkg.id)
+                        && pkg_features.normal().len() + pkg_features.optional_deps().len() > 1,
+                    combinations,
+                )
+            }
+        };
+        table.insert("default", toml_edit::value(default));
+        table.insert("no-default-features", toml_edit::value(no_default_features));
+        table.insert("all-features", toml_edit::value(all_features));
+        let combinations: Array = combinations
+            .iter()
+            .map(|c| c.iter().flat_map(|f| f.as_group()).map(String::as_str).collect::<Array>())
+            .collect();
+        table.insert("combinations", toml_edit::value(combinations));
+        doc.insert(&package.name, Item::Table(table));
+    }
+    fs::write(path, doc.to_string())
+        .with_context(|| format!("failed to write features to `{}`", path.display()))?;
+    let s = if packages.len() == 1 { "" } else { "s" };
+    info!("wrote features of {} package{s} to `{}`", packages.len(), path.display());
+    Ok(())
+}
+
+fn names(features: &[Feature]) -> Item {
+    toml_edit::value(features.iter().map(Feature::name).collect::<Array>())
+}
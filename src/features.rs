@@ -1,10 +1,13 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
 use std::{
-    collections::{BTreeMap, BTreeSet},
-    fmt, slice,
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt, iter, slice,
+    str::FromStr,
 };
 
+use anyhow::{bail, Error, Result};
+
 use crate::{manifest::Manifest, metadata::Metadata, PackageId};
 
 #[derive(Debug)]
@@ -12,6 +15,9 @@ pub(crate) struct Features {
     features: Vec<Feature>,
     optional_deps_start: usize,
     deps_features_start: usize,
+    /// Features of dependencies to the features (and the dependency itself)
+    /// that are enabled when that feature is enabled.
+    deps_features_enables: BTreeMap<String, Vec<String>>,
 }
 
 impl Features {
@@ -47,6 +53,7 @@ impl Features {
             }
         }
         let deps_features_start = features.len();
+        let mut deps_features_enables = BTreeMap::new();
 
         if include_deps_features {
             let node = &metadata.resolve.nodes[id];
@@ -62,12 +69,23 @@ impl Features {
                 if let Some(d) = package.dependencies.iter().find(|d| d.name == dep_package.name) {
                     let name = d.rename.as_ref().unwrap_or(&d.name);
                     features.extend(dep_package.features.keys().map(|f| Feature::path(name, f)));
+                    for (f, deps) in feature_deps(&dep_package.features) {
+                        let enables = iter::once(name.clone())
+                            .chain(
+                                deps.into_iter()
+                                    // features of the dependency's dependencies
+                                    .filter(|d| !d.contains('/'))
+                                    .map(|d| format!("{name}/{d}")),
+                            )
+                            .collect();
+                        deps_features_enables.insert(format!("{name}/{f}"), enables);
+                    }
                 }
                 // TODO: Optional deps of `dep_package`.
             }
         }
 
-        Self { features, optional_deps_start, deps_features_start }
+        Self { features, optional_deps_start, deps_features_start, deps_features_enables }
     }
 
     pub(crate) fn normal(&self) -> &[Feature] {
@@ -82,13 +100,24 @@ impl Features {
         &self.features[self.deps_features_start..]
     }
 
+    /// Returns the features that are also enabled when the given feature of a
+    /// dependency is enabled: the features of the dependency that it
+    /// transitively enables, and the dependency itself.
+    pub(crate) fn enabled_by_deps_feature(&self, f: &Feature) -> &[String] {
+        self.deps_features_enables.get(f.name()).map_or(&[], Vec::as_slice)
+    }
+
     pub(crate) fn contains(&self, name: &str) -> bool {
         self.features.iter().any(|f| f == name)
     }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&Feature> {
+        self.features.iter().find(|f| *f == name)
+    }
 }
 
 /// The representation of Cargo feature.
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) enum Feature {
     /// A feature of the current crate.
     Normal {
@@ -177,6 +206,54 @@ impl AsRef<str> for Feature {
     }
 }
 
+/// The value of --depth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Depth {
+    /// `max`: no limit (an explicit form of full powerset).
+    Max,
+    /// `<NUM>`: max number of simultaneous feature flags.
+    Count(usize),
+    /// `<NUM>%`: max number of simultaneous feature flags relative to the number of features.
+    Percent(u8),
+}
+
+impl Depth {
+    /// Resolves the depth against the number of features of a package.
+    /// Returns `None` if there is no limit.
+    pub(crate) fn resolve(self, feature_count: usize) -> Option<usize> {
+        match self {
+            Self::Max => None,
+            Self::Count(n) => Some(n),
+            // Round up so that a non-zero percentage never results in zero depth.
+            Self::Percent(p) => Some(((feature_count * p as usize + 99) / 100).max(1)),
+        }
+    }
+}
+
+impl FromStr for Depth {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "max" {
+            return Ok(Self::Max);
+        }
+        if let Some(p) = s.strip_suffix('%') {
+            match p.parse::<u8>() {
+                Ok(p @ 1..=100) => return Ok(Self::Percent(p)),
+                Ok(_) => {
+                    bail!("percentage for --depth must be between 1% and 100%, but found `{s}`")
+                }
+                Err(_) => {}
+            }
+        } else if let Ok(n) = s.parse::<usize>() {
+            return Ok(Self::Count(n));
+        }
+        bail!(
+            "argument for --depth must be `max`, an integer, or a percentage (e.g., `50%`), but found `{s}`"
+        )
+    }
+}
+
 pub(crate) fn feature_powerset<'a>(
     features: impl IntoIterator<Item = &'a Feature>,
     depth: Option<usize>,
@@ -186,6 +263,7 @@ pub(crate) fn feature_powerset<'a>(
 ) -> Vec<Vec<&'a Feature>> {
     let deps_map = feature_deps(package_features);
     let at_least_one_of = at_least_one_of_for_package(at_least_one_of, &deps_map);
+    let mut seen: HashSet<BTreeSet<&str>> = HashSet::new();
 
     powerset(features, depth)
         .into_iter()
@@ -222,10 +300,15 @@ pub(crate) fn feature_powerset<'a>(
             }
             true
         })
+        .filter(|fs| {
+            // Filter any feature set that resolves to the same set of features as a previous
+            // one, e.g., due to overlapping groups.
+            seen.insert(fs.iter().flat_map(|f| f.as_group()).map(String::as_str).collect())
+        })
         .collect()
 }
 
-fn feature_deps(map: &BTreeMap<String, Vec<String>>) -> BTreeMap<&str, BTreeSet<&str>> {
+pub(crate) fn feature_deps(map: &BTreeMap<String, Vec<String>>) -> BTreeMap<&str, BTreeSet<&str>> {
     fn f<'a>(
         map: &'a BTreeMap<String, Vec<String>>,
         set: &mut BTreeSet<&'a str>,
@@ -268,6 +351,30 @@ fn powerset<T: Copy>(iter: impl IntoIterator<Item = T>, depth: Option<usize>) ->
     })
 }
 
+/// Picks at most `max` elements of `combinations`, evenly spread over the list
+/// (--max-combinations-per-package).
+pub(crate) fn sample<T>(combinations: Vec<T>, max: usize) -> Vec<T> {
+    let len = combinations.len();
+    if len <= max {
+        return combinations;
+    }
+    // `i * len / max` is strictly increasing because `len > max`.
+    let mut next = 0;
+    combinations
+        .into_iter()
+        .enumerate()
+        .filter(|&(i, _)| {
+            if next < max && i == next * len / max {
+                next += 1;
+                true
+            } else {
+                false
+            }
+        })
+        .map(|(_, c)| c)
+        .collect()
+}
+
 // Leave only features that are possible to enable in the package.
 pub(crate) fn at_least_one_of_for_package<'a>(
     at_least_one_of: &[Feature],
@@ -305,7 +412,10 @@ pub(crate) fn at_least_one_of_for_package<'a>(
 mod tests {
     use std::collections::{BTreeMap, BTreeSet};
 
-    use super::{at_least_one_of_for_package, feature_deps, feature_powerset, powerset, Feature};
+    use super::{
+        at_least_one_of_for_package, feature_deps, feature_powerset, powerset, sample, Depth,
+        Feature,
+    };
 
     macro_rules! v {
         ($($expr:expr),* $(,)?) => {
@@ -380,6 +490,17 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn powerset_dedup() {
+        let map = map![("a", v![]), ("b", v![]), ("c", v![])];
+        let list =
+            [Feature::group(["a", "b"]), Feature::group(["b", "c"]), Feature::group(["a", "c"])];
+        let [ab, bc, ac] = &list;
+        let filtered = feature_powerset(&list, None, &[], &[], &map);
+        // [a,b] + [b,c], [a,b] + [a,c], [b,c] + [a,c], and [a,b] + [b,c] + [a,c] are all `a,b,c`.
+        assert_eq!(filtered, vec![vec![ab], vec![bc], vec![ab, bc], vec![ac]]);
+    }
+
     #[test]
     fn feature_deps1() {
         let map = map![("a", v![]), ("b", v!["a"]), ("c", v!["b"]), ("d", v!["a", "b"])];
@@ -482,4 +603,35 @@ mod tests {
             vec![2, 3, 4],
         ]);
     }
+
+    #[test]
+    fn depth() {
+        assert_eq!("max".parse::<Depth>().unwrap(), Depth::Max);
+        assert_eq!("2".parse::<Depth>().unwrap(), Depth::Count(2));
+        assert_eq!("50%".parse::<Depth>().unwrap(), Depth::Percent(50));
+        assert!("0%".parse::<Depth>().is_err());
+        assert!("101%".parse::<Depth>().is_err());
+        assert!("-1".parse::<Depth>().is_err());
+        assert!("a%".parse::<Depth>().is_err());
+        assert!("maximum".parse::<Depth>().is_err());
+
+        assert_eq!(Depth::Max.resolve(4), None);
+        assert_eq!(Depth::Count(2).resolve(4), Some(2));
+        assert_eq!(Depth::Percent(50).resolve(4), Some(2));
+        assert_eq!(Depth::Percent(50).resolve(5), Some(3));
+        assert_eq!(Depth::Percent(1).resolve(4), Some(1));
+        assert_eq!(Depth::Percent(1).resolve(0), Some(1));
+        assert_eq!(Depth::Percent(100).resolve(4), Some(4));
+    }
+
+    #[test]
+    fn sample_evenly() {
+        let list: Vec<_> = (0..10).collect();
+        assert_eq!(sample(list.clone(), 10), list);
+        assert_eq!(sample(list.clone(), 20), list);
+        assert_eq!(sample(list.clone(), 5), [0, 2, 4, 6, 8]);
+        assert_eq!(sample(list.clone(), 3), [0, 3, 6]);
+        assert_eq!(sample(list.clone(), 1), [0]);
+        assert_eq!(sample(list, 0), Vec::<i32>::new());
+    }
 }
@@ -19,3 +19,27 @@ pub(crate) fn read_to_string(path: impl AsRef<Path>) -> Result<String> {
     let res = std::fs::read_to_string(path);
     res.with_context(|| format!("failed to read from file `{}`", path.display()))
 }
+
+/// Recursively create a directory and all of its parent components if they are missing.
+/// This is a wrapper for [`std::fs::create_dir_all`].
+pub(crate) fn create_dir_all(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let res = std::fs::create_dir_all(path);
+    res.with_context(|| format!("failed to create directory `{}`", path.display()))
+}
+
+/// Removes a file from the filesystem.
+/// This is a wrapper for [`std::fs::remove_file`].
+pub(crate) fn remove_file(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let res = std::fs::remove_file(path);
+    res.with_context(|| format!("failed to remove file `{}`", path.display()))
+}
+
+/// Removes a directory at this path, after removing all its contents.
+/// This is a wrapper for [`std::fs::remove_dir_all`].
+pub(crate) fn remove_dir_all(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let res = std::fs::remove_dir_all(path);
+    res.with_context(|| format!("failed to remove directory `{}`", path.display()))
+}
@@ -0,0 +1,66 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// A minimal glob matcher used to match package names (e.g., `--exclude 'internal-*'`).
+// `*` matches any sequence of characters and `?` matches any single character.
+// Other characters match themselves.
+
+/// Returns `true` if `s` matches `pattern`.
+pub(crate) fn matches(pattern: &str, s: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+    let (mut p, mut i) = (0, 0);
+    // The position of the last `*` in the pattern and the position in `s` it is matched up to.
+    let mut star = None;
+    while i < s.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, i));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == s[i] => {
+                p += 1;
+                i += 1;
+            }
+            _ => match star {
+                // Let the last `*` match one more character and retry.
+                Some((sp, si)) => {
+                    star = Some((sp, si + 1));
+                    p = sp + 1;
+                    i = si + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Returns `true` if `pattern` contains `*` or `?`.
+pub(crate) fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_glob, matches};
+
+    #[test]
+    fn glob() {
+        assert!(matches("foo", "foo"));
+        assert!(!matches("foo", "foobar"));
+        assert!(matches("internal-*", "internal-foo"));
+        assert!(matches("internal-*", "internal-"));
+        assert!(!matches("internal-*", "foo-internal"));
+        assert!(matches("*-sys", "openssl-sys"));
+        assert!(matches("*a*b*", "xxaxxbxx"));
+        assert!(!matches("*a*b*", "xxbxxaxx"));
+        assert!(matches("member?", "member1"));
+        assert!(!matches("member?", "member10"));
+        assert!(matches("*", ""));
+        assert!(!matches("?", ""));
+
+        assert!(is_glob("api-*"));
+        assert!(is_glob("member?"));
+        assert!(!is_glob("member1"));
+    }
+}
@@ -8,57 +8,135 @@ mod term;
 #[macro_use]
 mod process;
 
+mod bisect;
 mod cargo;
+mod cfg;
+mod changed;
 mod cli;
 mod context;
+mod dump;
 mod features;
 mod fs;
+mod glob;
 mod manifest;
+mod matrix;
 mod metadata;
 mod restore;
+mod resume;
+mod run_plan;
+mod runner;
 mod rustup;
+mod summary;
+mod unification;
 mod version;
 
 use std::{
-    collections::{BTreeMap, HashSet},
+    cell::Cell,
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, HashSet},
     env,
     fmt::{self, Write},
-    str::FromStr,
+    fs::File,
+    hash::{Hash as _, Hasher as _},
+    io::{self, Write as _},
+    path::PathBuf,
+    process::Output,
+    str::{self, FromStr},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+    time::Instant,
 };
 
-use anyhow::{bail, format_err, Error, Result};
+use anyhow::{bail, format_err, Context as _, Error, Result};
 
 use crate::{
     context::Context,
     features::Feature,
-    metadata::PackageId,
+    metadata::{PackageId, PackageSpec},
     process::ProcessBuilder,
+    resume::LastRun,
     rustup::Rustup,
     version::{Version, VersionRange},
 };
 
+/// Exit codes of cargo-hack.
+#[derive(Clone, Copy)]
+enum ExitCode {
+    /// cargo-hack itself failed, e.g., due to invalid usage or configuration.
+    Error = 1,
+    /// A command run by cargo-hack for a feature combination failed.
+    CommandFailed = 2,
+    /// The run was interrupted, e.g., by Ctrl-C.
+    Interrupted = 3,
+}
+
+impl ExitCode {
+    fn exit(self) -> ! {
+        std::process::exit(self as i32)
+    }
+}
+
+/// Set when a command run for a feature combination failed.
+static COMMAND_FAILED: AtomicBool = AtomicBool::new(false);
+
 fn main() {
     term::init_coloring();
-    if let Err(e) = try_main() {
+    let res = try_main();
+    term::clear_progress_bar();
+    if let Err(e) = res {
         error!("{e:#}");
     }
     if term::error()
-        || term::warn() && env::var_os("CARGO_HACK_DENY_WARNINGS").filter(|v| v == "true").is_some()
+        || term::warn()
+            && env::var_os("CARGO_HACK_DENY_WARNINGS").as_ref().is_some_and(|v| v == "true")
     {
-        std::process::exit(1)
+        if COMMAND_FAILED.load(Ordering::Relaxed) {
+            ExitCode::CommandFailed.exit()
+        }
+        ExitCode::Error.exit()
     }
 }
 
 fn try_main() -> Result<()> {
-    let cx = &Context::new()?;
+    let cx = &Context::new(runner::from_env())?;
+
+    if cx.print_features {
+        return print_features(cx);
+    }
+    if cx.print_package_list {
+        return print_package_list(cx);
+    }
+    if cx.check_feature_unification {
+        return unification::check(cx);
+    }
 
     manifest::with(cx, || {
-        if cx.subcommand.is_none() {
+        if cx.subcommand.is_none() && cx.exec.is_none() && !cx.gh_matrix {
             return Ok(());
         }
+        let _target_dirs = cx.target_dir_per_combination.then_some(TargetDirsGuard(cx));
 
+        if let Some(path) = &cx.run_plan {
+            return run_plan::run(cx, path);
+        }
         let packages = determine_package_list(cx)?;
+        if let Some(path) = &cx.dump_features {
+            return dump::write(cx, &packages, path);
+        }
+        if cx.bisect_features {
+            bisect::run(cx, &packages);
+            return Ok(());
+        }
+        if cx.check_plan {
+            check_plan(cx, &packages)?;
+        }
+        let plan = cx.resume.then(|| resume::plan(cx, &packages));
         let mut progress = Progress::default();
+        if let Some(path) = cx.status_file.as_ref().filter(|_| !cx.count) {
+            progress.status_file = Some(
+                File::create(path)
+                    .with_context(|| format!("failed to create {}", path.display()))?,
+            );
+        }
         let mut keep_going = KeepGoing::default();
         if let Some(range) = cx.version_range {
             let mut versions = BTreeMap::new();
@@ -79,8 +157,11 @@ fn try_main() -> Result<()> {
                     versions.entry(msrv).or_insert_with(Vec::new).push(pkg);
                 } else {
                     let mut seen = false;
+                    // Toolchains older than the package's rust-version, which cannot build it.
+                    let mut too_old = vec![];
                     for cargo_version in &steps {
                         if msrv.is_some() && Some(*cargo_version) < msrv {
+                            too_old.push(cargo_version.to_string());
                             continue;
                         }
                         if !seen {
@@ -93,17 +174,29 @@ fn try_main() -> Result<()> {
                         }
                         versions.entry(*cargo_version).or_insert_with(Vec::new).push(pkg.clone());
                     }
+                    let name = &cx.packages(pkg.id).name;
                     if !seen {
-                        let package = cx.packages(pkg.id);
-                        let name = &package.name;
                         let msrv = msrv.expect("always `seen` if no msrv");
                         warn!("skipping {name}, rust-version ({msrv}) is not in specified range ({range})");
+                    } else if !too_old.is_empty() {
+                        let msrv = msrv.unwrap();
+                        info!(
+                            "skipping {name} on {}: older than its rust-version ({msrv})",
+                            too_old.join(", ")
+                        );
                     }
                 }
             }
 
+            if cx.print_toolchains {
+                print_toolchains(cx, &versions);
+                return Ok(());
+            }
+
+            let mut counted = HashSet::new();
             for (cargo_version, packages) in &versions {
                 for package in packages {
+                    counted.insert(package.id);
                     if cx.target.is_empty() || cargo_version.minor >= 64 {
                         progress.total += package.feature_count;
                     } else {
@@ -111,6 +204,17 @@ fn try_main() -> Result<()> {
                     }
                 }
             }
+            progress.total *= 1 + cx.also.len();
+
+            if cx.count {
+                print_count(progress.total, counted.len());
+                return Ok(());
+            }
+            check_total(cx, progress.total);
+            determine_start(cx, &mut progress, plan)?;
+            if cx.install_toolchains {
+                install_toolchains(cx, &versions)?;
+            }
 
             // First, generate the lockfile using the oldest cargo specified.
             // https://github.com/taiki-e/cargo-hack/issues/105
@@ -119,7 +223,7 @@ fn try_main() -> Result<()> {
             // (This does not work around the underlying cargo bug: https://github.com/rust-lang/cargo/issues/10623)
             let mut regenerate_lockfile_on_51_or_up = false;
             for (cargo_version, packages) in versions {
-                versioned_cargo_exec_on_packages(
+                let res = versioned_cargo_exec_on_packages(
                     cx,
                     &packages,
                     cargo_version.minor,
@@ -127,25 +231,262 @@ fn try_main() -> Result<()> {
                     &mut keep_going,
                     &mut generate_lockfile,
                     &mut regenerate_lockfile_on_51_or_up,
-                )?;
+                );
+                if let Err(e) = res {
+                    summary::write(cx, &keep_going.results)?;
+                    return Err(e);
+                }
             }
         } else {
-            let total = packages.iter().map(|p| p.feature_count).sum();
+            let total =
+                packages.iter().map(|p| p.feature_count).sum::<usize>() * (1 + cx.also.len());
             progress.total = total;
-            default_cargo_exec_on_packages(cx, &packages, &mut progress, &mut keep_going)?;
+            if cx.count {
+                print_count(total, packages.len());
+                return Ok(());
+            }
+            check_total(cx, total);
+            determine_start(cx, &mut progress, plan)?;
+            if let Err(e) =
+                default_cargo_exec_on_packages(cx, &packages, &mut progress, &mut keep_going)
+            {
+                // Also report the commands run before the failure.
+                summary::write(cx, &keep_going.results)?;
+                return Err(e);
+            }
         }
-        if keep_going.count > 0 {
-            eprintln!();
-            error!("{keep_going}");
+        if cx.gh_matrix {
+            println!("{}", serde_json::json!({ "include": progress.gh_matrix }));
+            return Ok(());
         }
-        Ok(())
+        finish(cx, &progress, &keep_going)
     })
 }
 
+/// Runs `cargo check --all-features` once on each package (--check-plan), so
+/// that errors that affect all feature combinations are reported before the
+/// full run.
+fn check_plan(cx: &Context, packages: &[PackageRuns<'_>]) -> Result<()> {
+    // The same command as the run, except that the subcommand is `check`, which
+    // does not accept arguments after `--`.
+    let mut line = cx.cargo();
+    line.apply_context(cx);
+    line.propagated_leading_args(&cx.check_plan_args);
+    line.trailing_args(&[]);
+    line.clippy_args(&[]);
+    line.arg("--message-format=json");
+    if cx.locked {
+        line.arg("--locked");
+    }
+    for target in &cx.target {
+        line.arg("--target");
+        line.arg(target);
+    }
+    for pkg in packages {
+        let package = cx.packages(pkg.id);
+        let mut line = line.clone();
+        line.append_features_from_args(cx, pkg.id);
+        if !cx.no_manifest_path {
+            line.arg("--manifest-path");
+            line.arg(
+                package
+                    .manifest_path
+                    .strip_prefix(&cx.current_dir)
+                    .unwrap_or(&package.manifest_path),
+            );
+        }
+        if exclude_all_features(cx, pkg.id) {
+            // Enable the features of the largest combination of the run instead,
+            // so that the excluded features are not enabled.
+            match &pkg.kind {
+                Kind::Normal => {}
+                Kind::Each { features } => {
+                    line.append_features(features.iter().flat_map(|f| f.as_group()));
+                }
+                Kind::Powerset { features } => {
+                    if let Some(largest) = features.iter().max_by_key(|f| f.len()) {
+                        line.append_features(largest.iter().flat_map(|f| f.as_group()));
+                    }
+                }
+            }
+        } else {
+            line.arg("--all-features");
+        }
+        info!("checking plan on {}", package.name);
+        let output = cx.runner.output(&mut line)?;
+        if !output.status.success() {
+            COMMAND_FAILED.store(true, Ordering::Relaxed);
+            // With --message-format=json, the errors from rustc are printed to
+            // stdout, and the errors from cargo itself to stderr.
+            let mut msg: String = str::from_utf8(&output.stdout)
+                .unwrap_or_default()
+                .lines()
+                .filter_map(|line| {
+                    let msg: serde_json::Value = serde_json::from_str(line).ok()?;
+                    if msg["reason"] != "compiler-message" || msg["message"]["level"] != "error" {
+                        return None;
+                    }
+                    msg["message"]["rendered"].as_str().map(str::to_owned)
+                })
+                .collect();
+            msg.push_str(&String::from_utf8_lossy(&output.stderr));
+            bail!(
+                "plan check failed on package `{}` ({}):\n{}",
+                package.name,
+                output.status,
+                msg.trim_end()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Reports the failures of the run, or removes the state of the run recorded
+/// for --resume if all commands have been completed.
+fn finish(cx: &Context, progress: &Progress, keep_going: &KeepGoing) -> Result<()> {
+    summary::write(cx, &keep_going.results)?;
+    for (command, output) in &keep_going.replays {
+        replay(command, output);
+    }
+    if !keep_going.allowed_failures.is_empty() {
+        eprintln!();
+        info!("{}", AllowedFailures(&keep_going.allowed_failures));
+    }
+    if keep_going.count > 0 && progress.stopped {
+        eprintln!();
+        info!("{keep_going}");
+    } else if keep_going.count > 0 {
+        eprintln!();
+        error!("{keep_going}");
+    } else if let Some(last_run) = &progress.last_run {
+        // All commands have been completed, so there is nothing to resume.
+        last_run.remove()?;
+    }
+    Ok(())
+}
+
+/// Prints the result of --count.
+fn print_count(total: usize, packages: usize) {
+    let s = |n: usize| if n == 1 { "" } else { "s" };
+    println!("{total} combination{} across {packages} package{}", s(total), s(packages));
+}
+
+/// Prints the toolchains to be used, and the packages run on each of them if
+/// not all packages are run on every toolchain (--print-toolchains).
+fn print_toolchains(cx: &Context, versions: &BTreeMap<Version, Vec<PackageRuns<'_>>>) {
+    let packages: HashSet<&PackageId> = versions.values().flatten().map(|pkg| pkg.id).collect();
+    let uniform = versions.values().all(|p| p.len() == packages.len());
+    for (version, packages) in versions {
+        if uniform {
+            println!("{version}");
+        } else {
+            let names: Vec<&str> =
+                packages.iter().map(|pkg| cx.packages(pkg.id).name.as_str()).collect();
+            println!("{version}: {}", names.join(", "));
+        }
+    }
+}
+
+/// Warns if the number of commands to be run exceeds --combination-warn-limit.
+fn check_total(cx: &Context, total: usize) {
+    let limit = cx.combination_warn_limit;
+    if limit != 0 && total > limit {
+        warn!(
+            "{total} commands will be run, which exceeds the limit of {limit} set by \
+             --combination-warn-limit; consider using --depth or --max-combinations-per-package \
+             to reduce the number of feature combinations"
+        );
+    }
+}
+
+/// Determines the number of commands to skip by --continue-from or --resume.
+fn determine_start(cx: &Context, progress: &mut Progress, plan: Option<Vec<String>>) -> Result<()> {
+    if let Some(index) = cx.continue_from {
+        if index > progress.total {
+            bail!(
+                "--continue-from {index} is greater than the total number of commands ({})",
+                progress.total
+            );
+        }
+        info!("resuming from command {index}/{}", progress.total);
+        progress.skip = index - 1;
+    }
+    if let Some(mut plan) = plan {
+        plan.push(format!("total: {}", progress.total));
+        let last_run = cx.last_run.as_ref().unwrap();
+        let mut completed = 0;
+        if last_run.plan == plan {
+            completed = last_run.completed;
+            info!(
+                "resuming from command {}/{} recorded in `{}`",
+                completed + 1,
+                progress.total,
+                last_run.path.display()
+            );
+        } else if !last_run.plan.is_empty() {
+            info!(
+                "the plan has changed since the last run recorded in `{}`; running all commands",
+                last_run.path.display()
+            );
+        }
+        progress.skip = completed;
+        progress.last_run = Some(LastRun { path: last_run.path.clone(), plan, completed });
+    }
+    Ok(())
+}
+
 #[derive(Default)]
 struct Progress {
     total: usize,
     count: usize,
+    /// The time the first command started, used for the progress bar.
+    start: Option<Instant>,
+    /// The number of commands to skip (--continue-from, --resume).
+    skip: usize,
+    /// The state of the run, recorded for --resume.
+    last_run: Option<LastRun>,
+    /// The file to write status events to (--status-file).
+    status_file: Option<File>,
+    /// Set when a feature combination succeeded (--stop-on-first-success).
+    stopped: bool,
+    /// The entries of the matrix printed by --gh-matrix.
+    gh_matrix: Vec<serde_json::Value>,
+}
+
+impl Progress {
+    /// Writes a `start` (if `success` is `None`) or `finish` event to --status-file.
+    fn write_status(
+        &self,
+        cx: &Context,
+        id: &PackageId,
+        features: &[String],
+        success: Option<bool>,
+    ) -> Result<()> {
+        let Some(file) = &self.status_file else { return Ok(()) };
+        let event = serde_json::json!({
+            "event": if success.is_some() { "finish" } else { "start" },
+            "package": cx.packages(id).name,
+            "features": features,
+            "index": self.count,
+            "total": self.total,
+            "status": success.map(|success| if success { "success" } else { "failure" }),
+        });
+        let mut file = file;
+        writeln!(file, "{event}").and_then(|()| file.flush()).with_context(|| {
+            format!("failed to write to {}", cx.status_file.as_ref().unwrap().display())
+        })
+    }
+
+    /// Records that the current command has been completed successfully.
+    fn complete(&mut self) -> Result<()> {
+        if let Some(last_run) = &mut self.last_run {
+            if self.count == last_run.completed + 1 {
+                last_run.completed = self.count;
+                last_run.write()?;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone)]
@@ -155,18 +496,64 @@ enum Kind<'a> {
     Powerset { features: Vec<Vec<&'a Feature>> },
 }
 
+/// Returns features whose `feature-cfg` does not match the target (--exclude-features-matching-cfg).
+fn cfg_excluded_features<'a>(cx: &'a Context, id: &PackageId) -> BTreeSet<&'a str> {
+    if !cx.exclude_features_matching_cfg {
+        return BTreeSet::new();
+    }
+    cx.manifests(id)
+        .config
+        .feature_cfg
+        .iter()
+        .filter(|(_, cfg)| !cx.target_cfgs.iter().all(|target| cfg.matches(target)))
+        .map(|(f, _)| f.as_str())
+        .collect()
+}
+
+/// Returns `true` if the run with just --all-features flag is excluded for the package.
+fn exclude_all_features(cx: &Context, id: &PackageId) -> bool {
+    cx.exclude_all_features
+        || !cx.manifests(id).config.exclude_features.is_empty()
+        || cx.workspace_config.exclude_features.iter().any(|f| cx.pkg_features(id).contains(f))
+        || !cfg_excluded_features(cx, id).is_empty()
+}
+
+/// Returns the phases of the runs performed on the package, in order.
+fn phases(cx: &Context, id: &PackageId) -> Vec<Phase> {
+    let mut phases = cx.order.clone();
+    // --always-include-default-run: without `default` feature, no combination
+    // corresponds to the run with default features.
+    if cx.always_include_default_run
+        && !phases.contains(&Phase::Default)
+        && !cx.packages(id).features.contains_key("default")
+    {
+        phases.insert(0, Phase::Default);
+    }
+    // `--exclude-features default` also excludes the run with default features.
+    if cx.exclude_features.iter().any(|f| f == "default")
+        || cx.manifests(id).config.exclude_features.iter().any(|f| f == "default")
+        || cx.workspace_config.exclude_features.iter().any(|f| f == "default")
+    {
+        phases.retain(|&p| p != Phase::Default);
+    }
+    phases
+}
+
 #[allow(clippy::redundant_closure_for_method_calls)]
 fn determine_kind<'a>(
     cx: &'a Context,
     id: &'a PackageId,
     multiple_packages: bool,
 ) -> Option<PackageRuns<'a>> {
-    assert!(cx.subcommand.is_some());
-    if cx.ignore_private && cx.is_private(id) {
-        info!("skipped running on private package `{}`", cx.name_verbose(id));
+    assert!(cx.subcommand.is_some() || cx.exec.is_some() || cx.gh_matrix);
+    if !cx.packages(id).has_lib_or_bin() && skip_targetless(cx) {
+        info!(
+            "skipped running on package `{}` that has no library or binary targets",
+            cx.name_verbose(id)
+        );
         return None;
     }
-    if !cx.each_feature && !cx.feature_powerset {
+    if cx.default_only || !cx.each_feature && !cx.feature_powerset {
         let feature_count = 1;
         let kind = Kind::Normal;
         return Some(PackageRuns { id, kind, feature_count });
@@ -174,20 +561,73 @@ fn determine_kind<'a>(
 
     let package = cx.packages(id);
     let pkg_features = cx.pkg_features(id);
-    let filter = |&f: &&Feature| {
-        !cx.exclude_features.iter().any(|s| f == s)
-            && !cx.group_features.iter().any(|g| g.matches(f.name()))
+    // `[package.metadata.cargo-hack]` and `[workspace.metadata.cargo-hack]` are
+    // merged with the values from the command line.
+    let config = &cx.manifests(id).config;
+    let workspace_config = &cx.workspace_config;
+    let cfg_excluded = cfg_excluded_features(cx, id);
+    let exclude_all_features = exclude_all_features(cx, id);
+    let phases = phases(cx, id);
+    let extra_runs = usize::from(phases.contains(&Phase::Default) && !cx.no_default_features)
+        + usize::from(phases.contains(&Phase::NoDefault) && !cx.exclude_no_default_features)
+        + usize::from(
+            phases.contains(&Phase::All)
+                && !exclude_all_features
+                && pkg_features.normal().len() + pkg_features.optional_deps().len() > 1,
+        );
+    // The number of runs, including the runs other than the feature combinations.
+    let count_runs = |combinations: usize| {
+        usize::from(phases.contains(&Phase::Features)) * combinations + extra_runs
     };
-    let features = if cx.include_features.is_empty() {
-        // TODO
-        if !multiple_packages {
-            for name in &cx.exclude_features {
-                if !pkg_features.contains(name) {
-                    warn!("specified feature `{name}` not found in package `{}`", package.name);
-                }
+    if let Some(combinations) = cx.matrix.get(&package.name) {
+        // --matrix-file: use the specified combinations instead of the generated ones.
+        // Feature names have already been validated when loading the file.
+        let features: Vec<Vec<_>> = combinations
+            .iter()
+            .map(|c| c.iter().map(|f| pkg_features.get(f).unwrap()).collect())
+            .collect();
+        let feature_count = count_runs(features.len());
+        let kind = Kind::Powerset { features };
+        return Some(PackageRuns { id, kind, feature_count });
+    }
+    // Features enabled by the `default` feature (--exclude-default-member-features).
+    let default_members = if cx.exclude_default_member_features {
+        features::feature_deps(&package.features).remove("default").unwrap_or_default()
+    } else {
+        BTreeSet::new()
+    };
+    let excluded_default_members = Cell::new(0_usize);
+    let filter = |&f: &&Feature| {
+        let reason = if default_members.contains(f.name()) {
+            // Optional dependencies enabled by `default` are excluded but not
+            // counted as features.
+            if !pkg_features.optional_deps().contains(f) {
+                excluded_default_members.set(excluded_default_members.get() + 1);
+            }
+            Some("enabled by the default feature (--exclude-default-member-features)")
+        } else if cfg_excluded.contains(f.name()) {
+            Some("its cfg does not match the target (--exclude-features-matching-cfg)")
+        } else if cx.features.iter().any(|s| f == s) {
+            Some("always enabled by --features")
+        } else if cx.exclude_features.iter().any(|s| f == s) {
+            Some("excluded by --exclude-features")
+        } else if config.exclude_features.iter().any(|s| f == s) {
+            Some("excluded by `exclude-features` in `[package.metadata.cargo-hack]`")
+        } else if workspace_config.exclude_features.iter().any(|s| f == s) {
+            Some("excluded by `exclude-features` in `[workspace.metadata.cargo-hack]`")
+        } else if cx.group_features.iter().any(|g| g.matches(f.name())) {
+            Some("member of a group specified by --group-features")
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            if cx.explain_plan {
+                info!("dropped feature `{}` of package `{}`: {reason}", f.name(), package.name);
             }
         }
-
+        reason.is_none()
+    };
+    let features = if cx.include_features.is_empty() {
         let mut features: Vec<_> = pkg_features.normal().iter().filter(filter).collect();
 
         if let Some(opt_deps) = &cx.optional_deps {
@@ -206,12 +646,44 @@ fn determine_kind<'a>(
             }
 
             features.extend(pkg_features.optional_deps().iter().filter(|f| {
-                filter(f) && (opt_deps.is_empty() || opt_deps.iter().any(|x| *f == x))
+                if !filter(f) {
+                    return false;
+                }
+                if !opt_deps.is_empty() && !opt_deps.iter().any(|x| *f == x) {
+                    if cx.explain_plan {
+                        info!(
+                            "dropped optional dependency `{}` of package `{}`: not specified by \
+                             --optional-deps",
+                            f.name(),
+                            package.name
+                        );
+                    }
+                    return false;
+                }
+                true
             }));
         }
 
         if cx.include_deps_features {
-            features.extend(pkg_features.deps_features().iter().filter(filter));
+            features.extend(pkg_features.deps_features().iter().filter(filter).filter(|f| {
+                let excluded = pkg_features.enabled_by_deps_feature(f).iter().find(|&d| {
+                    cx.exclude_features.contains(d)
+                        || config.exclude_features.contains(d)
+                        || workspace_config.exclude_features.contains(d)
+                });
+                if let Some(excluded) = excluded {
+                    if cx.explain_plan {
+                        info!(
+                            "dropped feature `{}` of package `{}`: enables excluded feature \
+                             `{excluded}`",
+                            f.name(),
+                            package.name
+                        );
+                    }
+                    return false;
+                }
+                true
+            }));
         }
 
         if !cx.group_features.is_empty() {
@@ -242,10 +714,61 @@ fn determine_kind<'a>(
 
         features
     } else {
+        if cx.explain_plan {
+            for f in pkg_features.normal() {
+                if !cx.include_features.contains(f) {
+                    let reason = if cx.features.iter().any(|s| f == s) {
+                        "always enabled by --features"
+                    } else {
+                        "not specified by --include-features"
+                    };
+                    info!("dropped feature `{}` of package `{}`: {reason}", f.name(), package.name);
+                }
+            }
+        }
         cx.include_features.iter().filter(filter).collect()
     };
+    if cx.explain_plan {
+        for f in &features {
+            info!("kept feature `{}` of package `{}`", f.name(), package.name);
+        }
+    }
+    let excluded = excluded_default_members.get();
+    if excluded > 0 {
+        let s = if excluded == 1 { "" } else { "s" };
+        warn!(
+            "excluded {excluded} feature{s} enabled by the default feature of package `{}`",
+            package.name
+        );
+    }
+
+    // The `default` feature to always run with (--include-features-from-default).
+    let default_feature = if cx.include_features_from_default {
+        pkg_features.normal().iter().find(|f| f.name() == "default")
+    } else {
+        None
+    };
+
+    // --max-combinations-per-package
+    let cap = |combinations: usize| match cx.max_combinations_per_package {
+        Some(max) if combinations > max => {
+            info!(
+                "capped feature combinations of package `{}` from {combinations} to {max}",
+                package.name
+            );
+            max
+        }
+        _ => combinations,
+    };
 
     if cx.each_feature {
+        let max = cap(features.len());
+        let mut features = features::sample(features, max);
+        if let Some(default_feature) = default_feature {
+            if !features.contains(&default_feature) {
+                features.insert(0, default_feature);
+            }
+        }
         if (pkg_features.normal().is_empty() && pkg_features.optional_deps().is_empty()
             || !cx.include_features.is_empty())
             && features.is_empty()
@@ -254,23 +777,54 @@ fn determine_kind<'a>(
             let kind = Kind::Normal;
             Some(PackageRuns { id, kind, feature_count })
         } else {
-            let feature_count = features.len()
-                + usize::from(!cx.exclude_no_default_features)
-                + usize::from(
-                    !cx.exclude_all_features
-                        && pkg_features.normal().len() + pkg_features.optional_deps().len() > 1,
-                );
+            let feature_count = count_runs(features.len());
             let kind = Kind::Each { features };
             Some(PackageRuns { id, kind, feature_count })
         }
     } else if cx.feature_powerset {
-        let features = features::feature_powerset(
+        let mut mutually_exclusive_features = cx.mutually_exclusive_features.clone();
+        mutually_exclusive_features.extend(config.mutually_exclusive_features.iter().cloned());
+        if cx.explain_plan {
+            for group in &mutually_exclusive_features {
+                info!(
+                    "skipping combinations of package `{}` with more than one of `{}`: \
+                     mutually exclusive",
+                    package.name,
+                    group.as_group().join(",")
+                );
+            }
+            for group in &cx.at_least_one_of {
+                info!(
+                    "skipping combinations of package `{}` with none of `{}`: --at-least-one-of",
+                    package.name,
+                    group.as_group().join(",")
+                );
+            }
+        }
+        let feature_len = features.len();
+        let depth = cx.depth.and_then(|depth| depth.resolve(feature_len));
+        let mut features = features::feature_powerset(
             features,
-            cx.depth,
+            depth,
             &cx.at_least_one_of,
-            &cx.mutually_exclusive_features,
+            &mutually_exclusive_features,
             &package.features,
         );
+        if term::verbose() {
+            info!(
+                "package {}: {feature_len} features, {} combinations (depth={})",
+                package.name,
+                features.len(),
+                depth.map_or_else(|| "max".to_owned(), |d| d.to_string())
+            );
+        }
+        let max = cap(features.len());
+        features = features::sample(features, max);
+        if let Some(default_feature) = default_feature {
+            if !features.iter().any(|f| *f == [default_feature]) {
+                features.insert(0, vec![default_feature]);
+            }
+        }
 
         if (pkg_features.normal().is_empty() && pkg_features.optional_deps().is_empty()
             || !cx.include_features.is_empty())
@@ -280,12 +834,7 @@ fn determine_kind<'a>(
             let kind = Kind::Normal;
             Some(PackageRuns { id, kind, feature_count })
         } else {
-            let feature_count = features.len()
-                + usize::from(!cx.exclude_no_default_features)
-                + usize::from(
-                    !cx.exclude_all_features
-                        && pkg_features.normal().len() + pkg_features.optional_deps().len() > 1,
-                );
+            let feature_count = count_runs(features.len());
             let kind = Kind::Powerset { features };
             Some(PackageRuns { id, kind, feature_count })
         }
@@ -294,6 +843,134 @@ fn determine_kind<'a>(
     }
 }
 
+/// Returns `true` if packages without library or binary targets should be
+/// skipped because the subcommand has nothing to do for them.
+fn skip_targetless(cx: &Context) -> bool {
+    if cx.no_skip_targetless
+        || !matches!(cx.subcommand.as_deref(), Some("check" | "build" | "clippy" | "doc"))
+    {
+        return false;
+    }
+    !cx.leading_args.iter().any(|a| match &**a {
+        "--example" | "--examples" | "--test" | "--tests" | "--bench" | "--benches"
+        | "--all-targets" => true,
+        _ => a.starts_with("--example=") || a.starts_with("--test=") || a.starts_with("--bench="),
+    })
+}
+
+/// Returns the features of the given package enabled by `line`, including the
+/// features enabled by other features, or `None` if --all-features is used.
+fn enabled_features<'a>(
+    cx: &'a Context,
+    id: &PackageId,
+    line: &'a ProcessBuilder<'_>,
+) -> Option<BTreeSet<&'a str>> {
+    // --all-features and --no-default-features specified by the user are
+    // propagated to cargo as a part of `leading_args`.
+    if line.has_arg("--all-features") || cx.leading_args.iter().any(|a| a == "--all-features") {
+        return None;
+    }
+    let mut enabled: BTreeSet<&str> = line.features().collect();
+    if !line.has_arg("--no-default-features") && !cx.no_default_features {
+        enabled.insert("default");
+    }
+    let feature_deps = features::feature_deps(&cx.packages(id).features);
+    for f in enabled.clone() {
+        if let Some(deps) = feature_deps.get(f) {
+            enabled.extend(deps);
+        }
+    }
+    Some(enabled)
+}
+
+/// Returns `CARGO_ENCODED_RUSTFLAGS` with the flags specified by
+/// `feature-rustflags` in `[package.metadata.cargo-hack]` for the features
+/// enabled by `line` and `-Dwarnings` (--deny-warnings) appended, or `None` if
+/// there is nothing to append.
+fn rustflags(cx: &Context, id: &PackageId, line: &ProcessBuilder<'_>) -> Option<String> {
+    let feature_rustflags = &cx.manifests(id).config.feature_rustflags;
+    if feature_rustflags.is_empty() && !cx.deny_warnings {
+        return None;
+    }
+    // This is always set if --deny-warnings is specified or any package has
+    // `feature-rustflags`.
+    let mut flags = cx.rustflags.clone().unwrap();
+    let mut push = |flag: &str| {
+        if !flags.is_empty() {
+            flags.push('\x1f');
+        }
+        flags.push_str(flag);
+    };
+    let mut appended = false;
+    if !feature_rustflags.is_empty() {
+        let enabled = enabled_features(cx, id, line);
+        for (feature, rustflags) in feature_rustflags {
+            if enabled.as_ref().is_some_and(|enabled| !enabled.contains(&**feature)) {
+                continue;
+            }
+            rustflags.split_whitespace().for_each(&mut push);
+            appended = true;
+        }
+    }
+    // Appended last so that it is not overridden by `-A warnings` etc. in
+    // `feature-rustflags`, while lints allowed individually stay allowed.
+    if cx.deny_warnings {
+        push("-Dwarnings");
+        appended = true;
+    }
+    appended.then_some(flags)
+}
+
+/// Returns the name of the first target explicitly selected by the target
+/// selection flags (e.g., `--example foo`) whose `required-features` are not
+/// enabled by `line`, and the features that are not enabled.
+///
+/// Cargo silently skips such targets when they are selected by flags like
+/// --examples, but fails when they are selected by name.
+fn missing_required_features<'a>(
+    cx: &'a Context,
+    id: &PackageId,
+    line: &ProcessBuilder<'_>,
+) -> Option<(&'a str, Vec<&'a str>)> {
+    let mut selected = vec![];
+    let mut args = cx.leading_args.iter().peekable();
+    while let Some(arg) = args.next() {
+        for kind in ["bin", "example", "test", "bench"] {
+            let Some(rest) = arg.strip_prefix("--").and_then(|a| a.strip_prefix(kind)) else {
+                continue;
+            };
+            if let Some(name) = rest.strip_prefix('=') {
+                selected.push((kind, name));
+            } else if rest.is_empty() {
+                if let Some(name) = args.peek().filter(|a| !a.starts_with('-')) {
+                    selected.push((kind, name));
+                }
+            }
+        }
+    }
+    if selected.is_empty() {
+        return None;
+    }
+
+    let enabled = enabled_features(cx, id, line)?;
+    cx.packages(id)
+        .targets
+        .iter()
+        .filter(|t| {
+            selected.iter().any(|&(kind, name)| t.name == name && t.kind.iter().any(|k| k == kind))
+        })
+        .find_map(|t| {
+            let missing: Vec<_> = t
+                .required_features
+                .iter()
+                .map(String::as_str)
+                // `dep/feature` is enabled by the dependency's features, which are not tracked here.
+                .filter(|f| !f.contains('/') && !enabled.contains(f))
+                .collect();
+            (!missing.is_empty()).then_some((&*t.name, missing))
+        })
+}
+
 #[derive(Clone)]
 struct PackageRuns<'a> {
     id: &'a PackageId,
@@ -302,9 +979,297 @@ struct PackageRuns<'a> {
 }
 
 fn determine_package_list(cx: &Context) -> Result<Vec<PackageRuns<'_>>> {
+    let (mut ids, multiple_packages) = selected_packages(cx)?;
+    // Skip private packages before anything else looks at their features, so
+    // that no warnings are emitted about the packages that are not run.
+    if cx.ignore_private {
+        ids.retain(|id| {
+            let private = cx.is_ignored_private(id);
+            if private {
+                info!("skipped running on private package `{}`", cx.name_verbose(id));
+            }
+            !private
+        });
+    }
+    if let Some(changed) = &cx.changed_packages {
+        let git_ref = cx.changed_since.as_deref().unwrap();
+        let selected = if cx.include_dependents {
+            changed::with_dependents(&cx.metadata, changed)
+        } else {
+            changed.iter().collect()
+        };
+        ids.retain(|id| {
+            let selected = selected.contains(id);
+            if !selected {
+                if cx.include_dependents {
+                    info!(
+                        "skipped running on package `{}` that neither changed since `{git_ref}` \
+                         nor depends on a changed package",
+                        cx.name_verbose(id)
+                    );
+                } else {
+                    info!(
+                        "skipped running on package `{}` not changed since `{git_ref}`",
+                        cx.name_verbose(id)
+                    );
+                }
+            }
+            selected
+        });
+    }
+    filter_packages_with_feature(cx, &mut ids);
+    let packages: Vec<_> =
+        ids.into_iter().filter_map(|id| determine_kind(cx, id, multiple_packages)).collect();
+    check_excluded_features(cx, &packages);
+    check_unknown_features(cx, &packages);
+    if cx.frozen_features {
+        check_frozen_features(cx, &packages)?;
+    }
+    if cx.no_dev_deps {
+        check_dev_targets(cx, &packages)?;
+    }
+    Ok(packages)
+}
+
+/// Warns about the features specified by --exclude-features that are not found
+/// in any of the selected packages.
+///
+/// This is done once after all packages are processed, so that a feature that
+/// exists only in some members of a large workspace does not cause a warning
+/// for each of the other members.
+fn check_excluded_features(cx: &Context, packages: &[PackageRuns<'_>]) {
+    if cx.default_only
+        || !cx.each_feature && !cx.feature_powerset
+        || !cx.include_features.is_empty()
+        || packages.is_empty()
+    {
+        return;
+    }
+    for name in &cx.exclude_features {
+        // `default` also excludes the run with default features, so it is
+        // meaningful even if the package has no `default` feature.
+        if name == "default" || packages.iter().any(|pkg| cx.pkg_features(pkg.id).contains(name)) {
+            continue;
+        }
+        if let [pkg] = packages {
+            warn!("specified feature `{name}` not found in package `{}`", cx.packages(pkg.id).name);
+        } else {
+            warn!("specified feature `{name}` not found in any of the selected packages");
+        }
+    }
+}
+
+/// Reports the features specified by --features that are ignored by
+/// --ignore-unknown-features because they are not found in the package.
+///
+/// The features dropped from each package are listed with --verbose, and a
+/// warning is emitted if none of them is found in any of the selected packages,
+/// which usually means that they are misspelled.
+fn check_unknown_features(cx: &Context, packages: &[PackageRuns<'_>]) {
+    if !cx.ignore_unknown_features || cx.features.is_empty() || packages.is_empty() {
+        return;
+    }
+    let mut all_dropped = true;
+    for pkg in packages {
+        let dropped: Vec<&str> = cx
+            .features
+            .iter()
+            .filter(|f| !cx.pkg_features(pkg.id).contains(f))
+            .map(String::as_str)
+            .collect();
+        all_dropped &= dropped.len() == cx.features.len();
+        if term::verbose() && !dropped.is_empty() {
+            info!(
+                "package {}: dropped unknown feature(s) `{}` specified by --features",
+                cx.packages(pkg.id).name,
+                dropped.join(",")
+            );
+        }
+    }
+    if all_dropped {
+        warn!(
+            "none of the features specified by --features (`{}`) were found in the selected \
+             packages, so all of them were ignored by --ignore-unknown-features",
+            cx.features.join(",")
+        );
+    }
+}
+
+/// Removes packages that do not have the feature specified by --only-packages-with-feature.
+fn filter_packages_with_feature(cx: &Context, ids: &mut Vec<&PackageId>) {
+    if let Some(feature) = &cx.only_packages_with_feature {
+        if ids.is_empty() {
+            // e.g., all selected packages are skipped by --ignore-private
+            return;
+        }
+        ids.retain(|id| {
+            let has_feature = cx.pkg_features(id).contains(feature);
+            if !has_feature {
+                info!(
+                    "skipped running on package `{}` that does not have feature `{feature}`",
+                    cx.name_verbose(id)
+                );
+            }
+            has_feature
+        });
+        if ids.is_empty() {
+            warn!("no package with feature `{feature}` found");
+        }
+    }
+}
+
+/// Checks that the targets selected by flags such as `--examples` do not
+/// require dev-dependencies removed by --no-dev-deps.
+fn check_dev_targets(cx: &Context, packages: &[PackageRuns<'_>]) -> Result<()> {
+    for pkg in packages {
+        let package = cx.packages(pkg.id);
+        if !package.has_dev_deps() {
+            continue;
+        }
+        for (flag, name) in &cx.dev_targets {
+            let kind = match &**flag {
+                "--example" | "--examples" => "example",
+                "--test" => "test",
+                "--bench" => "bench",
+                // These also build unit tests of the library and binaries.
+                _ => {
+                    dev_targets_error(
+                        cx,
+                        &format!(
+                            "--no-dev-deps may not be used together with {flag} because package \
+                             `{}` has dev-dependencies",
+                            package.name
+                        ),
+                    )?;
+                    continue;
+                }
+            };
+            let selected = package.targets.iter().any(|t| {
+                t.kind.iter().any(|k| k == kind) && name.as_ref().map_or(true, |n| t.name == *n)
+            });
+            if selected {
+                let flag = match name {
+                    Some(name) => format!("{flag} {name}"),
+                    None => flag.clone(),
+                };
+                dev_targets_error(
+                    cx,
+                    &format!(
+                        "--no-dev-deps may not be used together with {flag} because package `{}` \
+                         has dev-dependencies that {kind} targets may require",
+                        package.name
+                    ),
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reports an error of `check_dev_targets`, or only warns if --force is used.
+fn dev_targets_error(cx: &Context, msg: &str) -> Result<()> {
+    if cx.force {
+        warn!("{msg}; running anyway due to --force");
+        Ok(())
+    } else {
+        bail!("{msg}\n\nhelp: use --force to run anyway if the targets do not use dev-dependencies")
+    }
+}
+
+/// Checks that no feature combination activates an optional dependency that is
+/// not activated when the workspace is built without the feature combinations
+/// (--frozen-features).
+fn check_frozen_features(cx: &Context, packages: &[PackageRuns<'_>]) -> Result<()> {
+    fn group(f: &Feature) -> Vec<&str> {
+        f.as_group().iter().map(String::as_str).collect()
+    }
+
+    // The packages activated when the workspace is built without the feature
+    // combinations of cargo-hack.
+    let mut line = cx.cargo();
+    line.args(["metadata", "--format-version=1", "--manifest-path"]);
+    line.arg(cx.workspace_root().join("Cargo.toml"));
+    if cx.no_default_features {
+        line.arg("--no-default-features");
+    }
+    if cx.locked {
+        line.arg("--locked");
+    }
+    for target in &cx.target {
+        line.arg("--filter-platform");
+        line.arg(target);
+    }
+    let resolved = metadata::resolved_packages(&mut line, cx.cargo_version)?;
+    for pkg in packages {
+        let package = cx.packages(pkg.id);
+        let mut combinations = match &pkg.kind {
+            Kind::Normal => vec![if cx.no_default_features { vec![] } else { vec!["default"] }],
+            Kind::Each { features } => features.iter().map(|&f| group(f)).collect(),
+            Kind::Powerset { features } => {
+                features.iter().map(|f| f.iter().flat_map(|&f| group(f)).collect()).collect()
+            }
+        };
+        let phases = phases(cx, pkg.id);
+        if !matches!(pkg.kind, Kind::Normal) {
+            if !phases.contains(&Phase::Features) {
+                combinations.clear();
+            }
+            if phases.contains(&Phase::Default) && !cx.no_default_features {
+                combinations.push(vec!["default"]);
+            }
+        }
+        let pkg_features = cx.pkg_features(pkg.id);
+        if !matches!(pkg.kind, Kind::Normal)
+            && phases.contains(&Phase::All)
+            && !exclude_all_features(cx, pkg.id)
+            && pkg_features.normal().len() + pkg_features.optional_deps().len() > 1
+        {
+            combinations.push(
+                package
+                    .features
+                    .keys()
+                    .map(String::as_str)
+                    .chain(package.optional_deps())
+                    .collect(),
+            );
+        }
+        // The optional dependencies activated by --features are explicitly requested.
+        let requested = package.activated_optional_deps(cx.features.iter().map(String::as_str));
+        for mut features in combinations {
+            features.extend(cx.features.iter().map(String::as_str));
+            let activated = package.activated_optional_deps(features.iter().copied());
+            if let Some(dep) =
+                activated.iter().find(|&&dep| !resolved.contains(dep) && !requested.contains(dep))
+            {
+                bail!(
+                    "features `{}` of package `{}` activate optional dependency `{dep}` that is \
+                     not activated without them",
+                    features.join(","),
+                    package.name
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the packages selected by --workspace, --package, --exclude, or the
+/// current directory, and whether multiple packages can be selected.
+fn selected_packages(cx: &Context) -> Result<(Vec<&PackageId>, bool)> {
+    let matches = |spec: &str, id: &PackageId| PackageSpec::parse(spec).matches(cx.packages(id));
+    let selected = |id: &PackageId| cx.package.iter().any(|spec| matches(spec, id));
+    if let Some(spec) =
+        cx.package.iter().find(|&spec| !cx.workspace_members().any(|id| matches(spec, id)))
+    {
+        if glob::is_glob(spec) {
+            bail!("package pattern `{spec}` matched no packages")
+        }
+        bail!("package ID specification `{spec}` matched no packages")
+    }
+
     Ok(if cx.workspace {
         for spec in &cx.exclude {
-            if !cx.workspace_members().any(|id| cx.packages(id).name == *spec) {
+            if !cx.workspace_members().any(|id| matches(spec, id)) {
                 warn!(
                     "excluded package(s) `{spec}` not found in workspace `{}`",
                     cx.workspace_root().display()
@@ -312,38 +1277,111 @@ fn determine_package_list(cx: &Context) -> Result<Vec<PackageRuns<'_>>> {
             }
         }
 
-        let multiple_packages = cx.workspace_members().len().saturating_sub(cx.exclude.len()) > 1;
-        cx.workspace_members()
-            .filter(|id| !cx.exclude.contains(&cx.packages(id).name))
-            .filter_map(|id| determine_kind(cx, id, multiple_packages))
-            .collect()
+        let current = cx.current_package().filter(|_| cx.exclude_current);
+        // --package narrows the set of packages selected by --workspace.
+        let ids: Vec<_> = cx
+            .workspace_members()
+            .filter(|id| {
+                !cx.exclude.iter().any(|spec| matches(spec, id))
+                    && Some(*id) != current
+                    && (cx.package.is_empty() || selected(id))
+            })
+            .collect();
+        let multiple_packages = ids.len() > 1;
+        (ids, multiple_packages)
     } else if !cx.package.is_empty() {
-        if let Some(spec) = cx
-            .package
-            .iter()
-            .find(|&spec| !cx.workspace_members().any(|id| cx.packages(id).name == *spec))
-        {
-            bail!("package ID specification `{spec}` matched no packages")
-        }
-
-        let multiple_packages = cx.package.len() > 1;
-        cx.workspace_members()
-            .filter(|id| cx.package.contains(&cx.packages(id).name))
-            .filter_map(|id| determine_kind(cx, id, multiple_packages))
-            .collect()
+        let ids: Vec<_> = cx.workspace_members().filter(|id| selected(id)).collect();
+        let multiple_packages = cx.package.len() > 1 || ids.len() > 1;
+        (ids, multiple_packages)
     } else if cx.current_package().is_none() {
-        let multiple_packages = cx.workspace_members().len() > 1;
-        cx.workspace_members().filter_map(|id| determine_kind(cx, id, multiple_packages)).collect()
+        // Like cargo, use `workspace.default-members`, which defaults to all
+        // members in a virtual workspace.
+        let ids: Vec<_> = match cx.workspace_default_members() {
+            Some(default_members) => {
+                cx.workspace_members().filter(|id| default_members.contains(id)).collect()
+            }
+            None => cx.workspace_members().collect(),
+        };
+        let multiple_packages = ids.len() > 1;
+        (ids, multiple_packages)
     } else {
         let current_package = &cx.packages(cx.current_package().unwrap()).name;
         let multiple_packages = false;
-        cx.workspace_members()
-            .find(|id| cx.packages(id).name == *current_package)
-            .and_then(|id| determine_kind(cx, id, multiple_packages).map(|p| vec![p]))
-            .unwrap_or_default()
+        let ids =
+            cx.workspace_members().find(|id| cx.packages(id).name == *current_package).into_iter();
+        (ids.collect(), multiple_packages)
     })
 }
 
+/// Prints the selected packages (--print-package-list).
+fn print_package_list(cx: &Context) -> Result<()> {
+    let (mut ids, _) = selected_packages(cx)?;
+    filter_packages_with_feature(cx, &mut ids);
+    let mut list: Vec<_> = ids
+        .into_iter()
+        .map(|id| {
+            let name = cx.name_verbose(id);
+            if cx.is_ignored_private(id) {
+                format!("{name} (private, skipped)")
+            } else {
+                name.into_owned()
+            }
+        })
+        .collect();
+    list.sort();
+    let mut out = String::new();
+    for line in list {
+        let _ = writeln!(out, "{line}");
+    }
+    print!("{out}");
+    Ok(())
+}
+
+/// Prints the features discovered for each selected package (--print-features).
+fn print_features(cx: &Context) -> Result<()> {
+    let (ids, _) = selected_packages(cx)?;
+    let mut out = String::new();
+    for id in ids {
+        let features = cx.pkg_features(id);
+        let _ = writeln!(out, "{}:", cx.packages(id).name);
+        for (kind, list) in [
+            ("features", features.normal()),
+            ("optional deps", features.optional_deps()),
+            ("deps features", features.deps_features()),
+        ] {
+            let list = if list.is_empty() {
+                "(none)".to_owned()
+            } else {
+                list.iter().map(Feature::name).collect::<Vec<_>>().join(", ")
+            };
+            let _ = writeln!(out, "  {kind}: {list}");
+        }
+    }
+    print!("{out}");
+    Ok(())
+}
+
+/// Installs the missing toolchains before running any command (--install-toolchains).
+fn install_toolchains(
+    cx: &Context,
+    versions: &BTreeMap<Version, Vec<PackageRuns<'_>>>,
+) -> Result<()> {
+    for version in versions.keys() {
+        let toolchain = format!("1.{}", version.minor);
+        let installed = rustup::is_installed(&toolchain);
+        // If targets are specified, they may need to be added to the installed toolchain.
+        if installed && cx.target.is_empty() {
+            continue;
+        }
+        if !installed {
+            info!("installing toolchain {toolchain}");
+        }
+        let print_output = true;
+        rustup::install_toolchain(&toolchain, &cx.target, print_output, cx.log_group)?;
+    }
+    Ok(())
+}
+
 fn versioned_cargo_exec_on_packages(
     cx: &Context,
     packages: &[PackageRuns<'_>],
@@ -358,8 +1396,10 @@ fn versioned_cargo_exec_on_packages(
     line.leading_arg("run");
 
     let toolchain = format!("1.{cargo_version}");
-    let print_output = true;
-    rustup::install_toolchain(&toolchain, &cx.target, print_output, cx.log_group)?;
+    if !cx.install_toolchains {
+        let print_output = true;
+        rustup::install_toolchain(&toolchain, &cx.target, print_output, cx.log_group)?;
+    }
     if *generate_lockfile || *regenerate_lockfile_on_51_or_up && cargo_version >= 51 {
         let mut line = line.clone();
         line.leading_arg(&toolchain);
@@ -424,21 +1464,95 @@ fn exec_on_packages(
             line.arg("--target");
             line.arg(target);
         }
-        packages
-            .iter()
-            .try_for_each(|pkg| exec_on_package(cx, pkg.id, &pkg.kind, &line, progress, keep_going))
+        exec_on_each_package(cx, packages, &line, progress, keep_going)
     } else {
         cx.target.iter().try_for_each(|target| {
             let mut line = line.clone();
             line.arg("--target");
             line.arg(target);
-            packages.iter().try_for_each(|pkg| {
-                exec_on_package(cx, pkg.id, &pkg.kind, &line, progress, keep_going)
-            })
+            exec_on_each_package(cx, packages, &line, progress, keep_going)
         })
     }
 }
 
+/// Runs on each package in order, or on up to --package-jobs packages in parallel.
+fn exec_on_each_package(
+    cx: &Context,
+    packages: &[PackageRuns<'_>],
+    line: &ProcessBuilder<'_>,
+    progress: &mut Progress,
+    keep_going: &mut KeepGoing,
+) -> Result<()> {
+    let jobs = cx.package_jobs.min(packages.len());
+    if jobs <= 1 || cx.print_command_list || cx.gh_matrix {
+        return packages.iter().try_for_each(|pkg| {
+            exec_on_package(cx, pkg.id, &pkg.kind, line, progress, keep_going)
+        });
+    }
+
+    // The index of the first command of each package, so that the commands have
+    // the same indices as when the packages are run one at a time.
+    let mut offsets = Vec::with_capacity(packages.len());
+    let mut end = progress.count;
+    for pkg in packages {
+        offsets.push(end);
+        end += pkg.feature_count * (1 + cx.also.len());
+    }
+    let (total, skip, start) = (progress.total, progress.skip, progress.start);
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let results: Vec<(KeepGoing, Result<()>)> = thread::scope(|s| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|job| {
+                let (offsets, next, failed) = (&offsets, &next, &failed);
+                s.spawn(move || {
+                    let mut line = line.clone();
+                    if job != 0 && !cx.target_dir_per_combination {
+                        // Otherwise, the jobs wait for the lock of the same build directory.
+                        let dir = cx.metadata.target_directory.join("hack-package-jobs");
+                        line.env("CARGO_TARGET_DIR", dir.join(job.to_string()));
+                    }
+                    let mut keep_going = KeepGoing::default();
+                    let mut res = Ok(());
+                    while res.is_ok() && !failed.load(Ordering::Relaxed) {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(pkg) = packages.get(i) else { break };
+                        let mut progress = Progress {
+                            total,
+                            count: offsets[i],
+                            skip,
+                            start,
+                            ..Default::default()
+                        };
+                        res = exec_on_package(
+                            cx,
+                            pkg.id,
+                            &pkg.kind,
+                            &line,
+                            &mut progress,
+                            &mut keep_going,
+                        );
+                        if res.is_err() {
+                            failed.store(true, Ordering::Relaxed);
+                        }
+                    }
+                    (keep_going, res)
+                })
+            })
+            .collect();
+        workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+    });
+    progress.count = end;
+    // The results of the failed job are also merged, so that they are part of
+    // the summary.
+    let mut res = Ok(());
+    for (other, other_res) in results {
+        keep_going.merge(other);
+        res = res.and(other_res);
+    }
+    res
+}
+
 fn exec_on_package(
     cx: &Context,
     id: &PackageId,
@@ -461,12 +1575,16 @@ fn exec_on_package(
 
     match kind {
         Kind::Normal => {
-            // only run with default features
+            // only run with default features, or with all features (--only-all-features)
+            if cx.only_all_features {
+                line.arg("--all-features");
+            }
             return exec_cargo(cx, id, &mut line, progress, keep_going);
         }
         Kind::Each { .. } | Kind::Powerset { .. } => {}
     }
 
+    let default_line = line.clone();
     if !cx.no_default_features {
         line.arg("--no-default-features");
     }
@@ -476,33 +1594,46 @@ fn exec_on_package(
     // Otherwise, "run with default features" is basically the same as
     // "run with no default features".
 
-    if !cx.exclude_no_default_features {
-        // run with no default features if the package has other features
-        exec_cargo(cx, id, &mut line, progress, keep_going)?;
-    }
-
-    match kind {
-        Kind::Each { features } => {
-            for &f in features {
-                exec_cargo_with_features(cx, id, &line, progress, keep_going, &[f])?;
+    let pkg_features = cx.pkg_features(id);
+    for phase in phases(cx, id) {
+        match phase {
+            Phase::Default => {
+                // run with default features, i.e., without any feature flags
+                if !cx.no_default_features {
+                    exec_cargo(cx, id, &mut default_line.clone(), progress, keep_going)?;
+                }
             }
-        }
-        Kind::Powerset { features } => {
-            for f in features {
-                exec_cargo_with_features(cx, id, &line, progress, keep_going, f)?;
+            Phase::NoDefault => {
+                if !cx.exclude_no_default_features {
+                    // run with no default features if the package has other features
+                    exec_cargo(cx, id, &mut line.clone(), progress, keep_going)?;
+                }
+            }
+            Phase::Features => match kind {
+                Kind::Each { features } => {
+                    for &f in features {
+                        exec_cargo_with_features(cx, id, &line, progress, keep_going, &[f])?;
+                    }
+                }
+                Kind::Powerset { features } => {
+                    for f in features {
+                        exec_cargo_with_features(cx, id, &line, progress, keep_going, f)?;
+                    }
+                }
+                Kind::Normal => unreachable!(),
+            },
+            Phase::All => {
+                if !exclude_all_features(cx, id)
+                    && pkg_features.normal().len() + pkg_features.optional_deps().len() > 1
+                {
+                    // run with all features
+                    // https://github.com/taiki-e/cargo-hack/issues/42
+                    let mut line = line.clone();
+                    line.arg("--all-features");
+                    exec_cargo(cx, id, &mut line, progress, keep_going)?;
+                }
             }
         }
-        Kind::Normal => unreachable!(),
-    }
-
-    let pkg_features = cx.pkg_features(id);
-    if !cx.exclude_all_features
-        && pkg_features.normal().len() + pkg_features.optional_deps().len() > 1
-    {
-        // run with all features
-        // https://github.com/taiki-e/cargo-hack/issues/42
-        line.arg("--all-features");
-        exec_cargo(cx, id, &mut line, progress, keep_going)?;
     }
 
     Ok(())
@@ -525,6 +1656,32 @@ fn exec_cargo_with_features(
 struct KeepGoing {
     count: u64,
     failed_commands: BTreeMap<String, Vec<String>>,
+    /// Commands that failed but were allowed to fail by --allow-failure.
+    allowed_failures: BTreeMap<String, Vec<String>>,
+    /// The failed commands and their output captured by --replay-failures.
+    replays: Vec<(String, Output)>,
+    /// The results of the commands run, recorded if --summary-format is used.
+    results: Vec<summary::CommandResult>,
+}
+
+impl KeepGoing {
+    /// The number of failed commands, including those allowed by --allow-failure.
+    fn failures(&self) -> usize {
+        self.failed_commands.values().chain(self.allowed_failures.values()).map(Vec::len).sum()
+    }
+
+    /// Adds the failures recorded by another job of --package-jobs.
+    fn merge(&mut self, other: Self) {
+        self.count = self.count.saturating_add(other.count);
+        for (name, commands) in other.failed_commands {
+            self.failed_commands.entry(name).or_default().extend(commands);
+        }
+        for (name, commands) in other.allowed_failures {
+            self.allowed_failures.entry(name).or_default().extend(commands);
+        }
+        self.replays.extend(other.replays);
+        self.results.extend(other.results);
+    }
 }
 
 impl fmt::Display for KeepGoing {
@@ -541,6 +1698,70 @@ impl fmt::Display for KeepGoing {
     }
 }
 
+struct AllowedFailures<'a>(&'a BTreeMap<String, Vec<String>>);
+
+impl fmt::Display for AllowedFailures<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count: usize = self.0.values().map(Vec::len).sum();
+        writeln!(f, "{count} commands failed but were allowed to fail by --allow-failure\n")?;
+        writeln!(f, "allowed failures:")?;
+        for (pkg, commands) in self.0 {
+            writeln!(f, "    {pkg}:")?;
+            for cmd in commands {
+                writeln!(f, "        {cmd}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A phase of the runs on each package with --each-feature or --feature-powerset.
+/// The order of the phases is controlled by --order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// `default`: run with default features.
+    Default,
+    /// `none`: run with just --no-default-features flag.
+    NoDefault,
+    /// `each`: runs with the feature combinations of --each-feature or --feature-powerset.
+    Features,
+    /// `all`: run with just --all-features flag.
+    All,
+}
+
+impl Phase {
+    /// The order used when --order is not specified.
+    const DEFAULT_ORDER: &'static [Self] = &[Self::NoDefault, Self::Features, Self::All];
+}
+
+impl FromStr for Phase {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::Default),
+            "none" => Ok(Self::NoDefault),
+            "each" => Ok(Self::Features),
+            "all" => Ok(Self::All),
+            other => bail!(
+                "argument for --order must be a comma-separated list of default, none, each, and \
+                 all, but found `{other}`"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Default => "default",
+            Self::NoDefault => "none",
+            Self::Features => "each",
+            Self::All => "all",
+        })
+    }
+}
+
 #[derive(Clone, Copy, PartialEq)]
 enum LogGroup {
     None,
@@ -591,61 +1812,428 @@ impl FromStr for LogGroup {
     }
 }
 
-fn exec_cargo(
+fn exec_cargo<'a>(
+    cx: &'a Context,
+    id: &PackageId,
+    line: &mut ProcessBuilder<'a>,
+    progress: &mut Progress,
+    keep_going: &mut KeepGoing,
+) -> Result<()> {
+    if progress.stopped {
+        return Ok(());
+    }
+    if let Some((target, missing)) = missing_required_features(cx, id, line) {
+        info!(
+            "skipped running on {} with {}: target `{target}` requires feature(s) `{}`",
+            cx.packages(id).name,
+            feature_flags(line),
+            missing.join(",")
+        );
+        // Also skip the runs for --also.
+        for _ in 0..=cx.also.len() {
+            progress.count += 1;
+            progress.complete()?;
+        }
+        return Ok(());
+    }
+    let failures = keep_going.failures();
+    exec_cargo_once(cx, id, line, progress, keep_going)?;
+    // --also: run the other subcommands with the same feature combination.
+    for (subcommand, args) in &cx.also {
+        let mut line = line.clone();
+        line.propagated_leading_args(args);
+        line.clippy_args(if subcommand == "clippy" { &cx.clippy_args } else { &[] });
+        exec_cargo_once(cx, id, &mut line, progress, keep_going)?;
+    }
+    // Commands skipped by --continue-from or --resume are not successes.
+    if cx.stop_on_first_success
+        && progress.count > progress.skip
+        && keep_going.failures() == failures
+    {
+        info!(
+            "package `{}` succeeded with {}; stopping due to --stop-on-first-success",
+            cx.packages(id).name,
+            feature_flags(line)
+        );
+        progress.stopped = true;
+    }
+    Ok(())
+}
+
+fn exec_cargo_once(
     cx: &Context,
     id: &PackageId,
     line: &mut ProcessBuilder<'_>,
     progress: &mut Progress,
     keep_going: &mut KeepGoing,
 ) -> Result<()> {
-    let res = exec_cargo_inner(cx, id, line, progress);
-    if cx.keep_going {
+    let mut captured = None;
+    // Commands skipped by --continue-from or --resume are not run, so they are
+    // not part of the summary.
+    let record = summary::enabled(cx)
+        && progress.count >= progress.skip
+        && !cx.print_command_list
+        && !cx.gh_matrix;
+    let start = Instant::now();
+    let res = exec_cargo_inner(cx, id, line, progress, cx.replay_failures.then_some(&mut captured));
+    if record {
+        let status = match &res {
+            Ok(()) => summary::Status::Pass,
+            Err(_) if is_allowed_failure(cx, line) => summary::Status::AllowedFailure,
+            Err(_) => summary::Status::Fail,
+        };
+        keep_going.results.push(summary::CommandResult {
+            package: cx.packages(id).name.clone(),
+            features: feature_flags(line),
+            status,
+            duration: start.elapsed(),
+        });
+    }
+    match &res {
+        Ok(()) => progress.complete()?,
+        Err(e) if is_allowed_failure(cx, line) => {
+            warn!("{e:#}");
+            let name = cx.packages(id).name.clone();
+            keep_going.allowed_failures.entry(name).or_default().push(display_command(cx, line));
+            return progress.complete();
+        }
+        Err(_) => COMMAND_FAILED.store(true, Ordering::Relaxed),
+    }
+    if cx.keep_going || cx.stop_on_first_success {
         if let Err(e) = res {
-            error!("{e:#}");
+            if cx.keep_going {
+                error!("{e:#}");
+            } else {
+                // Not an error unless no feature combination succeeds.
+                info!("{e:#}");
+            }
             keep_going.count = keep_going.count.saturating_add(1);
             let name = cx.packages(id).name.clone();
             if !keep_going.failed_commands.contains_key(&name) {
                 keep_going.failed_commands.insert(name.clone(), vec![]);
             }
-            keep_going.failed_commands.get_mut(&name).unwrap().push(format!("{line:#}"));
+            keep_going.failed_commands.get_mut(&name).unwrap().push(display_command(cx, line));
+            if let Some(output) = captured {
+                let command = format!("{} on {name}", display_command(cx, line));
+                keep_going.replays.push((command, output));
+            }
         }
         Ok(())
     } else {
+        if let Some(output) = &captured {
+            replay(&format!("{} on {}", display_command(cx, line), cx.packages(id).name), output);
+        }
         res
     }
 }
 
+/// Prints the output of a failed command captured by --replay-failures.
+fn replay(command: &str, output: &Output) {
+    eprintln!();
+    info!("output of {command}:");
+    let _ = io::stdout().write_all(&output.stdout);
+    let _ = io::stderr().write_all(&output.stderr);
+}
+
+/// Returns `true` if the feature set of `line` exactly matches one of the sets
+/// specified by --allow-failure.
+fn is_allowed_failure(cx: &Context, line: &ProcessBuilder<'_>) -> bool {
+    if cx.allow_failure.is_empty() || line.has_arg("--all-features") {
+        return false;
+    }
+    let features: BTreeSet<&str> = line.features().collect();
+    cx.allow_failure.iter().any(|set| set.iter().map(String::as_str).eq(features.iter().copied()))
+}
+
 fn exec_cargo_inner(
     cx: &Context,
     id: &PackageId,
     line: &mut ProcessBuilder<'_>,
     progress: &mut Progress,
+    captured: Option<&mut Option<Output>>,
 ) -> Result<()> {
-    if progress.count != 0 && !cx.print_command_list && cx.log_group == LogGroup::None {
+    if progress.count < progress.skip {
+        // skipped by --continue-from or --resume
+        progress.count += 1;
+        return Ok(());
+    }
+    if cx.gh_matrix {
+        progress.count += 1;
+        progress.gh_matrix.push(gh_matrix_entry(cx, id, line));
+        return Ok(());
+    }
+    if progress.count > progress.skip
+        && !cx.print_command_list
+        && !cx.progress_bar
+        && cx.log_group == LogGroup::None
+    {
         eprintln!();
     }
     progress.count += 1;
+    progress.start.get_or_insert_with(Instant::now);
+    // --env is applied first so that it cannot override the variables set by cargo-hack.
+    let mut env: Vec<(&str, String)> = cx.env.iter().map(|(k, v)| (&**k, v.clone())).collect();
+    env.extend(hack_env(line, progress));
+    if cx.target_dir_per_combination {
+        env.push(("CARGO_TARGET_DIR", combination_target_dir(cx, line).display().to_string()));
+    }
+    if let Some(rustflags) = rustflags(cx, id, line) {
+        env.push(("CARGO_ENCODED_RUSTFLAGS", rustflags));
+    }
+    line.envs(env.clone());
+    line.command_prefix(&cx.command_prefix);
+    // <package> with <features>, shown in the progress bar
+    let bar_msg =
+        cx.progress_bar.then(|| format!("{} with {}", cx.packages(id).name, feature_flags(line)));
 
     if cx.clean_per_run {
         cargo_clean(cx, Some(id))?;
     }
 
+    let mut exec_line = cx.exec.as_ref().map(|exec| {
+        let mut exec_line = exec_process(cx, id, exec, line);
+        exec_line.envs(env);
+        exec_line
+    });
+
     if cx.print_command_list {
-        print_command(line.clone());
+        if cx.exec.is_some() {
+            println!("{}", display_command(cx, line));
+        } else {
+            print_command(line.clone());
+        }
         return Ok(());
     }
 
-    // running `<command>` (on <package>) (<count>/<total>)
     let mut msg = String::new();
-    if term::verbose() {
+    if let Some(exec) = &cx.exec {
+        // running `<command>` on <package> with <features> (<count>/<total>)
+        write!(msg, "running `{exec}` on {} with {}", cx.packages(id).name, feature_flags(line))
+            .unwrap();
+    } else if term::verbose() {
+        // running `<command>` (on <package>) (<count>/<total>)
         write!(msg, "running {line}").unwrap();
     } else {
         write!(msg, "running {line} on {}", cx.packages(id).name).unwrap();
     }
     write!(msg, " ({}/{})", progress.count, progress.total).unwrap();
-    let _guard = cx.log_group.print(&msg);
+    let _guard = bar_msg.is_none().then(|| cx.log_group.print(&msg));
+
+    let features: Vec<_> = line.features().map(str::to_owned).collect();
+    let log_file = match &cx.log_dir {
+        Some(dir) => {
+            fs::create_dir_all(dir)?;
+            Some(dir.join(log_file_name(cx, id, line)))
+        }
+        None => None,
+    };
+    let line = match &mut exec_line {
+        Some(exec_line) => exec_line,
+        None => {
+            if bar_msg.is_some() && env::var_os("CARGO_TERM_COLOR").is_none() {
+                // Keep cargo's colored output even though the output is piped.
+                line.env("CARGO_TERM_COLOR", "always");
+            }
+            line
+        }
+    };
+    line.timeout(cx.timeout);
+    line.log_file(log_file);
+    progress.write_status(cx, id, &features, None)?;
+    let res = run_with_retry(cx, line, progress, bar_msg.as_deref(), captured);
+    progress.write_status(cx, id, &features, Some(res.is_ok()))?;
+    res
+}
+
+/// Runs `line`, retrying up to --retry times on failure.
+///
+/// If `bar_msg` is `Some`, the output is captured and the progress bar is shown
+/// while the command is running.
+///
+/// If `captured` is `Some` (--replay-failures), the output is captured and, if
+/// the command failed, stored in it instead of being included in the error.
+fn run_with_retry(
+    cx: &Context,
+    line: &mut ProcessBuilder<'_>,
+    progress: &Progress,
+    bar_msg: Option<&str>,
+    mut captured: Option<&mut Option<Output>>,
+) -> Result<()> {
+    let mut tick = || {
+        if let Some(msg) = bar_msg {
+            let start = progress.start.unwrap();
+            term::draw_progress_bar(progress.count, progress.total, msg, start.elapsed());
+        }
+    };
+    let mut run = |line: &mut ProcessBuilder<'_>| match (&mut captured, bar_msg) {
+        (Some(captured), _) => {
+            let output = cx.runner.run_with_captured_output(line, &mut tick)?;
+            if output.status.success() {
+                **captured = None;
+                return Ok(());
+            }
+            let e = line.status_error(output.status);
+            **captured = Some(output);
+            Err(e)
+        }
+        (None, Some(_)) => cx.runner.run_with_progress(line, &mut tick),
+        (None, None) => cx.runner.run(line),
+    };
+    let mut res = run(line);
+    for retry in 1..=cx.retry {
+        let Err(e) = &res else { break };
+        info!("{e:#}\nretrying {line} ({retry}/{})", cx.retry);
+        res = run(line);
+    }
+    res
+}
+
+/// Returns the `CARGO_HACK*` environment variables set on every command run
+/// by cargo-hack, so that build scripts can tell that they are running under
+/// cargo-hack and which combination is being checked.
+fn hack_env(line: &ProcessBuilder<'_>, progress: &Progress) -> [(&'static str, String); 4] {
+    [
+        ("CARGO_HACK", "1".to_owned()),
+        ("CARGO_HACK_FEATURES", line.features().collect::<Vec<_>>().join(",")),
+        ("CARGO_HACK_COMMAND_INDEX", progress.count.to_string()),
+        ("CARGO_HACK_COMMAND_TOTAL", progress.total.to_string()),
+    ]
+}
+
+/// Creates the process for --exec from the cargo command line that would
+/// otherwise have been run.
+fn exec_process<'a>(
+    cx: &Context,
+    id: &PackageId,
+    exec: &str,
+    line: &ProcessBuilder<'_>,
+) -> ProcessBuilder<'a> {
+    let mut words = exec.split_whitespace();
+    let mut exec_line = cmd!(words.next().unwrap());
+    exec_line.args(words);
+    exec_line.env("CARGO_HACK_PACKAGE", &cx.packages(id).name);
+    exec_line
+        .env("CARGO_HACK_NO_DEFAULT_FEATURES", line.has_arg("--no-default-features").to_string());
+    exec_line.env("CARGO_HACK_ALL_FEATURES", line.has_arg("--all-features").to_string());
+    exec_line
+}
+
+/// Returns feature-related flags of `line` for display, e.g.,
+/// `` `--no-default-features --features a` ``.
+fn feature_flags(line: &ProcessBuilder<'_>) -> String {
+    let mut flags = vec![];
+    for flag in ["--no-default-features", "--all-features"] {
+        if line.has_arg(flag) {
+            flags.push(flag.to_owned());
+        }
+    }
+    let features = line.sorted_features();
+    if !features.is_empty() {
+        flags.push(format!("--features {features}"));
+    }
+    if flags.is_empty() {
+        "default features".to_owned()
+    } else {
+        format!("`{}`", flags.join(" "))
+    }
+}
+
+/// Returns the entry of --gh-matrix for this feature combination.
+fn gh_matrix_entry(cx: &Context, id: &PackageId, line: &ProcessBuilder<'_>) -> serde_json::Value {
+    let features = line.sorted_features();
+    let mut flags = vec![];
+    for flag in ["--no-default-features", "--all-features"] {
+        if line.has_arg(flag) {
+            flags.push(flag.to_owned());
+        }
+    }
+    if !features.is_empty() {
+        flags.push(format!("--features {features}"));
+    }
+    serde_json::json!({
+        "package": cx.packages(id).name,
+        "features": features,
+        "flags": flags.join(" "),
+    })
+}
+
+/// Returns the command to display in the list of failed commands.
+fn display_command(cx: &Context, line: &ProcessBuilder<'_>) -> String {
+    match &cx.exec {
+        Some(exec) => format!("`{exec}` with {}", feature_flags(line)),
+        None => format!("{line:#}"),
+    }
+}
+
+/// Returns the target directory used for this feature combination by
+/// --target-dir-per-combination.
+///
+/// The directory is keyed only by the feature flags, so workspace members
+/// checked with the same feature set share the build cache of their common
+/// dependencies, while different feature sets are still isolated.
+fn combination_target_dir(cx: &Context, line: &ProcessBuilder<'_>) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    feature_flags(line).hash(&mut hasher);
+    cx.target_directory().join(COMBINATION_TARGET_DIRS).join(format!("{:016x}", hasher.finish()))
+}
 
-    line.run()
+/// Returns the name of the file to which the output of this feature combination
+/// is written by --log-dir, e.g., `foo-no-default-features-a+b.log`.
+fn log_file_name(cx: &Context, id: &PackageId, line: &ProcessBuilder<'_>) -> String {
+    let mut name = cx.packages(id).name.clone();
+    for flag in ["--no-default-features", "--all-features"] {
+        if line.has_arg(flag) {
+            name.push('-');
+            name.push_str(&flag[2..]);
+        }
+    }
+    let features = line.sorted_features();
+    if !features.is_empty() {
+        name.push('-');
+        name.push_str(&features.replace(',', "+"));
+    } else if !line.has_arg("--no-default-features") && !line.has_arg("--all-features") {
+        name.push_str("-default");
+    }
+    // Feature names may contain characters that cannot be used in file names, e.g., `/`.
+    let mut name: String =
+        name.chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '+' | '.') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+    // Keep the file name within the limit of common file systems.
+    if name.len() > 200 {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        name.truncate(180);
+        write!(name, "-{:016x}", hasher.finish()).unwrap();
+    }
+    name.push_str(".log");
+    name
+}
+
+/// The subdirectory of the target directory that contains the directories
+/// created by --target-dir-per-combination.
+const COMBINATION_TARGET_DIRS: &str = "hack-combinations";
+
+/// Removes the directories created by --target-dir-per-combination on drop,
+/// unless --keep-target-dirs is passed.
+struct TargetDirsGuard<'a>(&'a Context);
+impl Drop for TargetDirsGuard<'_> {
+    fn drop(&mut self) {
+        let cx = self.0;
+        let dir = cx.target_directory().join(COMBINATION_TARGET_DIRS);
+        if cx.keep_target_dirs || cx.print_command_list || cx.count || !dir.exists() {
+            return;
+        }
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            warn!("{e:#}");
+        }
+    }
 }
 
 fn cargo_clean(cx: &Context, id: Option<&PackageId>) -> Result<()> {
@@ -669,7 +2257,7 @@ fn cargo_clean(cx: &Context, id: Option<&PackageId>) -> Result<()> {
         info!("running {line}");
     }
 
-    line.run()
+    cx.runner.run(&mut line)
 }
 
 fn print_command(mut line: ProcessBuilder<'_>) {
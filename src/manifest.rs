@@ -7,7 +7,7 @@ use std::{
 
 use anyhow::{bail, format_err, Context as _, Result};
 
-use crate::{context::Context, fs, term};
+use crate::{cfg::Cfg, context::Context, features::Feature, fs, term};
 
 type ParseResult<T> = Result<T, &'static str>;
 
@@ -16,8 +16,11 @@ type ParseResult<T> = Result<T, &'static str>;
 pub(crate) struct Manifest {
     raw: String,
     doc: toml_edit::DocumentMut,
-    pub(crate) package: Package,
+    /// `None` if this is a virtual manifest.
+    pub(crate) package: Option<Package>,
     pub(crate) features: BTreeMap<String, Vec<String>>,
+    pub(crate) config: PackageConfig,
+    pub(crate) workspace_config: WorkspaceConfig,
 }
 
 impl Manifest {
@@ -32,32 +35,54 @@ impl Manifest {
         let features = Features::from_table(&doc).map_err(|s| {
             format_err!("failed to parse `{s}` field from manifest `{}`", path.display())
         })?;
-        Ok(Self { raw, doc, package, features })
+        let config = PackageConfig::from_table(&doc).map_err(|s| {
+            format_err!("failed to parse `{s}` field from manifest `{}`", path.display())
+        })?;
+        let workspace_config = WorkspaceConfig::from_table(&doc).map_err(|s| {
+            format_err!("failed to parse `{s}` field from manifest `{}`", path.display())
+        })?;
+        Ok(Self { raw, doc, package, features, config, workspace_config })
     }
 }
 
 pub(crate) struct Package {
     // `metadata.package.publish` requires Rust 1.39
-    pub(crate) publish: Option<bool>,
+    #[allow(clippy::option_option)]
+    pub(crate) publish: Option<Option<Vec<String>>>,
     // `metadata.package.rust_version` requires Rust 1.58
     #[allow(clippy::option_option)]
     pub(crate) rust_version: Option<Option<String>>,
 }
 
 impl Package {
-    fn from_table(doc: &toml_edit::DocumentMut, metadata_cargo_version: u32) -> ParseResult<Self> {
-        let package = doc.get("package").and_then(toml_edit::Item::as_table).ok_or("package")?;
+    fn from_table(
+        doc: &toml_edit::DocumentMut,
+        metadata_cargo_version: u32,
+    ) -> ParseResult<Option<Self>> {
+        let package = match doc.get("package") {
+            Some(package) => package.as_table().ok_or("package")?,
+            // virtual manifest
+            None => return Ok(None),
+        };
 
-        Ok(Self {
+        Ok(Some(Self {
             // Publishing is unrestricted if `true` or the field is not
             // specified, and forbidden if `false` or the array is empty.
             publish: if metadata_cargo_version >= 39 {
                 None // Use `metadata.package.publish` instead.
             } else {
                 Some(match package.get("publish") {
-                    None => true,
-                    Some(toml_edit::Item::Value(toml_edit::Value::Boolean(b))) => *b.value(),
-                    Some(toml_edit::Item::Value(toml_edit::Value::Array(a))) => !a.is_empty(),
+                    None => None,
+                    Some(toml_edit::Item::Value(toml_edit::Value::Boolean(b))) => {
+                        if *b.value() {
+                            None
+                        } else {
+                            Some(vec![])
+                        }
+                    }
+                    Some(toml_edit::Item::Value(toml_edit::Value::Array(a))) => {
+                        Some(string_array(a).ok_or("publish")?)
+                    }
                     Some(_) => return Err("publish"),
                 })
             },
@@ -70,10 +95,109 @@ impl Package {
                     Some(None) => return Err("rust-version"),
                 })
             },
-        })
+        }))
+    }
+}
+
+/// Per-package configuration in `[package.metadata.cargo-hack]` table.
+#[derive(Default)]
+pub(crate) struct PackageConfig {
+    /// `exclude-features`
+    pub(crate) exclude_features: Vec<String>,
+    /// `mutually-exclusive-features`
+    pub(crate) mutually_exclusive_features: Vec<Feature>,
+    /// `feature-cfg`
+    pub(crate) feature_cfg: BTreeMap<String, Cfg>,
+    /// `feature-rustflags`
+    pub(crate) feature_rustflags: BTreeMap<String, String>,
+}
+
+impl PackageConfig {
+    fn from_table(doc: &toml_edit::DocumentMut) -> ParseResult<Self> {
+        const EXCLUDE_FEATURES: &str = "package.metadata.cargo-hack.exclude-features";
+        const MUTUALLY_EXCLUSIVE_FEATURES: &str =
+            "package.metadata.cargo-hack.mutually-exclusive-features";
+        const FEATURE_CFG: &str = "package.metadata.cargo-hack.feature-cfg";
+        const FEATURE_RUSTFLAGS: &str = "package.metadata.cargo-hack.feature-rustflags";
+
+        let table = match doc
+            .get("package")
+            .and_then(|v| v.get("metadata"))
+            .and_then(|v| v.get("cargo-hack"))
+        {
+            Some(table) => table.as_table_like().ok_or("package.metadata.cargo-hack")?,
+            None => return Ok(Self::default()),
+        };
+        let exclude_features = match table.get("exclude-features") {
+            Some(v) => v.as_array().and_then(string_array).ok_or(EXCLUDE_FEATURES)?,
+            None => vec![],
+        };
+        let mutually_exclusive_features = match table.get("mutually-exclusive-features") {
+            Some(v) => v
+                .as_array()
+                .ok_or(MUTUALLY_EXCLUSIVE_FEATURES)?
+                .iter()
+                .map(|v| v.as_array().and_then(string_array).map(Feature::group))
+                .collect::<Option<_>>()
+                .ok_or(MUTUALLY_EXCLUSIVE_FEATURES)?,
+            None => vec![],
+        };
+        let feature_cfg = match table.get("feature-cfg") {
+            Some(v) => v
+                .as_table_like()
+                .ok_or(FEATURE_CFG)?
+                .iter()
+                .map(|(k, v)| Some((k.to_owned(), v.as_str()?.parse().ok()?)))
+                .collect::<Option<_>>()
+                .ok_or(FEATURE_CFG)?,
+            None => BTreeMap::new(),
+        };
+        let feature_rustflags = match table.get("feature-rustflags") {
+            Some(v) => v
+                .as_table_like()
+                .ok_or(FEATURE_RUSTFLAGS)?
+                .iter()
+                .map(|(k, v)| Some((k.to_owned(), v.as_str()?.to_owned())))
+                .collect::<Option<_>>()
+                .ok_or(FEATURE_RUSTFLAGS)?,
+            None => BTreeMap::new(),
+        };
+        Ok(Self { exclude_features, mutually_exclusive_features, feature_cfg, feature_rustflags })
+    }
+}
+
+/// Workspace-wide configuration in `[workspace.metadata.cargo-hack]` table of
+/// the root manifest.
+#[derive(Default)]
+pub(crate) struct WorkspaceConfig {
+    /// `exclude-features`
+    pub(crate) exclude_features: Vec<String>,
+}
+
+impl WorkspaceConfig {
+    fn from_table(doc: &toml_edit::DocumentMut) -> ParseResult<Self> {
+        const EXCLUDE_FEATURES: &str = "workspace.metadata.cargo-hack.exclude-features";
+
+        let table = match doc
+            .get("workspace")
+            .and_then(|v| v.get("metadata"))
+            .and_then(|v| v.get("cargo-hack"))
+        {
+            Some(table) => table.as_table_like().ok_or("workspace.metadata.cargo-hack")?,
+            None => return Ok(Self::default()),
+        };
+        let exclude_features = match table.get("exclude-features") {
+            Some(v) => v.as_array().and_then(string_array).ok_or(EXCLUDE_FEATURES)?,
+            None => vec![],
+        };
+        Ok(Self { exclude_features })
     }
 }
 
+fn string_array(array: &toml_edit::Array) -> Option<Vec<String>> {
+    array.iter().map(|v| v.as_str().map(str::to_owned)).collect()
+}
+
 struct Features {}
 
 impl Features {
@@ -102,8 +226,14 @@ impl Features {
 pub(crate) fn with(cx: &Context, f: impl FnOnce() -> Result<()>) -> Result<()> {
     // TODO: provide option to keep updated Cargo.lock
     let restore_lockfile = true;
-    let no_dev_deps = cx.no_dev_deps | cx.remove_dev_deps;
+    let no_dev_deps = cx.no_dev_deps | cx.remove_dev_deps | !cx.remove_dev_dep.is_empty();
+    // --remove-dev-dep: remove only the specified dev-dependencies.
+    let dev_deps = if cx.remove_dev_dep.is_empty() { None } else { Some(&*cx.remove_dev_dep) };
     let no_private = cx.no_private;
+    // Prevent other cargo-hack processes from modifying the manifests at the same time.
+    let lock = (no_dev_deps || no_private)
+        .then(|| cx.restore.lock(&cx.metadata.workspace_root))
+        .transpose()?;
     let restore_handles = if no_dev_deps || no_private {
         let mut restore_handles = Vec::with_capacity(cx.metadata.workspace_members.len());
         let workspace_root = &cx.metadata.workspace_root;
@@ -127,14 +257,21 @@ pub(crate) fn with(cx: &Context, f: impl FnOnce() -> Result<()>) -> Result<()> {
                 private_crates.insert(manifest_path);
             } else if is_root && no_private {
                 // This case is handled in the if block after loop.
+            } else if no_dev_deps && cx.is_ignored_private(id) {
+                // Packages skipped by --ignore-private are not run, so their
+                // manifests are left untouched.
             } else if no_dev_deps {
                 let manifest = cx.manifests(id);
                 let mut doc = manifest.doc.clone();
                 if term::verbose() {
                     info!("removing dev-dependencies from {}", manifest_path.display());
                 }
-                remove_dev_deps(&mut doc);
-                restore_handles.push(cx.restore.register(&manifest.raw, manifest_path));
+                remove_dev_deps(&mut doc, dev_deps);
+                restore_handles.push(cx.restore.register_manifest(
+                    &manifest.raw,
+                    manifest_path,
+                    workspace_root,
+                )?);
                 fs::write(manifest_path, doc.to_string())?;
             }
         }
@@ -146,23 +283,16 @@ pub(crate) fn with(cx: &Context, f: impl FnOnce() -> Result<()>) -> Result<()> {
                     (manifest.doc.clone(), manifest.raw.clone())
                 }
                 None => {
-                    let orig = fs::read_to_string(manifest_path)?;
-                    (
-                        orig.parse().with_context(|| {
-                            format!(
-                                "failed to parse manifest `{}` as toml",
-                                manifest_path.display()
-                            )
-                        })?,
-                        orig,
-                    )
+                    // virtual manifest
+                    let manifest = Manifest::new(manifest_path, cx.metadata.cargo_version)?;
+                    (manifest.doc, manifest.raw)
                 }
             };
             if no_dev_deps && root_id.is_some() {
                 if term::verbose() {
                     info!("removing dev-dependencies from {}", manifest_path.display());
                 }
-                remove_dev_deps(&mut doc);
+                remove_dev_deps(&mut doc, dev_deps);
             }
             if !private_crates.is_empty() {
                 if term::verbose() {
@@ -170,7 +300,11 @@ pub(crate) fn with(cx: &Context, f: impl FnOnce() -> Result<()>) -> Result<()> {
                 }
                 remove_private_crates(&mut doc, workspace_root, private_crates);
             }
-            restore_handles.push(cx.restore.register(orig, manifest_path));
+            restore_handles.push(cx.restore.register_manifest(
+                orig,
+                manifest_path,
+                workspace_root,
+            )?);
             fs::write(manifest_path, doc.to_string())?;
         }
         if restore_lockfile {
@@ -188,18 +322,35 @@ pub(crate) fn with(cx: &Context, f: impl FnOnce() -> Result<()>) -> Result<()> {
 
     // Restore original Cargo.toml and Cargo.lock.
     drop(restore_handles);
+    drop(lock);
 
     Ok(())
 }
 
-fn remove_dev_deps(doc: &mut toml_edit::DocumentMut) {
+/// Removes dev-dependencies from the manifest.
+///
+/// If `names` is `None`, all dev-dependencies are removed, otherwise only the
+/// dev-dependencies with the given names are removed.
+fn remove_dev_deps(doc: &mut toml_edit::DocumentMut, names: Option<&[String]>) {
     const KEY: &str = "dev-dependencies";
+    let remove = |table: &mut dyn toml_edit::TableLike| match names {
+        None => {
+            table.remove(KEY);
+        }
+        Some(names) => {
+            if let Some(deps) = table.get_mut(KEY).and_then(toml_edit::Item::as_table_like_mut) {
+                for name in names {
+                    deps.remove(name);
+                }
+            }
+        }
+    };
     let table = doc.as_table_mut();
-    table.remove(KEY);
+    remove(table);
     if let Some(table) = table.get_mut("target").and_then(toml_edit::Item::as_table_like_mut) {
         for (_, val) in table.iter_mut() {
             if let Some(table) = val.as_table_like_mut() {
-                table.remove(KEY);
+                remove(table);
             }
         }
     }
@@ -266,14 +417,60 @@ fn remove_private_crates(
 
 #[cfg(test)]
 mod tests {
-    use super::remove_dev_deps;
+    use super::{remove_dev_deps, Package};
+
+    #[test]
+    fn virtual_manifest() {
+        let doc: toml_edit::DocumentMut = "[workspace]\nmembers = [\"a\"]\n".parse().unwrap();
+        assert!(Package::from_table(&doc, 0).unwrap().is_none());
+
+        let doc: toml_edit::DocumentMut =
+            "[package]\npublish = false\nrust-version = \"1.70\"\n".parse().unwrap();
+        let package = Package::from_table(&doc, 0).unwrap().unwrap();
+        assert_eq!(package.publish, Some(Some(vec![])));
+        assert_eq!(package.rust_version, Some(Some("1.70".to_owned())));
+
+        let doc: toml_edit::DocumentMut = "package = 1\n".parse().unwrap();
+        assert!(Package::from_table(&doc, 0).is_err());
+    }
+
+    #[test]
+    fn publish() {
+        let publish = |s: &str| {
+            let doc: toml_edit::DocumentMut = format!("[package]\n{s}").parse().unwrap();
+            Package::from_table(&doc, 0).map(|p| p.unwrap().publish.unwrap())
+        };
+        assert_eq!(publish(""), Ok(None));
+        assert_eq!(publish("publish = true"), Ok(None));
+        assert_eq!(publish("publish = false"), Ok(Some(vec![])));
+        assert_eq!(publish("publish = []"), Ok(Some(vec![])));
+        assert_eq!(publish("publish = [\"x\"]"), Ok(Some(vec!["x".to_owned()])));
+        assert_eq!(publish("publish = [1]"), Err("publish"));
+        assert_eq!(publish("publish = \"x\""), Err("publish"));
+
+        // `metadata.package.publish` is used instead.
+        let doc: toml_edit::DocumentMut = "[package]\npublish = false".parse().unwrap();
+        assert_eq!(Package::from_table(&doc, 39).unwrap().unwrap().publish, None);
+    }
 
     macro_rules! test {
         ($name:ident, $input:expr, $expected:expr) => {
             #[test]
             fn $name() {
                 let mut doc: toml_edit::DocumentMut = $input.parse().unwrap();
-                remove_dev_deps(&mut doc);
+                remove_dev_deps(&mut doc, None);
+                assert_eq!($expected, doc.to_string());
+            }
+        };
+    }
+
+    macro_rules! test_selective {
+        ($name:ident, $names:expr, $input:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                let mut doc: toml_edit::DocumentMut = $input.parse().unwrap();
+                let names: Vec<String> = $names.iter().map(|&s: &&str| s.to_owned()).collect();
+                remove_dev_deps(&mut doc, Some(&names));
                 assert_eq!($expected, doc.to_string());
             }
         };
@@ -437,6 +634,77 @@ foo = [
     ['dev-dependencies'],
     [\"dev-dependencies\"]
 ]
+"
+    );
+
+    test_selective!(
+        selective1,
+        ["foo"],
+        "\
+[package]
+
+[dev-dependencies]
+foo = \"0.1\"
+bar = { version = \"0.1\", features = [] }
+
+[dependencies]
+foo = \"0.1\"
+",
+        "\
+[package]
+
+[dev-dependencies]
+bar = { version = \"0.1\", features = [] }
+
+[dependencies]
+foo = \"0.1\"
+"
+    );
+
+    test_selective!(
+        selective2,
+        ["foo", "baz"],
+        "\
+[package]
+
+[dev-dependencies.foo]
+version = \"0.1\"
+
+[dev-dependencies]
+bar = \"0.1\"
+",
+        "\
+[package]
+
+[dev-dependencies]
+bar = \"0.1\"
+"
+    );
+
+    test_selective!(
+        selective_target_deps,
+        ["foo"],
+        "\
+[package]
+
+[target.'cfg(unix)'.dev-dependencies]
+foo = \"0.1\"
+bar = \"0.1\"
+
+[target.'cfg(windows)'.dev-dependencies.foo]
+version = \"0.1\"
+
+[target.'cfg(unix)'.dependencies]
+foo = \"0.1\"
+",
+        "\
+[package]
+
+[target.'cfg(unix)'.dev-dependencies]
+bar = \"0.1\"
+
+[target.'cfg(unix)'.dependencies]
+foo = \"0.1\"
 "
     );
 }
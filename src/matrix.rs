@@ -0,0 +1,59 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// The file specified by --matrix-file, which maps package names to the feature
+// combinations to use for that package instead of the generated ones.
+//
+// ```toml
+// foo = [["a"], ["a", "b"]]
+// bar = [["std"], ["alloc", "serde"]]
+// ```
+
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{format_err, Context as _, Result};
+
+use crate::fs;
+
+/// Package name to feature combinations.
+pub(crate) type Matrix = BTreeMap<String, Vec<Vec<String>>>;
+
+pub(crate) fn read(path: &Path) -> Result<Matrix> {
+    parse(&fs::read_to_string(path)?)
+        .with_context(|| format!("failed to parse `{}`", path.display()))
+}
+
+fn parse(s: &str) -> Result<Matrix> {
+    let doc: toml_edit::DocumentMut = s.parse()?;
+    doc.iter()
+        .map(|(name, item)| {
+            item.as_array()
+                .and_then(|combinations| {
+                    combinations
+                        .iter()
+                        .map(|c| {
+                            c.as_array()?.iter().map(|f| f.as_str().map(str::to_owned)).collect()
+                        })
+                        .collect()
+                })
+                .map(|combinations| (name.to_owned(), combinations))
+                .ok_or_else(|| format_err!("`{name}` must be an array of arrays of feature names"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn parse_matrix() {
+        let matrix = parse("foo = [[], [\"a\"], [\"a\", \"b\"]]\nbar = []\n").unwrap();
+        assert_eq!(matrix.len(), 2);
+        assert_eq!(matrix["foo"], [vec![], vec!["a".to_owned()], vec!["a".into(), "b".into()]]);
+        assert!(matrix["bar"].is_empty());
+
+        assert!(parse("foo = [\"a\"]").is_err());
+        assert!(parse("foo = [[1]]").is_err());
+        assert!(parse("[foo]\na = 1").is_err());
+    }
+}
@@ -7,17 +7,17 @@
 // - https://github.com/oli-obk/cargo_metadata
 
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     ffi::OsStr,
     path::{Path, PathBuf},
-    rc::Rc,
+    sync::Arc,
 };
 
 use anyhow::{format_err, Context as _, Result};
 use cargo_config2::Config;
 use serde_json::{Map, Value};
 
-use crate::{cargo, cli::Args, fs, process::ProcessBuilder, restore, term};
+use crate::{cargo, cli::Args, fs, glob, process::ProcessBuilder, restore, term};
 
 type Object = Map<String, Value>;
 type ParseResult<T> = Result<T, &'static str>;
@@ -25,7 +25,7 @@ type ParseResult<T> = Result<T, &'static str>;
 /// An opaque unique identifier for referring to the package.
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub(crate) struct PackageId {
-    repr: Rc<str>,
+    repr: Arc<str>,
 }
 
 impl From<String> for PackageId {
@@ -42,10 +42,16 @@ pub(crate) struct Metadata {
     pub(crate) packages: HashMap<PackageId, Package>,
     /// List of members of the workspace.
     pub(crate) workspace_members: Vec<PackageId>,
+    /// List of default members of the workspace.
+    ///
+    /// This is `None` if running with a version of Cargo older than 1.71.
+    pub(crate) workspace_default_members: Option<Vec<PackageId>>,
     /// The resolved dependency graph for the entire workspace.
     pub(crate) resolve: Resolve,
     /// The absolute path to the root of the workspace.
     pub(crate) workspace_root: PathBuf,
+    /// The absolute path to the build directory where Cargo places its output.
+    pub(crate) target_directory: PathBuf,
 }
 
 impl Metadata {
@@ -57,7 +63,7 @@ impl Metadata {
         restore: &restore::Manager,
     ) -> Result<Self> {
         let stable_cargo_version =
-            cargo::version(cmd!("rustup", "run", "stable", "cargo")).map(|v| v.minor).unwrap_or(0);
+            cargo::version(cmd!("rustup", "run", "stable", "cargo")).map_or(0, |v| v.minor);
 
         let config;
         let include_deps_features = if args.include_deps_features {
@@ -100,7 +106,7 @@ impl Metadata {
                 cmd.arg("--manifest-path");
                 cmd.arg(manifest_path);
             }
-            let no_deps_raw = cmd.read()?;
+            let no_deps_raw = read_metadata(&mut cmd)?;
             let no_deps: Object = serde_json::from_str(&no_deps_raw)
                 .with_context(|| format!("failed to parse output from {cmd}"))?;
             let lockfile =
@@ -133,7 +139,7 @@ impl Metadata {
                         // If failed, try again with the version of cargo we will actually use.
                         cmd = cmd!(cargo);
                         append_metadata_args(&mut cmd);
-                        cmd.read()?
+                        read_metadata(&mut cmd)?
                     } else {
                         no_deps_raw
                     }
@@ -142,7 +148,7 @@ impl Metadata {
         } else {
             cmd = cmd!(cargo);
             append_metadata_args(&mut cmd);
-            cmd.read()?
+            read_metadata(&mut cmd)?
         };
 
         let map = serde_json::from_str(&json)
@@ -165,15 +171,58 @@ impl Metadata {
                 .map(|v| Package::from_value(v, cargo_version))
                 .collect::<Result<_, _>>()?,
             workspace_members,
+            // This field was added in Rust 1.71.
+            workspace_default_members: if cargo_version >= 71 {
+                map.remove_nullable("workspace_default_members", into_array)?
+                    .map(|a| {
+                        a.into_iter()
+                            .map(|v| into_string(v).ok_or("workspace_default_members"))
+                            .collect::<Result<_, _>>()
+                    })
+                    .transpose()?
+            } else {
+                None
+            },
             resolve: match map.remove_nullable("resolve", into_object)? {
                 Some(resolve) => Resolve::from_obj(resolve, cargo_version)?,
                 None => Resolve { nodes: HashMap::new() },
             },
             workspace_root: map.remove_string("workspace_root")?,
+            target_directory: map.remove_string("target_directory")?,
         })
     }
 }
 
+/// Runs `cargo metadata`, and if it failed due to a malformed manifest of a
+/// workspace member, points to that manifest in the error.
+fn read_metadata(cmd: &mut ProcessBuilder<'_>) -> Result<String> {
+    cmd.read().map_err(|e| match bad_manifest(&format!("{e:#}")) {
+        Some(path) => e.context(format!("failed to load workspace metadata; check `{path}`")),
+        None => e,
+    })
+}
+
+/// Finds the path of the manifest that cargo failed to load from its error message.
+fn bad_manifest(msg: &str) -> Option<String> {
+    let quoted = |prefix: &str| {
+        let rest = &msg[msg.find(prefix)? + prefix.len()..];
+        Some(rest[..rest.find('`')?].to_owned())
+    };
+    // "failed to parse manifest at `<path>`" (older cargo)
+    if let Some(path) = quoted("failed to parse manifest at `") {
+        return Some(path);
+    }
+    // "failed to load manifest for workspace member `<dir>`"
+    if let Some(dir) = quoted("failed to load manifest for workspace member `") {
+        return Some(Path::new(&dir).join("Cargo.toml").display().to_string());
+    }
+    // "--> <path>:<line>:<column>" of toml parse errors
+    msg.lines().find_map(|line| {
+        let path = line.trim_start().strip_prefix("--> ")?.rsplitn(3, ':').last()?;
+        path.ends_with("Cargo.toml").then(|| path.to_owned())
+    })
+}
+
 /// The resolved dependency graph for the entire workspace.
 pub(crate) struct Resolve {
     /// Nodes in a dependency graph.
@@ -273,24 +322,57 @@ impl DepKindInfo {
 pub(crate) struct Package {
     /// The name of the package.
     pub(crate) name: String,
-    // /// The version of the package.
-    // pub(crate) version: String,
+    /// The version of the package.
+    pub(crate) version: String,
     /// List of dependencies of this particular package.
     pub(crate) dependencies: Vec<Dependency>,
+    /// Targets provided by the crate (lib, bin, example, test, ...).
+    pub(crate) targets: Vec<Target>,
     /// Features provided by the crate, mapped to the features required by that feature.
     pub(crate) features: BTreeMap<String, Vec<String>>,
     /// Absolute path to this package's manifest.
     pub(crate) manifest_path: PathBuf,
     /// List of registries to which this package may be published.
     ///
-    /// This is always `true` if running with a version of Cargo older than 1.39.
-    pub(crate) publish: bool,
+    /// Publishing is unrestricted if `None`, and forbidden if empty.
+    ///
+    /// This is always `None` if running with a version of Cargo older than 1.39.
+    pub(crate) publish: Option<Vec<String>>,
     /// The minimum supported Rust version of this package.
     ///
     /// This is always `None` if running with a version of Cargo older than 1.58.
     pub(crate) rust_version: Option<String>,
 }
 
+/// A package ID specification given to --package or --exclude.
+///
+/// This supports the `<name>[@<version>]` form, where `<name>` may be a glob
+/// pattern and `<version>` may be partial (e.g., `foo@1.2` matches `foo` 1.2.3).
+pub(crate) struct PackageSpec<'a> {
+    name: &'a str,
+    version: Option<&'a str>,
+}
+
+impl<'a> PackageSpec<'a> {
+    pub(crate) fn parse(spec: &'a str) -> Self {
+        match spec.split_once('@') {
+            Some((name, version)) => Self { name, version: Some(version) },
+            None => Self { name: spec, version: None },
+        }
+    }
+
+    /// Returns `true` if this specification matches the given package.
+    pub(crate) fn matches(&self, package: &Package) -> bool {
+        glob::matches(self.name, &package.name)
+            && self.version.map_or(true, |version| {
+                package
+                    .version
+                    .strip_prefix(version)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '-', '+']))
+            })
+    }
+}
+
 impl Package {
     fn from_value(mut value: Value, cargo_version: u32) -> ParseResult<(PackageId, Self)> {
         let map = value.as_object_mut().ok_or("packages")?;
@@ -298,12 +380,17 @@ impl Package {
         let id = map.remove_string("id")?;
         Ok((id, Self {
             name: map.remove_string("name")?,
-            // version: map.remove_string("version")?,
+            version: map.remove_string("version")?,
             dependencies: map
                 .remove_array("dependencies")?
                 .into_iter()
                 .map(Dependency::from_value)
                 .collect::<Result<_, _>>()?,
+            targets: map
+                .remove_array("targets")?
+                .into_iter()
+                .map(Target::from_value)
+                .collect::<Result<_, _>>()?,
             features: map
                 .remove_object("features")?
                 .into_iter()
@@ -318,9 +405,11 @@ impl Package {
             // This field was added in Rust 1.39.
             publish: if cargo_version >= 39 {
                 // Publishing is unrestricted if null, and forbidden if an empty array.
-                map.remove_nullable("publish", into_array)?.map_or(true, |a| !a.is_empty())
+                map.remove_nullable("publish", into_array)?
+                    .map(|a| a.into_iter().map(|v| into_string(v).ok_or("publish")).collect())
+                    .transpose()?
             } else {
-                true
+                None
             },
             // This field was added in Rust 1.58.
             rust_version: if cargo_version >= 58 {
@@ -334,6 +423,123 @@ impl Package {
     pub(crate) fn optional_deps(&self) -> impl Iterator<Item = &str> + '_ {
         self.dependencies.iter().filter_map(Dependency::as_feature)
     }
+
+    /// Returns `true` if this package has a library or binary target.
+    pub(crate) fn has_lib_or_bin(&self) -> bool {
+        self.targets.iter().any(Target::is_lib_or_bin)
+    }
+
+    pub(crate) fn has_dev_deps(&self) -> bool {
+        self.dependencies.iter().any(|d| d.kind.as_deref() == Some("dev"))
+    }
+
+    /// Returns the package names of optional dependencies activated by the given features.
+    pub(crate) fn activated_optional_deps<'a>(
+        &'a self,
+        features: impl IntoIterator<Item = &'a str>,
+    ) -> BTreeSet<&'a str> {
+        let mut activated = BTreeSet::new();
+        let mut activate = |name: &str| {
+            if let Some(d) = self.dependencies.iter().find(|d| d.as_feature() == Some(name)) {
+                activated.insert(&*d.name);
+            }
+        };
+        let mut seen = BTreeSet::new();
+        let mut stack: Vec<&str> = features.into_iter().collect();
+        while let Some(f) = stack.pop() {
+            if !seen.insert(f) {
+                continue;
+            }
+            if let Some(dep) = f.strip_prefix("dep:") {
+                activate(dep);
+            } else if let Some((dep, _)) = f.split_once('/') {
+                // `dep?/feature` does not activate `dep`
+                if !dep.ends_with('?') {
+                    activate(dep);
+                    stack.push(dep);
+                }
+            } else if let Some(v) = self.features.get(f) {
+                stack.extend(v.iter().map(String::as_str));
+            } else {
+                // implicit feature of an optional dependency
+                activate(f);
+            }
+        }
+        activated
+    }
+}
+
+/// Returns the names of the packages reachable from the workspace members in
+/// the dependency graph resolved by `cmd` (`cargo metadata` without --no-deps).
+///
+/// Unlike `Cargo.lock` and `resolve.nodes`, which contain every optional
+/// dependency whether it is activated or not, the edges of the graph only
+/// include the activated ones.
+pub(crate) fn resolved_packages(
+    cmd: &mut ProcessBuilder<'_>,
+    cargo_version: u32,
+) -> Result<BTreeSet<String>> {
+    let json = read_metadata(cmd)?;
+    let map = serde_json::from_str(&json)
+        .with_context(|| format!("failed to parse output from {cmd}"))?;
+    let metadata = Metadata::from_obj(map, cargo_version)
+        .map_err(|s| format_err!("failed to parse `{s}` field from metadata"))?;
+    let mut resolved = BTreeSet::new();
+    let mut seen = HashSet::new();
+    let mut stack: Vec<_> = metadata.workspace_members.iter().collect();
+    while let Some(id) = stack.pop() {
+        if !seen.insert(id) {
+            continue;
+        }
+        resolved.insert(metadata.packages[id].name.clone());
+        if let Some(node) = metadata.resolve.nodes.get(id) {
+            stack.extend(node.deps.iter().map(|dep| &dep.pkg));
+        }
+    }
+    Ok(resolved)
+}
+
+/// A single target (lib, bin, example, ...) provided by a crate.
+pub(crate) struct Target {
+    /// The name of the target.
+    pub(crate) name: String,
+    /// Kind of target ("bin", "example", "test", "bench", "lib", "custom-build", ...).
+    pub(crate) kind: Vec<String>,
+    /// Features required to build this target.
+    pub(crate) required_features: Vec<String>,
+}
+
+impl Target {
+    fn from_value(mut value: Value) -> ParseResult<Self> {
+        let map = value.as_object_mut().ok_or("targets")?;
+
+        Ok(Self {
+            name: map.remove_string("name")?,
+            kind: map
+                .remove_array("kind")?
+                .into_iter()
+                .map(into_string)
+                .collect::<Option<_>>()
+                .ok_or("kind")?,
+            // This field is omitted if the target has no required features.
+            required_features: map
+                .remove("required-features")
+                .and_then(into_array)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|v| into_string(v).ok_or("required-features"))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn is_lib_or_bin(&self) -> bool {
+        self.kind.iter().any(|kind| {
+            matches!(
+                &**kind,
+                "lib" | "rlib" | "dylib" | "cdylib" | "staticlib" | "proc-macro" | "bin"
+            )
+        })
+    }
 }
 
 /// A dependency of the main crate.
@@ -344,6 +550,8 @@ pub(crate) struct Dependency {
     // pub(crate) req: String,
     /// Whether or not this is an optional dependency.
     pub(crate) optional: bool,
+    /// The dependency kind ("dev", "build", or `None` for a normal dependency).
+    pub(crate) kind: Option<String>,
     // TODO: support this
     // /// The target platform for the dependency.
     // /// This is `None` if it is not a target dependency.
@@ -354,6 +562,10 @@ pub(crate) struct Dependency {
     ///
     /// This is always `None` if running with a version of Cargo older than 1.26.
     pub(crate) rename: Option<String>,
+    /// The path of the dependency if it is a path dependency.
+    ///
+    /// This is always `None` if running with a version of Cargo older than 1.51.
+    pub(crate) path: Option<PathBuf>,
 }
 
 impl Dependency {
@@ -364,8 +576,11 @@ impl Dependency {
             name: map.remove_string("name")?,
             // req: map.remove_string("req")?,
             optional: map.get("optional").and_then(Value::as_bool).ok_or("optional")?,
+            kind: map.remove_nullable("kind", into_string)?,
             // This field was added in Rust 1.26.
             rename: map.remove_nullable("rename", into_string)?,
+            // This field was added in Rust 1.51.
+            path: map.remove("path").and_then(into_string),
         })
     }
 
@@ -1,16 +1,21 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
 use std::{
+    collections::BTreeMap,
     env,
     ffi::{OsStr, OsString},
     fmt,
-    path::Path,
-    process::{Command, ExitStatus, Output},
-    rc::Rc,
+    fs::File,
+    io::{self, BufRead as _, Read, Write as _},
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Output, Stdio},
     str,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
 };
 
-use anyhow::{Context as _, Error, Result};
+use anyhow::{format_err, Context as _, Error, Result};
 
 use crate::{term, Context, PackageId};
 
@@ -27,16 +32,20 @@ macro_rules! cmd {
 // A builder for an external process, inspired by https://github.com/rust-lang/cargo/blob/0.47.0/src/cargo/util/process_builder.rs
 //
 // The fields will be expanded in the following order:
-//   <program> <leading_args> <propagated_leading_args> <arg> [--features <features>] [ -- <propagated_trailing_args> ]
+//   <program> <leading_args> <propagated_leading_args> <arg> [--features <features>] [ -- <propagated_trailing_args> <clippy_args> ]
 #[derive(Clone)]
 #[must_use]
 pub(crate) struct ProcessBuilder<'a> {
+    /// The command to run the program through (--command-prefix).
+    command_prefix: Vec<String>,
     /// The program to execute.
-    program: Rc<OsStr>,
+    program: Arc<OsStr>,
     /// A list of arguments to pass to the program (until '--').
     propagated_leading_args: &'a [String],
     /// A list of arguments to pass to the program (after '--').
     trailing_args: &'a [String],
+    /// A list of arguments to pass to clippy (after `trailing_args`, --clippy-args).
+    clippy_args: &'a [String],
 
     /// A list of arguments to pass to the program (between `program` and 'propagated_leading_args').
     leading_args: Vec<String>,
@@ -46,6 +55,12 @@ pub(crate) struct ProcessBuilder<'a> {
     /// This list always has a trailing comma if it is not empty.
     // cargo less than Rust 1.38 cannot handle multiple '--features' flags, so it creates another String.
     features: String,
+    /// Environment variables to set for the program.
+    env: BTreeMap<String, OsString>,
+    /// The time limit after which the program is killed (--timeout).
+    timeout: Option<Duration>,
+    /// The file to which the output of the program is also written (--log-dir).
+    log_file: Option<PathBuf>,
     pub(crate) strip_program_path: bool,
 }
 
@@ -53,12 +68,17 @@ impl<'a> ProcessBuilder<'a> {
     /// Creates a new `ProcessBuilder`.
     pub(crate) fn new(program: impl Into<OsString>) -> Self {
         Self {
+            command_prefix: vec![],
             program: program.into().into(),
             propagated_leading_args: &[],
             trailing_args: &[],
+            clippy_args: &[],
             leading_args: vec![],
             args: vec![],
             features: String::new(),
+            env: BTreeMap::new(),
+            timeout: None,
+            log_file: None,
             strip_program_path: false,
         }
     }
@@ -78,15 +98,70 @@ impl<'a> ProcessBuilder<'a> {
         self
     }
 
+    /// Sets an environment variable to pass to the program.
+    pub(crate) fn env(&mut self, key: impl Into<String>, val: impl Into<OsString>) -> &mut Self {
+        self.env.insert(key.into(), val.into());
+        self
+    }
+
+    /// Sets multiple environment variables to pass to the program.
+    pub(crate) fn envs(
+        &mut self,
+        vars: impl IntoIterator<Item = (impl Into<String>, impl Into<OsString>)>,
+    ) -> &mut Self {
+        self.env.extend(vars.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Sets the command to run the program through, e.g., `timeout 600`.
+    pub(crate) fn command_prefix(&mut self, prefix: &[String]) -> &mut Self {
+        self.command_prefix = prefix.to_vec();
+        self
+    }
+
+    /// Sets the time limit after which the program is killed.
+    pub(crate) fn timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the file to which the output of the program is also written.
+    pub(crate) fn log_file(&mut self, path: Option<PathBuf>) -> &mut Self {
+        self.log_file = path;
+        self
+    }
+
     /// Adds an argument to the leading arguments list.
     pub(crate) fn leading_arg(&mut self, arg: impl Into<String>) -> &mut Self {
         self.leading_args.push(arg.into());
         self
     }
 
+    /// Replaces the arguments propagated from the command line (see `apply_context`).
+    pub(crate) fn propagated_leading_args(&mut self, args: &'a [String]) -> &mut Self {
+        self.propagated_leading_args = args;
+        self
+    }
+
+    /// Replaces the arguments after `--` propagated from the command line (see `apply_context`).
+    pub(crate) fn trailing_args(&mut self, args: &'a [String]) -> &mut Self {
+        self.trailing_args = args;
+        self
+    }
+
+    /// Sets the arguments passed to clippy (--clippy-args), which should be empty
+    /// if the subcommand is not clippy.
+    pub(crate) fn clippy_args(&mut self, args: &'a [String]) -> &mut Self {
+        self.clippy_args = args;
+        self
+    }
+
     pub(crate) fn apply_context(&mut self, cx: &'a Context) -> &mut Self {
         self.propagated_leading_args = &cx.leading_args;
         self.trailing_args = &cx.trailing_args;
+        if cx.subcommand.as_deref() == Some("clippy") {
+            self.clippy_args = &cx.clippy_args;
+        }
         self
     }
 
@@ -113,6 +188,23 @@ impl<'a> ProcessBuilder<'a> {
         }
     }
 
+    /// Gets the list of features.
+    pub(crate) fn features(&self) -> impl Iterator<Item = &str> {
+        self.features.split(',').filter(|f| !f.is_empty())
+    }
+
+    /// Returns `true` if the specified argument has been added by `arg` or `args`.
+    pub(crate) fn has_arg(&self, arg: &str) -> bool {
+        self.args.iter().any(|a| a == arg)
+    }
+
+    /// Gets the comma-separated features list sorted alphabetically, for display.
+    pub(crate) fn sorted_features(&self) -> String {
+        let mut features: Vec<_> = self.features().collect();
+        features.sort_unstable();
+        features.join(",")
+    }
+
     /// Gets the comma-separated features list
     fn get_features(&self) -> &str {
         // drop a trailing comma if it is not empty.
@@ -122,9 +214,18 @@ impl<'a> ProcessBuilder<'a> {
     /// Executes a process, waiting for completion, and mapping non-zero exit
     /// status to an error.
     pub(crate) fn run(&mut self) -> Result<()> {
-        let status = self.build().status().with_context(|| {
-            process_error(format!("could not execute process {self:#}"), None, None)
-        })?;
+        let status = match (&self.log_file, self.timeout) {
+            (Some(path), _) => self.run_tee(path)?,
+            (None, Some(_)) => {
+                let mut child = self.build().spawn().with_context(|| {
+                    process_error(format!("could not execute process {self:#}"), None, None)
+                })?;
+                self.wait(&mut child, || {})?
+            }
+            (None, None) => self.build().status().with_context(|| {
+                process_error(format!("could not execute process {self:#}"), None, None)
+            })?,
+        };
         if status.success() {
             Ok(())
         } else {
@@ -136,6 +237,126 @@ impl<'a> ProcessBuilder<'a> {
         }
     }
 
+    /// Executes a process with its stdio output forwarded line by line to the
+    /// inherited stdio, calling `tick` periodically while waiting for
+    /// completion, and mapping non-zero exit status to an error.
+    ///
+    /// `tick` may draw the progress bar, which is cleared before each line is
+    /// written so that the output of the process does not interleave with it.
+    pub(crate) fn run_with_progress(&mut self, tick: impl FnMut()) -> Result<()> {
+        let file = match &self.log_file {
+            Some(path) => Some(Arc::new(Mutex::new(open_log_file(path)?))),
+            None => None,
+        };
+        let mut child =
+            self.build().stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().with_context(
+                || process_error(format!("could not execute process {self:#}"), None, None),
+            )?;
+        let stdout = forward_lines(child.stdout.take().unwrap(), io::stdout(), file.clone());
+        let stderr = forward_lines(child.stderr.take().unwrap(), io::stderr(), file);
+        let status = self.wait(&mut child, tick);
+        let _ = stdout.join();
+        let _ = stderr.join();
+        let status = status?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(self.status_error(status))
+        }
+    }
+
+    /// Executes a process with its stdio output captured, calling `tick`
+    /// periodically while waiting for completion, and returning the captured
+    /// output regardless of the exit status.
+    pub(crate) fn run_with_captured_output(&mut self, mut tick: impl FnMut()) -> Result<Output> {
+        let mut child =
+            self.build().stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().with_context(
+                || process_error(format!("could not execute process {self:#}"), None, None),
+            )?;
+        let stdout = read_to_end(child.stdout.take().unwrap());
+        let stderr = read_to_end(child.stderr.take().unwrap());
+        let status = self.wait(&mut child, &mut tick)?;
+        let output = Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        };
+        if let Some(path) = &self.log_file {
+            let mut file = open_log_file(path)?;
+            file.write_all(&output.stdout)
+                .and_then(|()| file.write_all(&output.stderr))
+                .with_context(|| format!("failed to write to {}", path.display()))?;
+        }
+        Ok(output)
+    }
+
+    /// Executes a process with its stdio output written to both the inherited
+    /// stdio and the file at `path`, waiting for completion.
+    fn run_tee(&self, path: &Path) -> Result<ExitStatus> {
+        let file = Arc::new(Mutex::new(open_log_file(path)?));
+        let mut child =
+            self.build().stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().with_context(
+                || process_error(format!("could not execute process {self:#}"), None, None),
+            )?;
+        let stdout = tee(child.stdout.take().unwrap(), io::stdout(), file.clone());
+        let stderr = tee(child.stderr.take().unwrap(), io::stderr(), file);
+        let status = self.wait(&mut child, || {});
+        let _ = stdout.join();
+        let _ = stderr.join();
+        status
+    }
+
+    /// Appends the program and arguments to the file at `path` instead of
+    /// executing the process, as a fake cargo for tests.
+    #[cfg(debug_assertions)]
+    pub(crate) fn record(&self, path: &Path) -> Result<()> {
+        let cmd = self.build();
+        let mut line =
+            Path::new(cmd.get_program()).file_stem().unwrap().to_string_lossy().into_owned();
+        for arg in cmd.get_args() {
+            line.push(' ');
+            line.push_str(&arg.to_string_lossy());
+        }
+        line.push('\n');
+        open_log_file(path)?
+            .write_all(line.as_bytes())
+            .with_context(|| format!("failed to write to {}", path.display()))
+    }
+
+    /// Returns the error for the process exiting with non-zero `status`,
+    /// without its output.
+    pub(crate) fn status_error(&self, status: ExitStatus) -> Error {
+        process_error(format!("process didn't exit successfully: {self:#}"), Some(status), None)
+    }
+
+    /// Waits for `child` to exit, calling `tick` periodically, and kills it if
+    /// it runs longer than the timeout.
+    fn wait(&self, child: &mut Child, mut tick: impl FnMut()) -> Result<ExitStatus> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(status);
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                kill(child);
+                return Err(format_err!(
+                    "process timed out after {}s: {self:#}",
+                    self.timeout.unwrap().as_secs()
+                ));
+            }
+            tick();
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    /// Executes a process, captures its stdio output, returning the captured
+    /// output regardless of the exit status.
+    pub(crate) fn output(&mut self) -> Result<Output> {
+        self.build().output().with_context(|| {
+            process_error(format!("could not execute process {self:#}"), None, None)
+        })
+    }
+
     /// Executes a process, captures its stdio output, returning the captured
     /// output, or an error if non-zero exit status.
     pub(crate) fn run_with_output(&mut self) -> Result<Output> {
@@ -165,8 +386,24 @@ impl<'a> ProcessBuilder<'a> {
     }
 
     fn build(&self) -> Command {
-        let mut cmd = Command::new(&*self.program);
+        let mut cmd = match self.command_prefix.split_first() {
+            Some((program, args)) => {
+                let mut cmd = Command::new(program);
+                cmd.args(args);
+                cmd.arg(&*self.program);
+                cmd
+            }
+            None => Command::new(&*self.program),
+        };
+        // Run the program in its own process group so that the processes it
+        // spawns are killed together with it on timeout, see `kill`.
+        #[cfg(unix)]
+        if self.timeout.is_some() {
+            use std::os::unix::process::CommandExt as _;
+            cmd.process_group(0);
+        }
 
+        cmd.envs(&self.env);
         cmd.args(&*self.leading_args);
         cmd.args(self.propagated_leading_args);
         cmd.args(&self.args);
@@ -174,9 +411,10 @@ impl<'a> ProcessBuilder<'a> {
             cmd.arg("--features");
             cmd.arg(self.get_features());
         }
-        if !self.trailing_args.is_empty() {
+        if !self.trailing_args.is_empty() || !self.clippy_args.is_empty() {
             cmd.arg("--");
             cmd.args(self.trailing_args);
+            cmd.args(self.clippy_args);
         }
 
         cmd
@@ -187,6 +425,10 @@ impl fmt::Display for ProcessBuilder<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str("`")?;
 
+        for arg in &self.command_prefix {
+            write!(f, "{arg} ")?;
+        }
+
         if !self.strip_program_path && (f.alternate() || term::verbose()) {
             f.write_str(&self.program.to_string_lossy())?;
         } else {
@@ -219,12 +461,12 @@ impl fmt::Display for ProcessBuilder<'_> {
         }
 
         if !self.features.is_empty() {
-            write!(f, " --features {}", self.get_features())?;
+            write!(f, " --features {}", self.sorted_features())?;
         }
 
-        if !self.trailing_args.is_empty() {
+        if !self.trailing_args.is_empty() || !self.clippy_args.is_empty() {
             f.write_str(" --")?;
-            for arg in self.trailing_args {
+            for arg in self.trailing_args.iter().chain(self.clippy_args) {
                 write!(f, " {arg}")?;
             }
         }
@@ -233,6 +475,79 @@ impl fmt::Display for ProcessBuilder<'_> {
     }
 }
 
+/// Opens the file for --log-dir, appending to it if it already exists (e.g.,
+/// when the same feature combination is run with multiple subcommands).
+fn open_log_file(path: &Path) -> Result<File> {
+    File::options()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open {}", path.display()))
+}
+
+fn tee(
+    mut reader: impl Read + Send + 'static,
+    mut writer: impl io::Write + Send + 'static,
+    file: Arc<Mutex<File>>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buf = [0; 8192];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            let _ = writer.write_all(&buf[..n]);
+            let _ = writer.flush();
+            let _ = file.lock().unwrap().write_all(&buf[..n]);
+        }
+    })
+}
+
+/// Kills `child` and, on Unix, the other processes of its process group.
+fn kill(child: &mut Child) {
+    // The child may have exited just now, so ignore the errors.
+    #[cfg(unix)]
+    {
+        use rustix::process::{kill_process_group, Pid, Signal};
+        let _ = kill_process_group(Pid::from_child(child), Signal::KILL);
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Like [`tee`], but writes the output of `reader` one line at a time above the
+/// progress bar.
+fn forward_lines(
+    reader: impl Read + Send + 'static,
+    mut writer: impl io::Write + Send + 'static,
+    file: Option<Arc<Mutex<File>>>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut reader = io::BufReader::new(reader);
+        let mut buf = vec![];
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            term::write_above_progress_bar(&mut writer, &buf);
+            if let Some(file) = &file {
+                let _ = file.lock().unwrap().write_all(&buf);
+            }
+        }
+    })
+}
+
+fn read_to_end(mut reader: impl Read + Send + 'static) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = vec![];
+        let _ = reader.read_to_end(&mut buf);
+        buf
+    })
+}
+
 // Based on https://github.com/rust-lang/cargo/blob/0.47.0/src/cargo/util/errors.rs
 /// Creates a new process error.
 ///
@@ -1,35 +1,56 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
 use std::{
+    fs::OpenOptions,
+    io::{self, Write as _},
     mem,
-    path::PathBuf,
+    path::{Component, Path, PathBuf},
+    process,
     sync::{Arc, Mutex},
 };
 
-use anyhow::Result;
+use anyhow::{bail, Context as _, Result};
 use slab::Slab;
 
-use crate::{fs, term};
+use crate::{fs, term, ExitCode};
+
+/// The name of the lock file created in the workspace root while the manifests
+/// are modified.
+const LOCK_FILE: &str = ".cargo-hack.lock";
 
 #[derive(Clone)]
 pub(crate) struct Manager {
     // A flag that indicates restore is needed.
     needs_restore: bool,
+    /// The directory to write the original manifests to (--backup-manifests).
+    backup_dir: Option<PathBuf>,
+    /// Keep the backups even if the manifests have been restored (--keep-backups).
+    keep_backups: bool,
     /// Information on files that need to be restored.
     files: Arc<Mutex<Slab<File>>>,
+    /// Path to the lock file held by this process, and the file on which the
+    /// lock is held.
+    lock: Arc<Mutex<Option<(PathBuf, std::fs::File)>>>,
 }
 
 impl Manager {
-    pub(crate) fn new(needs_restore: bool) -> Self {
-        let this = Self { needs_restore, files: Arc::new(Mutex::new(Slab::new())) };
+    pub(crate) fn new(
+        needs_restore: bool,
+        backup_dir: Option<PathBuf>,
+        keep_backups: bool,
+    ) -> Self {
+        let this = Self {
+            needs_restore,
+            backup_dir,
+            keep_backups,
+            files: Arc::new(Mutex::new(Slab::new())),
+            lock: Arc::new(Mutex::new(None)),
+        };
 
         let cloned = this.clone();
         ctrlc::set_handler(move || {
             cloned.restore_all();
-            if term::error() {
-                std::process::exit(1)
-            }
-            std::process::exit(0)
+            ExitCode::Interrupted.exit()
         })
         .unwrap();
 
@@ -45,20 +66,86 @@ impl Manager {
         self.register_always(text.into(), path.into())
     }
 
+    /// Registers the given manifest of the workspace at `workspace_root` like
+    /// [`register`](Self::register).
+    ///
+    /// If --backup-manifests is used, this also writes the original text of the
+    /// manifest to `<DIR>/<path>.bak`, where `<path>` is the path of the manifest
+    /// relative to the workspace root, which is removed when the manifest is
+    /// restored unless --keep-backups is used.
+    pub(crate) fn register_manifest(
+        &self,
+        text: impl Into<String>,
+        path: impl Into<PathBuf>,
+        workspace_root: &Path,
+    ) -> Result<Handle<'_>> {
+        let text = text.into();
+        let path = path.into();
+        let backup = match &self.backup_dir {
+            Some(dir) => Some(write_backup(dir, &path, workspace_root, &text)?),
+            None => None,
+        };
+        let handle = self.register(text, path);
+        if let (Some((_, key)), Some(backup)) = (handle.0, backup) {
+            if !self.keep_backups {
+                self.files.lock().unwrap()[key].backup = Some(backup);
+            }
+        }
+        Ok(handle)
+    }
+
     /// Registers the given path regardless of the value of `needs_restore`.
     pub(crate) fn register_always(
         &self,
         text: impl Into<String>,
         path: impl Into<PathBuf>,
     ) -> Handle<'_> {
+        let path = path.into();
+        // Restore the file a symbolic link points to rather than the path of the
+        // link, so that the link is never replaced by a regular file.
+        let path = path.canonicalize().unwrap_or(path);
         let mut files = self.files.lock().unwrap();
         let entry = files.vacant_entry();
         let key = entry.key();
-        entry.insert(File { text: text.into(), path: path.into() });
+        entry.insert(File { text: text.into(), path, backup: None });
 
         Handle(Some((self, key)))
     }
 
+    /// Acquires the lock that prevents other cargo-hack processes from modifying
+    /// the manifests of the given workspace at the same time.
+    ///
+    /// The lock is held by the OS on a file created in the workspace root, so it
+    /// is released even if this process is killed, and a lock file left behind
+    /// by such a process does not block later runs. The file is removed when the
+    /// returned handle is dropped (or the process is interrupted).
+    pub(crate) fn lock(&self, workspace_root: &Path) -> Result<LockHandle<'_>> {
+        let path = workspace_root.join(LOCK_FILE);
+        let Some(mut file) =
+            try_lock(&path).with_context(|| format!("failed to lock {}", path.display()))?
+        else {
+            bail!("another cargo-hack process is modifying manifests in this workspace")
+        };
+        // Record the process ID to help identify the owner of the lock.
+        let res = file
+            .set_len(0)
+            .and_then(|()| writeln!(file, "{}", process::id()))
+            .with_context(|| format!("failed to write to {}", path.display()));
+        *self.lock.lock().unwrap() = Some((path, file));
+        let handle = LockHandle(self);
+        res?;
+        Ok(handle)
+    }
+
+    fn unlock(&self) -> Result<()> {
+        if let Some((path, file)) = self.lock.lock().unwrap().take() {
+            // Remove the file before releasing the lock, see `try_lock`.
+            fs::remove_file(path)?;
+            drop(file);
+        }
+        Ok(())
+    }
+
     fn restore(&self, key: usize) -> Result<()> {
         let mut files = self.files.lock().unwrap();
         if let Some(file) = files.try_remove(key) {
@@ -76,14 +163,65 @@ impl Manager {
                 }
             }
         }
+        drop(files);
+        if let Err(e) = self.unlock() {
+            error!("{e:#}");
+        }
     }
 }
 
+/// Opens the lock file at `path` and locks it, returning `None` if another
+/// process holds the lock.
+#[cfg(unix)]
+fn try_lock(path: &Path) -> io::Result<Option<std::fs::File>> {
+    use rustix::{fs::FlockOperation, io::Errno};
+    loop {
+        let file = OpenOptions::new().write(true).create(true).truncate(false).open(path)?;
+        match rustix::fs::flock(&file, FlockOperation::NonBlockingLockExclusive) {
+            Ok(()) => {}
+            Err(Errno::WOULDBLOCK) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        // The process that held the lock may have removed the file after it was
+        // opened here, in which case the lock does not protect anything.
+        if same_file::Handle::from_path(path).ok()
+            == Some(same_file::Handle::from_file(file.try_clone()?)?)
+        {
+            return Ok(Some(file));
+        }
+    }
+}
+#[cfg(windows)]
+fn try_lock(path: &Path) -> io::Result<Option<std::fs::File>> {
+    use std::os::windows::fs::OpenOptionsExt as _;
+    const FILE_SHARE_DELETE: u32 = 0x4;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    // Opening the file without sharing read or write access fails while another
+    // process has it open. Deletion is shared so that the holder can remove it.
+    match OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .share_mode(FILE_SHARE_DELETE)
+        .open(path)
+    {
+        Ok(file) => Ok(Some(file)),
+        Err(e) if e.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+#[cfg(not(any(unix, windows)))]
+fn try_lock(path: &Path) -> io::Result<Option<std::fs::File>> {
+    OpenOptions::new().write(true).create(true).truncate(false).open(path).map(Some)
+}
+
 struct File {
     /// The original text of this file.
     text: String,
     /// Path to this file.
     path: PathBuf,
+    /// Path to the backup of this file, which is removed after restored.
+    backup: Option<PathBuf>,
 }
 
 impl File {
@@ -91,10 +229,44 @@ impl File {
         if term::verbose() {
             info!("restoring {}", self.path.display());
         }
-        fs::write(&self.path, &self.text)
+        fs::write(&self.path, &self.text)?;
+        if let Some(backup) = &self.backup {
+            fs::remove_file(backup)?;
+        }
+        Ok(())
     }
 }
 
+/// Writes the original text of the manifest at `manifest_path` to the backup
+/// directory, and returns the path to the backup.
+fn write_backup(
+    dir: &Path,
+    manifest_path: &Path,
+    workspace_root: &Path,
+    text: &str,
+) -> Result<PathBuf> {
+    // Mirror the layout of the workspace, so that the backups of different
+    // manifests never have the same path. Members outside the workspace root
+    // are placed by their path without the root and parent components.
+    let relative: PathBuf = manifest_path
+        .strip_prefix(workspace_root)
+        .unwrap_or(manifest_path)
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect();
+    let mut path = dir.join(relative).into_os_string();
+    path.push(".bak");
+    let path = PathBuf::from(path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if term::verbose() {
+        info!("writing backup of {} to {}", manifest_path.display(), path.display());
+    }
+    fs::write(&path, text)?;
+    Ok(path)
+}
+
 #[must_use]
 pub(crate) struct Handle<'a>(Option<(&'a Manager, usize)>);
 
@@ -114,3 +286,14 @@ impl Drop for Handle<'_> {
         }
     }
 }
+
+#[must_use]
+pub(crate) struct LockHandle<'a>(&'a Manager);
+
+impl Drop for LockHandle<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.unlock() {
+            error!("{e:#}");
+        }
+    }
+}
@@ -0,0 +1,93 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// State of the last run, used by --resume.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{format_err, Context as _, Result};
+use serde_json::{json, Value};
+
+use crate::{fs, Kind, PackageRuns};
+
+/// The path of the file that records the state of the last run, relative to the workspace root.
+const LAST_RUN: &str = ".cargo-hack/last-run.json";
+
+pub(crate) struct LastRun {
+    pub(crate) path: PathBuf,
+    /// The plan of the run. It is used to detect whether the plan has changed
+    /// since the last run.
+    pub(crate) plan: Vec<String>,
+    /// The 1-based index of the last command that was completed successfully,
+    /// with all commands before it also completed successfully.
+    pub(crate) completed: usize,
+}
+
+impl LastRun {
+    /// Reads the state of the last run. Returns a state with an empty plan if
+    /// there is no last run.
+    pub(crate) fn read(workspace_root: &Path) -> Result<Self> {
+        let path = workspace_root.join(LAST_RUN);
+        if !path.exists() {
+            return Ok(Self { path, plan: vec![], completed: 0 });
+        }
+        let parse = |s: &str| -> Option<(Vec<String>, usize)> {
+            let value: Value = serde_json::from_str(s).ok()?;
+            let plan = value
+                .get("plan")?
+                .as_array()?
+                .iter()
+                .map(|v| v.as_str().map(str::to_owned))
+                .collect::<Option<_>>()?;
+            let completed = usize::try_from(value.get("completed")?.as_u64()?).ok()?;
+            Some((plan, completed))
+        };
+        let (plan, completed) = parse(&fs::read_to_string(&path)?)
+            .ok_or_else(|| format_err!("failed to parse `{}`", path.display()))?;
+        Ok(Self { path, plan, completed })
+    }
+
+    /// Writes the current state to the file.
+    pub(crate) fn write(&self) -> Result<()> {
+        let value = json!({ "plan": self.plan, "completed": self.completed });
+        fs::create_dir_all(self.path.parent().unwrap())?;
+        fs::write(&self.path, value.to_string())
+            .with_context(|| "failed to record the state of the run for --resume")
+    }
+
+    /// Removes the file, as there is nothing to resume.
+    pub(crate) fn remove(&self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the plan of the run: the command and its arguments, and the
+/// feature combinations of each package. The total number of commands is
+/// appended later, once it is known.
+pub(crate) fn plan(cx: &crate::Context, packages: &[PackageRuns<'_>]) -> Vec<String> {
+    let mut args: Vec<&str> = cx.exec.as_deref().into_iter().collect();
+    args.extend(cx.leading_args.iter().map(String::as_str));
+    if !cx.trailing_args.is_empty() {
+        args.push("--");
+        args.extend(cx.trailing_args.iter().map(String::as_str));
+    }
+    let mut plan = vec![args.join(" ")];
+    if !cx.clippy_args.is_empty() {
+        plan.push(format!("clippy args: {}", cx.clippy_args.join(" ")));
+    }
+    if cx.order != crate::Phase::DEFAULT_ORDER {
+        let order: Vec<_> = cx.order.iter().map(ToString::to_string).collect();
+        plan.push(format!("order: {}", order.join(",")));
+    }
+    for pkg in packages {
+        let name = &cx.packages(pkg.id).name;
+        match &pkg.kind {
+            Kind::Normal => plan.push(format!("{name}: default")),
+            Kind::Each { features } => plan.push(format!("{name}: each {features:?}")),
+            Kind::Powerset { features } => plan.push(format!("{name}: powerset {features:?}")),
+        }
+    }
+    plan
+}
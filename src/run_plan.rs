@@ -0,0 +1,140 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Execution of a pre-computed plan (--run-plan), which allows planning and
+// executing the feature combinations on different machines.
+//
+// The plan is a JSON array of invocations:
+//
+// ```json
+// [
+//   {"package": "foo", "features": [], "no-default-features": true},
+//   {"package": "foo", "features": ["a", "b"], "no-default-features": true},
+//   {"package": "foo", "all-features": true}
+// ]
+// ```
+//
+// `features` defaults to an empty array, and `no-default-features` and
+// `all-features` default to `false`.
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, Read as _},
+    path::Path,
+};
+
+use anyhow::{bail, format_err, Context as _, Result};
+use serde_json::Value;
+
+use crate::{
+    context::Context, determine_start, exec_cargo, finish, fs, metadata::PackageId, print_count,
+    summary, KeepGoing, Progress,
+};
+
+struct Invocation<'a> {
+    id: &'a PackageId,
+    features: Vec<String>,
+    no_default_features: bool,
+    all_features: bool,
+}
+
+pub(crate) fn run(cx: &Context, path: &Path) -> Result<()> {
+    let plan = read(cx, path)?;
+    let mut progress = Progress { total: plan.len() * (1 + cx.also.len()), ..Default::default() };
+    if cx.count {
+        let packages: HashSet<_> = plan.iter().map(|inv| inv.id).collect();
+        print_count(progress.total, packages.len());
+        return Ok(());
+    }
+    if let Some(path) = &cx.status_file {
+        progress.status_file = Some(
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?,
+        );
+    }
+    determine_start(cx, &mut progress, None)?;
+
+    let mut line = cx.cargo();
+    line.apply_context(cx);
+    if cx.locked {
+        line.arg("--locked");
+    }
+    for target in &cx.target {
+        line.arg("--target");
+        line.arg(target);
+    }
+    let mut keep_going = KeepGoing::default();
+    for inv in &plan {
+        let package = cx.packages(inv.id);
+        let mut line = line.clone();
+        line.append_features_from_args(cx, inv.id);
+        if !cx.no_manifest_path {
+            line.arg("--manifest-path");
+            line.arg(
+                package
+                    .manifest_path
+                    .strip_prefix(&cx.current_dir)
+                    .unwrap_or(&package.manifest_path),
+            );
+        }
+        if inv.no_default_features {
+            line.arg("--no-default-features");
+        }
+        if inv.all_features {
+            line.arg("--all-features");
+        }
+        line.append_features(&inv.features);
+        if let Err(e) = exec_cargo(cx, inv.id, &mut line, &mut progress, &mut keep_going) {
+            summary::write(cx, &keep_going.results)?;
+            return Err(e);
+        }
+    }
+    finish(cx, &progress, &keep_going)
+}
+
+fn read<'a>(cx: &'a Context, path: &Path) -> Result<Vec<Invocation<'a>>> {
+    let s = if path == Path::new("-") {
+        let mut s = String::new();
+        io::stdin().read_to_string(&mut s).context("failed to read plan from stdin")?;
+        s
+    } else {
+        fs::read_to_string(path)?
+    };
+    parse(cx, &s).with_context(|| format!("failed to parse plan `{}`", path.display()))
+}
+
+fn parse<'a>(cx: &'a Context, s: &str) -> Result<Vec<Invocation<'a>>> {
+    let value: Value = serde_json::from_str(s)?;
+    let invocations = value.as_array().ok_or_else(|| format_err!("expected an array"))?;
+    invocations
+        .iter()
+        .enumerate()
+        .map(|(i, inv)| {
+            let i = i + 1;
+            let invalid = |field: &str| format_err!("invalid `{field}` field in invocation {i}");
+            let inv =
+                inv.as_object().ok_or_else(|| format_err!("invocation {i} is not an object"))?;
+            let name =
+                inv.get("package").and_then(Value::as_str).ok_or_else(|| invalid("package"))?;
+            let Some(id) = cx.workspace_members().find(|id| cx.packages(id).name == name) else {
+                bail!("package `{name}` specified in invocation {i} not found in workspace")
+            };
+            let features = match inv.get("features") {
+                None => vec![],
+                Some(features) => features
+                    .as_array()
+                    .and_then(|a| a.iter().map(|f| f.as_str().map(str::to_owned)).collect())
+                    .ok_or_else(|| invalid("features"))?,
+            };
+            let flag = |field: &str| match inv.get(field) {
+                None => Ok(false),
+                Some(v) => v.as_bool().ok_or_else(|| invalid(field)),
+            };
+            Ok(Invocation {
+                id,
+                features,
+                no_default_features: flag("no-default-features")?,
+                all_features: flag("all-features")?,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Execution of the cargo commands that make up a run: the commands of feature
+// combinations, --check-plan, --clean-per-run, and the `cargo tree` queries of
+// --check-feature-unification.
+//
+// The queries needed to plan the run (`cargo metadata`, `cargo locate-project`,
+// `cargo -vV`, `rustc --print cfg`, ...) are made before the runner is
+// selected and always execute the real programs, since their output cannot be
+// faked without duplicating what cargo would resolve.
+
+use std::process::Output;
+
+use anyhow::Result;
+
+use crate::ProcessBuilder;
+
+/// Runs processes on behalf of cargo-hack.
+pub(crate) trait Runner: Send + Sync {
+    /// Executes `line`, inheriting stdio, see [`ProcessBuilder::run`].
+    fn run(&self, line: &mut ProcessBuilder<'_>) -> Result<()>;
+
+    /// Executes `line` with its output written above the progress bar, see
+    /// [`ProcessBuilder::run_with_progress`].
+    fn run_with_progress(
+        &self,
+        line: &mut ProcessBuilder<'_>,
+        tick: &mut dyn FnMut(),
+    ) -> Result<()>;
+
+    /// Executes `line` with its output captured and returned regardless of
+    /// the exit status, see [`ProcessBuilder::run_with_captured_output`].
+    fn run_with_captured_output(
+        &self,
+        line: &mut ProcessBuilder<'_>,
+        tick: &mut dyn FnMut(),
+    ) -> Result<Output>;
+
+    /// Executes `line` and returns its output, see [`ProcessBuilder::output`].
+    fn output(&self, line: &mut ProcessBuilder<'_>) -> Result<Output>;
+
+    /// Executes `line` and returns its stdout, see [`ProcessBuilder::read`].
+    fn read(&self, line: &mut ProcessBuilder<'_>) -> Result<String>;
+}
+
+/// Returns the runner used by this process.
+///
+/// In debug builds, if `CARGO_HACK_TEST_CARGO` is set, the commands are
+/// recorded to the file it points to instead of being executed. This is only
+/// intended for the tests of cargo-hack itself; release builds ignore the
+/// variable, so that setting it cannot turn a real run into one that silently
+/// succeeds without running anything.
+pub(crate) fn from_env() -> Box<dyn Runner> {
+    #[cfg(debug_assertions)]
+    if let Some(path) = std::env::var_os("CARGO_HACK_TEST_CARGO") {
+        return Box::new(Recorder { path: path.into() });
+    }
+    Box::new(Process)
+}
+
+/// Executes the processes.
+struct Process;
+
+impl Runner for Process {
+    fn run(&self, line: &mut ProcessBuilder<'_>) -> Result<()> {
+        line.run()
+    }
+
+    fn run_with_progress(
+        &self,
+        line: &mut ProcessBuilder<'_>,
+        tick: &mut dyn FnMut(),
+    ) -> Result<()> {
+        line.run_with_progress(tick)
+    }
+
+    fn run_with_captured_output(
+        &self,
+        line: &mut ProcessBuilder<'_>,
+        tick: &mut dyn FnMut(),
+    ) -> Result<Output> {
+        line.run_with_captured_output(tick)
+    }
+
+    fn output(&self, line: &mut ProcessBuilder<'_>) -> Result<Output> {
+        line.output()
+    }
+
+    fn read(&self, line: &mut ProcessBuilder<'_>) -> Result<String> {
+        line.read()
+    }
+}
+
+/// A fake that appends the program and arguments of each process to the file
+/// at `path` instead of executing it, as if the process had succeeded without
+/// any output. Only available in debug builds, see [`from_env`].
+#[cfg(debug_assertions)]
+struct Recorder {
+    path: std::path::PathBuf,
+}
+
+#[cfg(debug_assertions)]
+impl Recorder {
+    fn success(&self, line: &ProcessBuilder<'_>) -> Result<Output> {
+        #[cfg(unix)]
+        use std::os::unix::process::ExitStatusExt as _;
+        #[cfg(windows)]
+        use std::os::windows::process::ExitStatusExt as _;
+
+        line.record(&self.path)?;
+        Ok(Output { status: std::process::ExitStatus::from_raw(0), stdout: vec![], stderr: vec![] })
+    }
+}
+
+#[cfg(debug_assertions)]
+impl Runner for Recorder {
+    fn run(&self, line: &mut ProcessBuilder<'_>) -> Result<()> {
+        line.record(&self.path)
+    }
+
+    fn run_with_progress(
+        &self,
+        line: &mut ProcessBuilder<'_>,
+        _tick: &mut dyn FnMut(),
+    ) -> Result<()> {
+        line.record(&self.path)
+    }
+
+    fn run_with_captured_output(
+        &self,
+        line: &mut ProcessBuilder<'_>,
+        _tick: &mut dyn FnMut(),
+    ) -> Result<Output> {
+        self.success(line)
+    }
+
+    fn output(&self, line: &mut ProcessBuilder<'_>) -> Result<Output> {
+        self.success(line)
+    }
+
+    fn read(&self, line: &mut ProcessBuilder<'_>) -> Result<String> {
+        line.record(&self.path)?;
+        Ok(String::new())
+    }
+}
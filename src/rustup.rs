@@ -1,6 +1,6 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
-use std::str;
+use std::{io, str};
 
 use anyhow::{bail, format_err, Result};
 
@@ -16,18 +16,32 @@ pub(crate) struct Rustup {
 }
 
 impl Rustup {
-    pub(crate) fn new() -> Self {
-        // If failed to determine rustup version, assume the latest version.
-        let version = minor_version()
-            .map_err(|e| {
+    /// Detects rustup, which is required by `flag` to switch toolchains.
+    pub(crate) fn new(flag: &str) -> Result<Self> {
+        let version = match minor_version() {
+            Ok(version) => version,
+            // Otherwise, each `rustup run <toolchain> cargo` invocation fails
+            // with a less obvious error.
+            Err(e) if is_not_found(&e) => {
+                bail!("{flag} requires rustup; install from https://rustup.rs")
+            }
+            Err(e) => {
+                // If failed to determine rustup version, assume the latest version.
                 warn!("unable to determine rustup version; assuming latest stable rustup: {e:#}");
-            })
-            .unwrap_or(u32::MAX);
+                u32::MAX
+            }
+        };
 
-        Self { version }
+        Ok(Self { version })
     }
 }
 
+/// Returns `true` if the error is caused by the command not being found.
+fn is_not_found(e: &anyhow::Error) -> bool {
+    e.chain()
+        .any(|e| e.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::NotFound))
+}
+
 pub(crate) fn version_range(
     range: VersionRange,
     step: u16,
@@ -134,9 +148,7 @@ pub(crate) fn install_toolchain(
 ) -> Result<()> {
     toolchain = toolchain.strip_prefix('+').unwrap_or(toolchain);
 
-    if target.is_empty()
-        && cmd!("rustup", "run", toolchain, "cargo", "--version").run_with_output().is_ok()
-    {
+    if target.is_empty() && is_installed(toolchain) {
         // Do not run `rustup toolchain add` if the toolchain already has installed.
         return Ok(());
     }
@@ -159,6 +171,11 @@ pub(crate) fn install_toolchain(
     }
 }
 
+/// Returns `true` if the toolchain is installed.
+pub(crate) fn is_installed(toolchain: &str) -> bool {
+    cmd!("rustup", "run", toolchain, "cargo", "--version").run_with_output().is_ok()
+}
+
 fn minor_version() -> Result<u32> {
     let mut cmd = cmd!("rustup", "--version");
     let output = cmd.read()?;
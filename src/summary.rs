@@ -0,0 +1,203 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// The summary of the run printed by --summary-format or written to
+// --summary-file, with one row per command. On GitHub Actions, the Markdown
+// summary is also appended to the job summary (--gh-summary, --no-gh-summary).
+//
+// ```text
+// package  features                              result  duration
+// foo      `--no-default-features`               pass    1.20s
+// foo      `--no-default-features --features a`  fail    0.85s
+// ```
+//
+// ```markdown
+// | package | features | result | duration |
+// | --- | --- | --- | --- |
+// | foo | `--no-default-features` | pass | 1.20s |
+// | foo | `--no-default-features --features a` | fail | 0.85s |
+// ```
+
+use std::{fmt::Write as _, fs::File, io::Write as _, path::Path, str::FromStr, time::Duration};
+
+use anyhow::{bail, Context as _, Error, Result};
+
+use crate::{context::Context, fs};
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Format {
+    Text,
+    Markdown,
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "markdown" => Ok(Self::Markdown),
+            other => {
+                bail!("argument for --summary-format must be text or markdown, but found `{other}`")
+            }
+        }
+    }
+}
+
+/// The result of a command run for a feature combination.
+pub(crate) struct CommandResult {
+    pub(crate) package: String,
+    /// The feature flags for display, e.g., `` `--no-default-features --features a` ``.
+    pub(crate) features: String,
+    pub(crate) status: Status,
+    pub(crate) duration: Duration,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Status {
+    Pass,
+    Fail,
+    /// Failed but allowed to fail by --allow-failure.
+    AllowedFailure,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Pass => "pass",
+            Self::Fail => "fail",
+            Self::AllowedFailure => "allowed failure",
+        }
+    }
+}
+
+/// Returns `true` if the results of the commands need to be recorded.
+pub(crate) fn enabled(cx: &Context) -> bool {
+    cx.summary_format.is_some() || cx.gh_summary.is_some()
+}
+
+/// Prints the summary to stdout, or writes it to --summary-file, and appends it
+/// to the GitHub Actions job summary.
+pub(crate) fn write(cx: &Context, results: &[CommandResult]) -> Result<()> {
+    if let Some(path) = &cx.gh_summary {
+        // Do not add an empty table for runs that do not run any command,
+        // e.g., --print-command-list.
+        if !results.is_empty() {
+            append_gh_summary(path, results).with_context(|| {
+                format!("failed to write summary to GITHUB_STEP_SUMMARY (`{}`)", path.display())
+            })?;
+        }
+    }
+    let Some(format) = cx.summary_format else { return Ok(()) };
+    let summary = match format {
+        Format::Text => text(results),
+        Format::Markdown => markdown(results),
+    };
+    match &cx.summary_file {
+        Some(path) => fs::write(path, summary)
+            .with_context(|| format!("failed to write summary to `{}`", path.display())),
+        None => {
+            print!("{summary}");
+            Ok(())
+        }
+    }
+}
+
+fn append_gh_summary(path: &Path, results: &[CommandResult]) -> Result<()> {
+    let failed = results.iter().filter(|r| r.status == Status::Fail).count();
+    let s = if results.len() == 1 { "" } else { "s" };
+    let mut summary =
+        format!("### cargo-hack\n\n{} command{s} run, {failed} failed\n\n", results.len());
+    summary.push_str(&markdown(results));
+    summary.push('\n');
+    let mut file = File::options().create(true).append(true).open(path)?;
+    file.write_all(summary.as_bytes())?;
+    Ok(())
+}
+
+fn text(results: &[CommandResult]) -> String {
+    let rows: Vec<[String; 4]> = results.iter().map(row).collect();
+    let header = ["package", "features", "result", "duration"].map(str::to_owned);
+    let mut widths = header.clone().map(|h| h.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let mut out = String::new();
+    for row in [header].iter().chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+fn markdown(results: &[CommandResult]) -> String {
+    let mut out = String::new();
+    out.push_str("| package | features | result | duration |\n");
+    out.push_str("| --- | --- | --- | --- |\n");
+    for row in results.iter().map(row) {
+        let row = row.map(|cell| cell.replace('|', "\\|"));
+        writeln!(out, "| {} |", row.join(" | ")).unwrap();
+    }
+    out
+}
+
+fn row(result: &CommandResult) -> [String; 4] {
+    [
+        result.package.clone(),
+        result.features.clone(),
+        result.status.as_str().to_owned(),
+        format!("{:.2}s", result.duration.as_secs_f64()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{markdown, text, CommandResult, Status};
+
+    fn results() -> Vec<CommandResult> {
+        vec![
+            CommandResult {
+                package: "foo".to_owned(),
+                features: "`--no-default-features`".to_owned(),
+                status: Status::Pass,
+                duration: Duration::from_millis(1200),
+            },
+            CommandResult {
+                package: "foo".to_owned(),
+                features: "`--no-default-features --features a`".to_owned(),
+                status: Status::Fail,
+                duration: Duration::from_millis(850),
+            },
+        ]
+    }
+
+    #[test]
+    fn summary_text() {
+        assert_eq!(
+            text(&results()),
+            "package  features                              result  duration\n\
+             foo      `--no-default-features`               pass    1.20s\n\
+             foo      `--no-default-features --features a`  fail    0.85s\n"
+        );
+    }
+
+    #[test]
+    fn summary_markdown() {
+        assert_eq!(
+            markdown(&results()),
+            "| package | features | result | duration |\n\
+             | --- | --- | --- | --- |\n\
+             | foo | `--no-default-features` | pass | 1.20s |\n\
+             | foo | `--no-default-features --features a` | fail | 0.85s |\n"
+        );
+    }
+}
@@ -2,13 +2,14 @@
 
 use std::{
     env,
-    io::Write,
+    io::{self, IsTerminal as _, Write},
     str::FromStr,
     sync::atomic::{AtomicBool, AtomicU8, Ordering},
+    time::Duration,
 };
 
 use anyhow::{format_err, Result};
-use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+use termcolor::{Buffer, BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
 
 #[derive(PartialEq)]
 #[repr(u8)]
@@ -40,7 +41,7 @@ impl FromStr for Coloring {
 static COLORING: AtomicU8 = AtomicU8::new(Coloring::AUTO);
 // Errors during argument parsing are returned before set_coloring, so check is_terminal first.
 pub(crate) fn init_coloring() {
-    if !std::io::IsTerminal::is_terminal(&std::io::stderr()) {
+    if !io::stderr().is_terminal() {
         COLORING.store(Coloring::NEVER, Ordering::Relaxed);
     }
 }
@@ -100,18 +101,117 @@ macro_rules! global_flag {
 global_flag!(verbose: bool = AtomicBool::new(false));
 global_flag!(error: bool = AtomicBool::new(false));
 global_flag!(warn: bool = AtomicBool::new(false));
+// --log-format short
+global_flag!(short_log: bool = AtomicBool::new(false));
 
-pub(crate) fn print_status(status: &str, color: Option<Color>) -> StandardStream {
-    let mut stream = StandardStream::stderr(coloring());
-    let _ = stream.set_color(ColorSpec::new().set_bold(true).set_fg(color));
+/// Returns `true` if the progress bar can be rendered: stderr is a terminal
+/// and coloring is not disabled.
+///
+/// In debug builds, `CARGO_HACK_TEST_PROGRESS_BAR` forces the progress bar on,
+/// so that the tests of cargo-hack itself, whose stderr is never a terminal,
+/// can cover it. Release builds ignore the variable.
+pub(crate) fn progress_bar_supported() -> bool {
+    #[cfg(debug_assertions)]
+    if env::var_os("CARGO_HACK_TEST_PROGRESS_BAR").is_some() {
+        return true;
+    }
+    io::stderr().is_terminal() && coloring() != ColorChoice::Never
+}
+
+static PROGRESS_BAR_DRAWN: AtomicBool = AtomicBool::new(false);
+
+/// Draws (or redraws) the progress bar on the current line of stderr.
+pub(crate) fn draw_progress_bar(count: usize, total: usize, msg: &str, elapsed: Duration) {
+    const BAR_WIDTH: usize = 25;
+    let width = env::var("COLUMNS").ok().and_then(|c| c.parse().ok()).unwrap_or(80_usize);
+    let filled =
+        (BAR_WIDTH * count.saturating_sub(1)).checked_div(total).unwrap_or(0).min(BAR_WIDTH);
+    let secs = elapsed.as_secs();
+    let mut line = format!(
+        "[{}>{}] {count}/{total} {:02}:{:02} {msg}",
+        "=".repeat(filled),
+        " ".repeat(BAR_WIDTH - filled),
+        secs / 60,
+        secs % 60
+    );
+    // Do not wrap the line, otherwise the line cannot be cleared correctly.
+    if let Some((i, _)) = line.char_indices().nth(width.saturating_sub(1)) {
+        line.truncate(i);
+    }
+    let mut stderr = io::stderr().lock();
+    let _ = write!(stderr, "\r\x1b[2K{line}");
+    let _ = stderr.flush();
+    PROGRESS_BAR_DRAWN.store(true, Ordering::Relaxed);
+}
+
+/// Clears the progress bar if it is drawn.
+pub(crate) fn clear_progress_bar() {
+    if PROGRESS_BAR_DRAWN.swap(false, Ordering::Relaxed) {
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[2K");
+        let _ = stderr.flush();
+    }
+}
+
+/// Writes `buf` to `writer`, clearing the progress bar first if it is drawn.
+/// The progress bar is redrawn by the next call to [`draw_progress_bar`].
+pub(crate) fn write_above_progress_bar(writer: &mut impl Write, buf: &[u8]) {
+    // Hold the lock of stderr so that the progress bar is not redrawn until
+    // `buf` has been written.
+    let _stderr = io::stderr().lock();
+    clear_progress_bar();
+    let _ = writer.write_all(buf);
+    let _ = writer.flush();
+}
+
+pub(crate) fn print_status(status: &str, color: Option<Color>) -> Status {
+    clear_progress_bar();
+    if short_log() {
+        // A stable prefix without any styling, for log scrapers.
+        let mut stream = Status::new(ColorChoice::Never);
+        let _ = write!(stream, "cargo-hack: {status}: ");
+        return stream;
+    }
+    let mut stream = Status::new(coloring());
+    let _ = stream.buf.set_color(ColorSpec::new().set_bold(true).set_fg(color));
     let _ = write!(stream, "{status}");
-    let _ = stream.set_color(ColorSpec::new().set_bold(true));
+    let _ = stream.buf.set_color(ColorSpec::new().set_bold(true));
     let _ = write!(stream, ":");
-    let _ = stream.reset();
+    let _ = stream.buf.reset();
     let _ = write!(stream, " ");
     stream
 }
 
+/// A status message, written to stderr at once when dropped so that the
+/// messages printed by the jobs of --package-jobs are not interleaved.
+pub(crate) struct Status {
+    writer: BufferWriter,
+    buf: Buffer,
+}
+
+impl Status {
+    fn new(choice: ColorChoice) -> Self {
+        let writer = BufferWriter::stderr(choice);
+        let buf = writer.buffer();
+        Self { writer, buf }
+    }
+}
+
+impl Write for Status {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Status {
+    fn drop(&mut self) {
+        let _ = self.writer.print(&self.buf);
+    }
+}
+
 macro_rules! error {
     ($($msg:expr),* $(,)?) => {{
         use std::io::Write;
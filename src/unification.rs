@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Detection of the features of dependencies that differ between building a
+// package alone and building it as part of the workspace
+// (--check-feature-unification).
+//
+// When multiple workspace members are built together (e.g., by
+// `cargo build --workspace`), cargo unifies the features of their shared
+// dependencies, so a package may compile only because another member enables a
+// feature of a dependency that the package itself forgot to enable. The
+// features resolved in both cases are read from `cargo tree`, which does not
+// build anything:
+//
+// ```text
+// foo v0.1.0 (/path/to/foo)|
+// dep v0.1.0 (/path/to/dep)|a,b
+// ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{bail, Result};
+
+use crate::{context::Context, selected_packages};
+
+/// The features resolved for each package, keyed by `<name> v<version>`.
+type Resolved = BTreeMap<String, BTreeSet<String>>;
+
+pub(crate) fn check(cx: &Context) -> Result<()> {
+    if cx.cargo_version < 44 {
+        bail!("--check-feature-unification requires Cargo 1.44 or later");
+    }
+    let (ids, _) = selected_packages(cx)?;
+    let workspace = resolve(cx, None)?;
+    let mut differ = 0;
+    for &id in &ids {
+        let name = &cx.packages(id).name;
+        info!("resolving features of {name}");
+        let alone = resolve(cx, Some(name))?;
+        let mut found = false;
+        for (package, features) in &alone {
+            let Some(unified) = workspace.get(package) else { continue };
+            let extra: Vec<&str> = unified.difference(features).map(String::as_str).collect();
+            if extra.is_empty() {
+                continue;
+            }
+            found = true;
+            let s = if extra.len() == 1 { "" } else { "s" };
+            warn!(
+                "`{package}` has feature{s} `{}` enabled when {name} is built together with the \
+                 workspace, but not when {name} is built alone",
+                extra.join(",")
+            );
+        }
+        differ += usize::from(found);
+    }
+    let s = |n: usize| if n == 1 { "" } else { "s" };
+    if differ > 0 {
+        bail!(
+            "features resolved for {differ} package{} differ when built together with the \
+             workspace",
+            s(differ)
+        );
+    }
+    info!("no feature unification differences found in {} package{}", ids.len(), s(ids.len()));
+    Ok(())
+}
+
+/// Returns the features resolved when `package` is built alone, or when the
+/// whole workspace is built if `package` is `None`.
+fn resolve(cx: &Context, package: Option<&str>) -> Result<Resolved> {
+    let mut line = cx.cargo();
+    line.arg("tree");
+    line.arg("--manifest-path");
+    line.arg(cx.metadata.workspace_root.join("Cargo.toml"));
+    match package {
+        Some(package) => {
+            line.arg("--package");
+            line.arg(package);
+        }
+        None => {
+            line.arg("--workspace");
+        }
+    }
+    // Dev-dependencies are only unified when testing the package itself.
+    line.args(["--edges", "normal,build", "--prefix", "none", "--format", "{p}|{f}"]);
+    if cx.locked {
+        line.arg("--locked");
+    }
+    for target in &cx.target {
+        line.arg("--target");
+        line.arg(target);
+    }
+    Ok(parse(&cx.runner.read(&mut line)?))
+}
+
+fn parse(output: &str) -> Resolved {
+    let mut resolved = Resolved::new();
+    for l in output.lines() {
+        let Some((package, features)) = l.trim_end_matches(" (*)").split_once('|') else {
+            continue;
+        };
+        // Omit the source, e.g., the path of path dependencies.
+        let package = package.split(' ').take(2).collect::<Vec<_>>().join(" ");
+        // A package built for both the host and the target appears twice, so
+        // merge their features.
+        resolved
+            .entry(package)
+            .or_default()
+            .extend(features.split(',').filter(|f| !f.is_empty()).map(str::to_owned));
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn parse_tree() {
+        let resolved = parse(
+            "foo v0.1.0 (/path/to/foo)|\n\
+             dep v0.1.0 (/path/to/dep)|a,b\n\
+             \n\
+             dep v0.1.0 (/path/to/dep)|a,c (*)\n",
+        );
+        assert_eq!(resolved.len(), 2);
+        assert!(resolved["foo v0.1.0"].is_empty());
+        assert_eq!(resolved["dep v0.1.0"].iter().collect::<Vec<_>>(), ["a", "b", "c"]);
+    }
+}
@@ -24,6 +24,8 @@ pub(crate) fn cargo_bin_exe() -> Command {
     cmd.env_remove("RUSTFLAGS");
     cmd.env_remove("CARGO_TERM_COLOR");
     cmd.env_remove("GITHUB_ACTIONS");
+    // Do not append to the job summary of the CI running the tests.
+    cmd.env_remove("GITHUB_STEP_SUMMARY");
     cmd
 }
 
@@ -74,6 +76,20 @@ pub(crate) fn cargo_hack<O: AsRef<OsStr>>(args: impl AsRef<[O]>) -> Command {
 impl Command {
     #[track_caller]
     pub(crate) fn assert_output(&mut self, test_model: &str, require: Option<u32>) -> AssertOutput {
+        self.assert_output_with(test_model, require, |_| {}, |_| {})
+    }
+
+    /// Like `assert_output`, but calls `setup` with the path of the test project
+    /// before running the command, and `check` after running it (before the
+    /// test project is removed).
+    #[track_caller]
+    pub(crate) fn assert_output_with<F: FnOnce(&Path), G: FnOnce(&Path)>(
+        &mut self,
+        test_model: &str,
+        require: Option<u32>,
+        setup: F,
+        check: G,
+    ) -> AssertOutput {
         match (test_version(), require) {
             (Some(toolchain), Some(require)) if require > toolchain => {
                 return AssertOutput(None);
@@ -81,8 +97,10 @@ impl Command {
             _ => {}
         }
         let (_test_project, cur_dir) = test_project(test_model).unwrap();
+        setup(&cur_dir);
         let output =
-            self.current_dir(cur_dir).output().context("could not execute process").unwrap();
+            self.current_dir(&cur_dir).output().context("could not execute process").unwrap();
+        check(&cur_dir);
         AssertOutput(Some(AssertOutputInner {
             stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
             stderr: String::from_utf8_lossy(&output.stderr)
@@ -109,16 +127,28 @@ impl Command {
         require: Option<u32>,
     ) -> AssertOutput {
         let output = self.assert_output(test_model, require);
-        if let Some(output) = &output.0 {
-            if !output.status.success() {
-                panic!(
-                    "assertion failed: `self.status.success()`:\n\nSTDOUT:\n{0}\n{1}\n{0}\n\nSTDERR:\n{0}\n{2}\n{0}\n",
-                    "-".repeat(60),
-                    output.stdout,
-                    output.stderr,
-                );
-            }
-        }
+        output.assert_success_status();
+        output
+    }
+
+    #[track_caller]
+    pub(crate) fn assert_success_with<F: FnOnce(&Path)>(
+        &mut self,
+        test_model: &str,
+        setup: F,
+    ) -> AssertOutput {
+        self.assert_success_with_check(test_model, setup, |_| {})
+    }
+
+    #[track_caller]
+    pub(crate) fn assert_success_with_check<F: FnOnce(&Path), G: FnOnce(&Path)>(
+        &mut self,
+        test_model: &str,
+        setup: F,
+        check: G,
+    ) -> AssertOutput {
+        let output = self.assert_output_with(test_model, None, setup, check);
+        output.assert_success_status();
         output
     }
 
@@ -134,7 +164,43 @@ impl Command {
         require: Option<u32>,
     ) -> AssertOutput {
         let output = self.assert_output(test_model, require);
-        if let Some(output) = &output.0 {
+        output.assert_failure_status();
+        output
+    }
+
+    #[track_caller]
+    pub(crate) fn assert_failure_with_check<F: FnOnce(&Path), G: FnOnce(&Path)>(
+        &mut self,
+        test_model: &str,
+        setup: F,
+        check: G,
+    ) -> AssertOutput {
+        let output = self.assert_output_with(test_model, None, setup, check);
+        output.assert_failure_status();
+        output
+    }
+}
+
+pub(crate) struct AssertOutput(Option<AssertOutputInner>);
+
+impl AssertOutput {
+    #[track_caller]
+    fn assert_success_status(&self) {
+        if let Some(output) = &self.0 {
+            if !output.status.success() {
+                panic!(
+                    "assertion failed: `self.status.success()`:\n\nSTDOUT:\n{0}\n{1}\n{0}\n\nSTDERR:\n{0}\n{2}\n{0}\n",
+                    "-".repeat(60),
+                    output.stdout,
+                    output.stderr,
+                );
+            }
+        }
+    }
+
+    #[track_caller]
+    fn assert_failure_status(&self) {
+        if let Some(output) = &self.0 {
             if output.status.success() {
                 panic!(
                     "assertion failed: `!self.status.success()`:\n\nSTDOUT:\n{0}\n{1}\n{0}\n\nSTDERR:\n{0}\n{2}\n{0}\n",
@@ -144,12 +210,9 @@ impl Command {
                 );
             }
         }
-        output
     }
 }
 
-pub(crate) struct AssertOutput(Option<AssertOutputInner>);
-
 struct AssertOutputInner {
     stdout: String,
     stderr: String,
@@ -187,6 +250,22 @@ impl AssertOutput {
         self
     }
 
+    /// Asserts whether the process exited with the given exit code.
+    #[track_caller]
+    pub(crate) fn exit_code(&self, code: i32) -> &Self {
+        if let Some(output) = &self.0 {
+            if output.status.code() != Some(code) {
+                panic!(
+                    "assertion failed: `self.status.code() == Some({code})`: {1}\n\nSTDERR:\n{0}\n{2}\n{0}\n",
+                    "-".repeat(60),
+                    output.status,
+                    output.stderr,
+                );
+            }
+        }
+        self
+    }
+
     /// Receives a line(`\n`)-separated list of patterns and asserts whether stdout contains each pattern.
     #[track_caller]
     pub(crate) fn stderr_not_contains(&self, pats: impl AsRef<str>) -> &Self {
@@ -0,0 +1,2 @@
+#[cfg(all(feature = "b", feature = "d"))]
+compile_error!("`b` and `d` features specified");
@@ -0,0 +1,2 @@
+#[cfg(feature = "a")]
+fn unused() {}
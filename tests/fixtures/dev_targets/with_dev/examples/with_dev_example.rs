@@ -0,0 +1,3 @@
+fn main() {
+    let _ = dev_dep::f;
+}
@@ -0,0 +1,10 @@
+#![allow(unknown_lints, unexpected_cfgs)]
+
+#[cfg(all(feature = "a", not(feature_rustflags_a)))]
+compile_error!("feature_rustflags_a is not set");
+#[cfg(all(not(feature = "a"), feature_rustflags_a))]
+compile_error!("feature_rustflags_a is set");
+#[cfg(all(feature = "c", not(feature_rustflags_c)))]
+compile_error!("feature_rustflags_c is not set");
+#[cfg(all(not(feature = "c"), feature_rustflags_c))]
+compile_error!("feature_rustflags_c is set");
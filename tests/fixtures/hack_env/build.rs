@@ -0,0 +1,8 @@
+use std::env;
+
+fn main() {
+    for var in ["CARGO_HACK", "CARGO_HACK_FEATURES", "CARGO_HACK_COMMAND_INDEX", "CARGO_HACK_COMMAND_TOTAL"] {
+        println!("cargo:rerun-if-env-changed={var}");
+        println!("cargo:warning={var}={}", env::var(var).unwrap_or_default());
+    }
+}
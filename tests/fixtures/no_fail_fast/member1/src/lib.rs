@@ -0,0 +1,4 @@
+#[test]
+fn member1_lib_fail() {
+    panic!("member1 lib test failed");
+}
@@ -0,0 +1,4 @@
+#[test]
+fn member1_integration_fail() {
+    panic!("member1 integration test failed");
+}
@@ -0,0 +1,4 @@
+#[test]
+fn member2_lib_fail() {
+    panic!("member2 lib test failed");
+}
@@ -0,0 +1,2 @@
+#[cfg(not(feature = "b"))]
+compile_error!("`b` feature not specified");
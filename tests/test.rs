@@ -6,7 +6,9 @@ mod auxiliary;
 
 use std::{
     env::{self, consts::EXE_SUFFIX},
-    path::MAIN_SEPARATOR,
+    fs,
+    path::{Path, MAIN_SEPARATOR},
+    process::Command,
     sync::Mutex,
 };
 
@@ -28,7 +30,18 @@ fn failures() {
         .assert_failure("real")
         .stderr_contains("no subcommand or valid flag specified");
 
-    cargo_hack(["--all"])
+    cargo_hack(["--all"]).assert_failure("real").stderr_contains(
+        "
+        no subcommand specified, but the following flags require a subcommand: --all
+        help: specify a subcommand such as `check`, e.g., `cargo hack check --all`
+        ",
+    );
+
+    cargo_hack(["--feature-powerset", "--depth", "2", "-p", "real"]).assert_failure("real").stderr_contains(
+        "no subcommand specified, but the following flags require a subcommand: --feature-powerset, --depth, -p",
+    );
+
+    cargo_hack(["--frozen"])
         .assert_failure("real")
         .stderr_contains("no subcommand or valid flag specified");
 
@@ -175,6 +188,18 @@ fn real_ignore_private() {
                     ",
                 );
 
+            // no warnings about the features of skipped private packages
+            cargo_hack([
+                "check",
+                flag,
+                "--each-feature",
+                "--exclude-features=z",
+                "--only-packages-with-feature=a",
+            ])
+            .assert_success("real")
+            .stderr_contains("skipped running on private package `real`")
+            .stderr_not_contains("skipped running on package `real` that does not have feature");
+
             cargo_hack(["check", "--all", flag])
                 .assert_success("real")
                 .stderr_contains(
@@ -270,6 +295,21 @@ fn package() {
         .assert_success("virtual")
         .stderr_contains("running `cargo check` on member1")
         .stderr_not_contains("running `cargo check` on member2");
+
+    // glob
+    cargo_hack(["check", "--package", "member*"])
+        .assert_success("virtual")
+        .stderr_contains(
+            "
+            running `cargo check` on member1
+            running `cargo check` on member2
+            ",
+        )
+        .stderr_not_contains("running `cargo check` on not_find_manifest");
+    cargo_hack(["check", "--package", "member?", "--exclude", "member2", "--workspace"])
+        .assert_success("virtual")
+        .stderr_contains("running `cargo check` on member1")
+        .stderr_not_contains("running `cargo check` on member2");
 }
 
 #[test]
@@ -277,6 +317,120 @@ fn package_no_packages() {
     cargo_hack(["check", "--package", "foo"])
         .assert_failure("virtual")
         .stderr_contains("package ID specification `foo` matched no packages");
+    cargo_hack(["check", "--package", "api-*"])
+        .assert_failure("virtual")
+        .stderr_contains("package pattern `api-*` matched no packages");
+}
+
+#[test]
+fn only_packages_with_feature() {
+    cargo_hack(["check", "--workspace", "--only-packages-with-feature", "f"])
+        .assert_success("virtual")
+        .stderr_contains(
+            "
+            skipped running on package `member1` that does not have feature `f`
+            running `cargo check` on member2 (1/1)
+            ",
+        )
+        .stderr_not_contains("running `cargo check` on member1");
+
+    cargo_hack(["check", "--workspace", "--only-packages-with-feature", "z"])
+        .assert_failure("virtual") // warn
+        .stderr_contains("no package with feature `z` found")
+        .stderr_not_contains("running `cargo check`");
+}
+
+#[test]
+fn changed_since() {
+    // The test projects are copied to a temporary directory that is not a git repository.
+    cargo_hack(["check", "--workspace", "--changed-since", "HEAD"])
+        .assert_failure("virtual")
+        .stderr_contains("failed to find files changed since `HEAD`")
+        .stderr_not_contains("running `cargo check`");
+
+    cargo_hack(["check", "--include-dependents"])
+        .assert_failure("virtual")
+        .stderr_contains("--include-dependents can only be used together with --changed-since");
+}
+
+#[test]
+fn include_dependents() {
+    // a <- b <- c, and d is independent of them.
+    cargo_hack(["check", "--workspace", "--changed-since", "HEAD"])
+        .assert_success_with("changed_since", |dir| commit_and_modify(dir, "b/src/lib.rs"))
+        .stderr_contains(
+            "
+            skipped running on package `a` not changed since `HEAD`
+            skipped running on package `c` not changed since `HEAD`
+            skipped running on package `d` not changed since `HEAD`
+            running `cargo check` on b (1/1)
+            ",
+        );
+
+    cargo_hack(["check", "--workspace", "--changed-since", "HEAD", "--include-dependents"])
+        .assert_success_with("changed_since", |dir| commit_and_modify(dir, "a/src/lib.rs"))
+        .stderr_contains(
+            "
+            skipped running on package `d` that neither changed since `HEAD` nor depends on a changed package
+            running `cargo check` on a (1/3)
+            running `cargo check` on b (2/3)
+            running `cargo check` on c (3/3)
+            ",
+        );
+
+    cargo_hack(["check", "--workspace", "--changed-since", "HEAD", "--include-dependents"])
+        .assert_success_with("changed_since", |dir| commit_and_modify(dir, "b/src/lib.rs"))
+        .stderr_contains(
+            "
+            skipped running on package `a` that neither changed since `HEAD` nor depends on a changed package
+            running `cargo check` on b (1/2)
+            running `cargo check` on c (2/2)
+            ",
+        );
+
+    // Cargo.toml in the root of a virtual workspace affects all members.
+    cargo_hack(["check", "--workspace", "--changed-since", "HEAD", "--include-dependents"])
+        .assert_success_with("changed_since", |dir| commit_and_modify(dir, "Cargo.toml"))
+        .stderr_contains("running `cargo check` on d (4/4)")
+        .stderr_not_contains("skipped running");
+}
+
+/// Makes the test project a git repository with all files committed, and then
+/// modifies `file`.
+fn commit_and_modify(dir: &Path, file: &str) {
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(["-c", "commit.gpgsign=false"])
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    git(&["init", "-q"]);
+    git(&["add", "-A"]);
+    git(&["commit", "-q", "-m", "initial"]);
+    let path = dir.join(file);
+    let contents = fs::read_to_string(&path).unwrap();
+    fs::write(path, contents + "\n").unwrap();
+}
+
+#[test]
+fn package_with_workspace() {
+    cargo_hack(["check", "--workspace", "--package", "member1"])
+        .assert_success("virtual")
+        .stderr_contains("running `cargo check` on member1 (1/1)")
+        .stderr_not_contains("running `cargo check` on member2");
+
+    cargo_hack(["check", "--all", "-p", "member1", "-p", "member2", "--exclude", "member2"])
+        .assert_success("virtual")
+        .stderr_contains("running `cargo check` on member1 (1/1)")
+        .stderr_not_contains("running `cargo check` on member2");
+
+    cargo_hack(["check", "--workspace", "--package", "foo"])
+        .assert_failure("virtual")
+        .stderr_contains("package ID specification `foo` matched no packages");
 }
 
 #[test]
@@ -294,6 +448,26 @@ fn exclude() {
         running `cargo check` on member2
         ",
     );
+
+    // glob
+    cargo_hack(["check", "--all", "--exclude", "member*"])
+        .assert_success("virtual")
+        .stderr_contains("running `cargo check` on not_find_manifest")
+        .stderr_not_contains(
+            "
+            running `cargo check` on member1
+            running `cargo check` on member2
+            ",
+        );
+    cargo_hack(["check", "--all", "--exclude", "member?", "--exclude", "foo-*"])
+        .assert_failure("virtual")
+        .stderr_contains(
+            "
+            excluded package(s) `foo-*` not found in workspace
+            running `cargo check` on not_find_manifest
+            ",
+        )
+        .stderr_not_contains("running `cargo check` on member1");
 }
 
 #[test]
@@ -364,6 +538,19 @@ fn no_dev_deps() {
         restores it when finished
         ",
     );
+
+    // in the root of a virtual workspace
+    cargo_hack(["check", "--no-dev-deps", "--workspace"])
+        .assert_success("virtual")
+        .stderr_contains(
+            "
+        running `cargo check` on member1 (1/3)
+        running `cargo check` on member2 (2/3)
+        ",
+        );
+    cargo_hack(["check", "--no-dev-deps", "--no-private", "--workspace"])
+        .assert_success("virtual")
+        .stderr_contains("running `cargo check` on member1 (1/1)");
 }
 
 #[test]
@@ -374,13 +561,20 @@ fn no_dev_deps_failure() {
         .stderr_contains("--no-dev-deps may not be used together with --remove-dev-deps");
 
     // with options requires dev-deps
-    for flag in
-        &["--example", "--examples", "--test", "--tests", "--bench", "--benches", "--all-targets"]
-    {
-        cargo_hack(["check", "--no-dev-deps", flag])
-            .assert_failure("real")
-            .stderr_contains(format!("--no-dev-deps may not be used together with {flag}"));
+    for flag in &["--examples", "--tests", "--benches", "--all-targets"] {
+        cargo_hack(["check", "--no-dev-deps", flag]).assert_failure("dev_targets").stderr_contains(
+            format!(
+                "--no-dev-deps may not be used together with {flag} because package `with_dev` \
+                 has dev-dependencies"
+            ),
+        );
     }
+    cargo_hack(["check", "--no-dev-deps", "--example", "with_dev_example"])
+        .assert_failure("dev_targets")
+        .stderr_contains(
+            "--no-dev-deps may not be used together with --example with_dev_example because \
+             package `with_dev` has dev-dependencies that example targets may require",
+        );
 
     // with subcommands requires dev-deps
     for subcommand in &["test", "bench"] {
@@ -390,6 +584,36 @@ fn no_dev_deps_failure() {
     }
 }
 
+#[test]
+fn no_dev_deps_dev_targets() {
+    // packages without dev-dependencies
+    cargo_hack(["check", "--no-dev-deps", "--examples", "--package", "without_dev"])
+        .assert_success("dev_targets")
+        .stderr_contains("running `cargo check --examples` on without_dev (1/1)");
+    cargo_hack(["check", "--no-dev-deps", "--tests", "--package", "without_dev"])
+        .assert_success("dev_targets")
+        .stderr_contains("running `cargo check --tests` on without_dev (1/1)");
+
+    // the package with dev-dependencies has no target with that name
+    cargo_hack(["check", "--no-dev-deps", "--example=without_dev_example", "--workspace"])
+        .assert_failure("dev_targets")
+        .stderr_not_contains("--no-dev-deps may not be used together with");
+
+    // --force downgrades the error to a warning
+    cargo_hack(["check", "--no-dev-deps", "--tests", "--package", "with_dev", "--force"])
+        .assert_failure("dev_targets") // warn
+        .stderr_contains(
+            "
+            warning: --no-dev-deps may not be used together with --tests because package \
+            `with_dev` has dev-dependencies; running anyway due to --force
+            running `cargo check --tests` on with_dev (1/1)
+            ",
+        );
+    cargo_hack(["check", "--tests", "--force"])
+        .assert_failure("dev_targets")
+        .stderr_contains("--force can only be used together with --no-dev-deps");
+}
+
 #[test]
 fn remove_dev_deps_failure() {
     // with options requires dev-deps
@@ -436,6 +660,34 @@ fn ignore_unknown_features() {
         ",
     )
     .stderr_not_contains("skipped applying unknown `missing` feature to member2");
+
+    cargo_hack([
+        "check",
+        "--ignore-unknown-features",
+        "--features",
+        "f,missing",
+        "--workspace",
+        "--verbose",
+    ])
+    .assert_success("virtual")
+    .stderr_contains(
+        "
+        package member1: dropped unknown feature(s) `f,missing` specified by --features
+        package member2: dropped unknown feature(s) `missing` specified by --features
+        ",
+    )
+    .stderr_not_contains("none of the features specified by --features");
+
+    // warn
+    cargo_hack(["check", "--ignore-unknown-features", "--features", "missing", "--workspace"])
+        .assert_failure("virtual")
+        .stderr_contains(
+            "
+            none of the features specified by --features (`missing`) were found in the selected \
+            packages, so all of them were ignored by --ignore-unknown-features
+            ",
+        )
+        .stderr_not_contains("dropped unknown feature(s)");
 }
 
 #[test]
@@ -491,6 +743,66 @@ fn each_feature() {
             ",
         )
         .stderr_not_contains("--features a,a");
+
+    // with multiple base features
+    cargo_hack(["check", "--each-feature", "--features", "a,b", "--print-command-list"])
+        .assert_success("real")
+        .stdout_contains(
+            "
+            cargo check --manifest-path Cargo.toml --no-default-features --features a,b
+            cargo check --manifest-path Cargo.toml --no-default-features --features a,b,c
+            cargo check --manifest-path Cargo.toml --no-default-features --features a,b,default
+            cargo check --manifest-path Cargo.toml --no-default-features --all-features --features a,b
+            ",
+        );
+
+    // separated by both space and comma
+    cargo_hack(["check", "--features", "a b,c", "--print-command-list"])
+        .assert_success("real")
+        .stdout_contains("cargo check --manifest-path Cargo.toml --features a,b,c");
+}
+
+#[test]
+#[cfg(debug_assertions)] // CARGO_HACK_TEST_CARGO is ignored in release builds.
+fn each_feature_recorded_commands() {
+    // CARGO_HACK_TEST_CARGO records the commands instead of running them.
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("commands");
+    cargo_hack(["check", "--each-feature", "--order", "default,all,each"])
+        .env("CARGO_HACK_TEST_CARGO", &path)
+        .assert_success("real")
+        .stderr_not_contains("Checking real");
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "cargo check --manifest-path Cargo.toml\n\
+         cargo check --manifest-path Cargo.toml --no-default-features --all-features\n\
+         cargo check --manifest-path Cargo.toml --no-default-features --features a\n\
+         cargo check --manifest-path Cargo.toml --no-default-features --features b\n\
+         cargo check --manifest-path Cargo.toml --no-default-features --features c\n\
+         cargo check --manifest-path Cargo.toml --no-default-features --features default\n"
+    );
+
+    // --check-plan and --clean-per-run go through the same runner.
+    let path = dir.path().join("commands-check-plan");
+    cargo_hack(["check", "--each-feature", "--check-plan", "--clean-per-run"])
+        .env("CARGO_HACK_TEST_CARGO", &path)
+        .assert_success("real");
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "cargo check --message-format=json --manifest-path Cargo.toml --all-features\n\
+         cargo clean --package real\n\
+         cargo check --manifest-path Cargo.toml --no-default-features\n\
+         cargo clean --package real\n\
+         cargo check --manifest-path Cargo.toml --no-default-features --features a\n\
+         cargo clean --package real\n\
+         cargo check --manifest-path Cargo.toml --no-default-features --features b\n\
+         cargo clean --package real\n\
+         cargo check --manifest-path Cargo.toml --no-default-features --features c\n\
+         cargo clean --package real\n\
+         cargo check --manifest-path Cargo.toml --no-default-features --features default\n\
+         cargo clean --package real\n\
+         cargo check --manifest-path Cargo.toml --no-default-features --all-features\n"
+    );
 }
 
 #[test]
@@ -564,6 +876,41 @@ fn feature_powerset_failure() {
         .stderr_contains("--no-default-features may not be used together with --feature-powerset");
 }
 
+#[test]
+fn default_only() {
+    cargo_hack(["check", "--all", "--default-only", "--ignore-private"])
+        .assert_success("virtual")
+        .stderr_contains(
+            "
+            running `cargo check` on member1 (1/1)
+            skipped running on private package `member2`
+            ",
+        )
+        .stderr_not_contains(
+            "
+            running `cargo check` on member2
+            --no-default-features
+            --all-features
+            ",
+        );
+}
+
+#[test]
+fn default_only_failure() {
+    for (flag, msg) in [
+        ("--each-feature", "--default-only may not be used together with --each-feature"),
+        ("--feature-powerset", "--default-only may not be used together with --feature-powerset"),
+        ("--features=a", "--default-only may not be used together with --features"),
+        ("--all-features", "--default-only may not be used together with --all-features"),
+        (
+            "--no-default-features",
+            "--default-only may not be used together with --no-default-features",
+        ),
+    ] {
+        cargo_hack(["check", "--default-only", flag]).assert_failure("real").stderr_contains(msg);
+    }
+}
+
 #[test]
 fn powerset_deduplication() {
     // require Rust 1.34 due to easytime requires it.
@@ -640,7 +987,7 @@ fn powerset_deduplication() {
             running `cargo check --no-default-features --features e` on deduplication (4/7)
             running `cargo check --no-default-features --features c,e` on deduplication (5/7)
             running `cargo check --no-default-features --features b,d` on deduplication (6/7)
-            running `cargo check --no-default-features --features c,b,d` on deduplication (7/7)
+            running `cargo check --no-default-features --features b,c,d` on deduplication (7/7)
             ",
         )
         .stderr_not_contains(
@@ -691,7 +1038,7 @@ fn powerset_deduplication() {
             running `cargo check --no-default-features --features a,member1` on deduplication (7/10)
             running `cargo check --no-default-features --features c,member1` on deduplication (8/10)
             running `cargo check --no-default-features --features b,d` on deduplication (9/10)
-            running `cargo check --no-default-features --features c,b,d` on deduplication (10/10)
+            running `cargo check --no-default-features --features b,c,d` on deduplication (10/10)
             ",
         )
         .stderr_not_contains(
@@ -779,483 +1126,1271 @@ fn feature_powerset_depth() {
             ",
         )
         .stderr_not_contains("--features a,b,c");
-}
-
-#[test]
-fn depth_failure() {
-    cargo_hack(["check", "--each-feature", "--depth", "2"])
-        .assert_failure("real")
-        .stderr_contains("--depth can only be used together with --feature-powerset");
-}
 
-#[test]
-fn powerset_group_features() {
-    cargo_hack(["check", "--feature-powerset", "--group-features", "a,b"])
+    // real has 4 features, so 50% is equivalent to --depth 2.
+    cargo_hack(["check", "--feature-powerset", "--depth", "50%"])
         .assert_success("real")
         .stderr_contains(
             "
-            running `cargo check --no-default-features` on real (1/8)
-            running `cargo check --no-default-features --features c` on real (2/8)
-            running `cargo check --no-default-features --features default` on real (3/8)
-            running `cargo check --no-default-features --features c,default` on real (4/8)
-            running `cargo check --no-default-features --features a,b` on real (5/8)
-            running `cargo check --no-default-features --features c,a,b` on real (6/8)
-            running `cargo check --no-default-features --features default,a,b` on real (7/8)
-            running `cargo check --no-default-features --features c,default,a,b` on real (8/8)
+            running `cargo check --no-default-features --features b,c` on real (7/12)
+            running `cargo check --no-default-features --all-features` on real (12/12)
             ",
         )
-        .stderr_not_contains(
-            "
-            --features a`
-            --features b`
-            ",
-        );
+        .stderr_not_contains("--features a,b,c");
 
-    cargo_hack(["check", "--feature-powerset", "--group-features", "a,b,c"])
+    cargo_hack(["check", "--feature-powerset", "--depth", "max"])
         .assert_success("real")
         .stderr_contains(
             "
-            running `cargo check --no-default-features` on real (1/4)
-            running `cargo check --no-default-features --features default` on real (2/4)
-            running `cargo check --no-default-features --features a,b,c` on real (3/4)
-            running `cargo check --no-default-features --features default,a,b,c` on real (4/4)
+            running `cargo check --no-default-features --features a,b,c` on real (8/16)
+            running `cargo check --no-default-features --features a,b,c,default` on real (16/16)
             ",
         )
-        .stderr_not_contains(
-            "
-            --features a`
-            --features b`
-            --features c`
-            ",
-        );
+        .stderr_not_contains("--all-features");
+}
 
-    // overlapping
-    // TODO: Maybe we should warn this, but allow it for now.
+#[test]
+fn feature_powerset_verbose_size() {
     cargo_hack([
         "check",
         "--feature-powerset",
-        "--group-features",
-        "a,b",
-        "--group-features",
-        "a,c",
+        "--depth",
+        "2",
+        "--verbose",
+        "--print-command-list",
     ])
     .assert_success("real")
-    .stderr_contains(
-        "
-        running `cargo check --no-default-features` on real (1/8)
-        running `cargo check --no-default-features --features default` on real (2/8)
-        running `cargo check --no-default-features --features a,b` on real (3/8)
-        running `cargo check --no-default-features --features default,a,b` on real (4/8)
-        running `cargo check --no-default-features --features a,c` on real (5/8)
-        running `cargo check --no-default-features --features default,a,c` on real (6/8)
-        running `cargo check --no-default-features --features a,b,a,c` on real (7/8)
-        running `cargo check --no-default-features --features default,a,b,a,c` on real (8/8)
-        ",
-    )
-    .stderr_not_contains(
-        "
-        --features a`
-        --features b`
-        --features c`
-        ",
-    );
+    .stderr_contains("package real: 4 features, 10 combinations (depth=2)");
+    cargo_hack(["check", "--feature-powerset", "--verbose", "--print-command-list"])
+        .assert_success("real")
+        .stderr_contains("package real: 4 features, 15 combinations (depth=max)");
+    cargo_hack(["check", "--feature-powerset", "--print-command-list"])
+        .assert_success("real")
+        .stderr_not_contains("combinations (depth=");
 }
 
 #[test]
-fn group_features_failure() {
-    cargo_hack(["check", "--each-feature", "--group-features", "a,b"])
-        .assert_failure("real")
-        .stderr_contains("--group-features can only be used together with --feature-powerset");
-
-    cargo_hack(["check", "--feature-powerset", "--group-features", "a"])
-        .assert_failure("real")
+fn required_features() {
+    cargo_hack(["check", "--example", "ex", "--each-feature"])
+        .assert_success("required_features")
         .stderr_contains(
-            "--group-features requires a list of two or more features separated by space or comma",
+            "
+            skipped running on required_features with `--no-default-features`: target `ex` requires feature(s) `a`
+            running `cargo check --example ex --no-default-features --features a` on required_features (2/4)
+            skipped running on required_features with `--no-default-features --features b`: target `ex` requires feature(s) `a`
+            running `cargo check --example ex --no-default-features --all-features` on required_features (4/4)
+            ",
         );
+
+    // Cargo skips the targets whose required features are not enabled.
+    cargo_hack(["check", "--examples", "--each-feature"])
+        .assert_success("required_features")
+        .stderr_contains("running `cargo check --examples --no-default-features --features b` on required_features (3/4)")
+        .stderr_not_contains("requires feature(s)");
 }
 
 #[test]
-fn include_features() {
-    cargo_hack(["check", "--each-feature", "--include-features", "a,b"])
-        .assert_success("real")
+fn feature_rustflags() {
+    // The library fails to compile unless the cfgs are set exactly when the corresponding features are enabled.
+    cargo_hack(["check", "--feature-powerset"])
+        .assert_success("feature_rustflags")
         .stderr_contains(
             "
-            running `cargo check --no-default-features --features a` on real (1/2)
-            running `cargo check --no-default-features --features b` on real (2/2)
+            running `cargo check --no-default-features --features a,c` on feature_rustflags (5/6)
+            running `cargo check --no-default-features --features b,c` on feature_rustflags (6/6)
             ",
-        )
-        .stderr_not_contains("--features c");
+        );
+    cargo_hack(["check", "--all-features"]).assert_success("feature_rustflags");
+}
 
-    cargo_hack(["check", "--feature-powerset", "--include-features", "a,b"])
-        .assert_success("real")
+#[test]
+fn deny_warnings() {
+    cargo_hack(["check", "--each-feature"]).assert_success("deny_warnings");
+
+    cargo_hack(["check", "--each-feature", "--deny-warnings"])
+        .assert_failure("deny_warnings")
         .stderr_contains(
             "
-            running `cargo check --no-default-features --features a` on real (1/3)
-            running `cargo check --no-default-features --features b` on real (2/3)
-            running `cargo check --no-default-features --features a,b` on real (3/3)
+            running `cargo check --no-default-features` on deny_warnings (1/4)
+            running `cargo check --no-default-features --features a` on deny_warnings (2/4)
+            function `unused` is never used
             ",
+        )
+        .stderr_not_contains("(3/4)");
+
+    // The flag is appended to the existing rustflags.
+    cargo_hack(["check", "--each-feature", "--deny-warnings"])
+        .env("RUSTFLAGS", "-A dead_code")
+        .assert_success("deny_warnings")
+        .stderr_contains(
+            "running `cargo check --no-default-features --all-features` on deny_warnings (4/4)",
         );
-}
 
-#[test]
-fn exclude_features() {
-    cargo_hack(["check", "--each-feature", "--exclude-features", "f"])
-        .assert_success("virtual")
-        .stderr_not_contains("specified feature `f` not found");
+    // Merged with `feature-rustflags`.
+    cargo_hack(["check", "--feature-powerset", "--deny-warnings"])
+        .assert_success("feature_rustflags");
 }
 
 #[test]
-fn exclude_features_failure() {
-    cargo_hack(["check", "--exclude-features", "a"])
+fn depth_failure() {
+    cargo_hack(["check", "--each-feature", "--depth", "2"])
         .assert_failure("real")
-        .stderr_contains(
-            "--exclude-features (--skip) can only be used together with either --each-feature or --feature-powerset",
-        );
+        .stderr_contains("--depth can only be used together with --feature-powerset");
 
-    cargo_hack(["check", "--each-feature", "--exclude-features=a", "--features=a"])
+    cargo_hack(["check", "--feature-powerset", "--depth", "0%"])
         .assert_failure("real")
-        .stderr_contains("feature `a` specified by both --exclude-features and --features");
+        .stderr_contains("percentage for --depth must be between 1% and 100%, but found `0%`");
+
+    cargo_hack(["check", "--feature-powerset", "--depth", "all"])
+        .assert_failure("real")
+        .stderr_contains(
+            "argument for --depth must be `max`, an integer, or a percentage (e.g., `50%`), but found `all`",
+        );
+}
 
+#[test]
+fn max_combinations_per_package() {
     cargo_hack([
         "check",
-        "--each-feature",
-        "--exclude-features=member1",
-        "--optional-deps=member1",
+        "--feature-powerset",
+        "--depth",
+        "2",
+        "--max-combinations-per-package",
+        "3",
     ])
-    .assert_failure("real")
-    .stderr_contains("feature `member1` specified by both --exclude-features and --optional-deps");
+    .assert_success("real")
+    .stderr_contains(
+        "
+            capped feature combinations of package `real` from 10 to 3
+            running `cargo check --no-default-features` on real (1/5)
+            running `cargo check --no-default-features --features a` on real (2/5)
+            running `cargo check --no-default-features --features c` on real (3/5)
+            running `cargo check --no-default-features --features default` on real (4/5)
+            running `cargo check --no-default-features --all-features` on real (5/5)
+            ",
+    );
 
-    cargo_hack(["check", "--feature-powerset", "--exclude-features=a", "--group-features=a,b"])
-        .assert_failure("real")
-        .stderr_contains("feature `a` specified by both --exclude-features and --group-features");
+    // not capped
+    cargo_hack(["check", "--each-feature", "--max-combinations-per-package", "4"])
+        .assert_success("real")
+        .stderr_contains("running `cargo check --no-default-features --all-features` on real (6/6)")
+        .stderr_not_contains("capped feature combinations");
 
-    cargo_hack(["check", "--each-feature", "--exclude-features=a", "--include-features=a,b"])
+    cargo_hack(["check", "--max-combinations-per-package", "3"])
         .assert_failure("real")
-        .stderr_contains("feature `a` specified by both --exclude-features and --include-features");
+        .stderr_contains(
+        "--max-combinations-per-package can only be used together with either --each-feature or \
+         --feature-powerset",
+    );
 
-    cargo_hack(["check", "--each-feature", "--exclude-features=z"])
-        .assert_failure("real") // warn
-        .stderr_contains("specified feature `z` not found in package `real`");
+    cargo_hack(["check", "--feature-powerset", "--max-combinations-per-package", "0"])
+        .assert_failure("real")
+        .stderr_contains(
+            "argument for --max-combinations-per-package must be a positive integer, but found `0`",
+        );
 }
 
 #[test]
-fn each_feature_skip_success() {
-    cargo_hack(["check", "--each-feature", "--exclude-features", "a"])
-        .assert_success("real")
-        .stderr_contains(
-            "
-            running `cargo check --no-default-features` on real (1/4)
-            running `cargo check --no-default-features --features b` on real (2/4)
-            running `cargo check --no-default-features --features c` on real (3/4)
-            running `cargo check --no-default-features --features default` on real (4/4)
-            ",
-        )
-        .stderr_not_contains("--features a");
+fn explain_plan() {
+    cargo_hack([
+        "check",
+        "--feature-powerset",
+        "--explain-plan",
+        "--exclude-features",
+        "c",
+        "--group-features",
+        "a,b",
+        "--mutually-exclusive-features",
+        "a,default",
+    ])
+    .assert_success("real")
+    .stderr_contains(
+        "
+        dropped feature `a` of package `real`: member of a group specified by --group-features
+        dropped feature `b` of package `real`: member of a group specified by --group-features
+        dropped feature `c` of package `real`: excluded by --exclude-features
+        kept feature `default` of package `real`
+        kept feature `a,b` of package `real`
+        skipping combinations of package `real` with more than one of `a,default`: mutually exclusive
+        ",
+    );
 
-    cargo_hack(["check", "--each-feature", "--exclude-features", "a b"])
+    cargo_hack([
+        "check",
+        "--each-feature",
+        "--explain-plan",
+        "--include-features",
+        "a",
+        "--features",
+        "b",
+    ])
+    .assert_success("real")
+    .stderr_contains(
+        "
+        dropped feature `b` of package `real`: always enabled by --features
+        dropped feature `c` of package `real`: not specified by --include-features
+        kept feature `a` of package `real`
+        ",
+    );
+
+    cargo_hack(["check", "--each-feature"])
         .assert_success("real")
+        .stderr_not_contains("kept feature");
+
+    cargo_hack(["check", "--explain-plan"]).assert_failure("real").stderr_contains(
+        "--explain-plan can only be used together with either --each-feature or --feature-powerset",
+    );
+}
+
+#[test]
+fn matrix_file() {
+    cargo_hack(["check", "--each-feature", "--matrix-file", "matrix.toml"])
+        .assert_success("matrix_file")
         .stderr_contains(
             "
-            running `cargo check --no-default-features` on real (1/3)
-            running `cargo check --no-default-features --features c` on real (2/3)
-            running `cargo check --no-default-features --features default` on real (3/3)
+            running `cargo check --no-default-features` on member1 (1/9)
+            running `cargo check --no-default-features --features a` on member1 (2/9)
+            running `cargo check --no-default-features --features a,b` on member1 (3/9)
+            running `cargo check --no-default-features --all-features` on member1 (4/9)
+            running `cargo check --no-default-features --features c` on member2 (8/9)
             ",
         )
         .stderr_not_contains(
             "
-            --features a
-            --features b
+            running `cargo check --no-default-features --features b` on member1
+            running `cargo check --no-default-features --features c` on member1
             ",
         );
 
-    cargo_hack(["check", "--each-feature", "--exclude-features", "a", "--exclude-features", "b"])
-        .assert_success("real")
+    cargo_hack(["check", "--each-feature", "--matrix-file", "unknown-feature.toml"])
+        .assert_failure("matrix_file")
         .stderr_contains(
-            "
-            running `cargo check --no-default-features` on real (1/3)
-            running `cargo check --no-default-features --features c` on real (2/3)
-            running `cargo check --no-default-features --features default` on real (3/3)
-            ",
-        )
-        .stderr_not_contains(
-            "
-            --features a
-            --features b
-            ",
+            "feature `z` specified for package `member1` in `unknown-feature.toml` not found",
+        );
+    cargo_hack(["check", "--each-feature", "--matrix-file", "unknown-package.toml"])
+        .assert_failure("matrix_file")
+        .stderr_contains(
+            "package `foo` specified in `unknown-package.toml` not found in workspace",
+        );
+    cargo_hack(["check", "--each-feature", "--matrix-file", "invalid.toml"])
+        .assert_failure("matrix_file")
+        .stderr_contains(
+            "failed to parse `invalid.toml`: `member1` must be an array of arrays of feature names",
         );
+    cargo_hack(["check", "--matrix-file", "matrix.toml"])
+        .assert_failure("matrix_file")
+        .stderr_contains(
+        "--matrix-file can only be used together with either --each-feature or --feature-powerset",
+    );
 }
 
 #[test]
-fn powerset_skip_success() {
-    cargo_hack(["check", "--feature-powerset", "--exclude-features", "a"])
+fn powerset_group_features() {
+    cargo_hack(["check", "--feature-powerset", "--group-features", "a,b"])
         .assert_success("real")
         .stderr_contains(
             "
             running `cargo check --no-default-features` on real (1/8)
-            running `cargo check --no-default-features --features b` on real (2/8)
-            running `cargo check --no-default-features --features c` on real (3/8)
-            running `cargo check --no-default-features --features b,c` on real (4/8)
-            running `cargo check --no-default-features --features default` on real (5/8)
-            running `cargo check --no-default-features --features b,default` on real (6/8)
-            running `cargo check --no-default-features --features c,default` on real (7/8)
-            running `cargo check --no-default-features --features b,c,default` on real (8/8)
+            running `cargo check --no-default-features --features c` on real (2/8)
+            running `cargo check --no-default-features --features default` on real (3/8)
+            running `cargo check --no-default-features --features c,default` on real (4/8)
+            running `cargo check --no-default-features --features a,b` on real (5/8)
+            running `cargo check --no-default-features --features a,b,c` on real (6/8)
+            running `cargo check --no-default-features --features a,b,default` on real (7/8)
+            running `cargo check --no-default-features --features a,b,c,default` on real (8/8)
             ",
         )
         .stderr_not_contains(
             "
-            --features a
-            --features a,b
-            --features a,c
-            --features a,b,c
+            --features a`
+            --features b`
             ",
         );
-}
 
-#[test]
-fn exclude_features_default() {
-    cargo_hack(["check", "--each-feature", "--exclude-features", "default"])
+    cargo_hack(["check", "--feature-powerset", "--group-features", "a,b,c"])
         .assert_success("real")
-        .stderr_not_contains("running `cargo check` on real")
         .stderr_contains(
             "
             running `cargo check --no-default-features` on real (1/4)
-            running `cargo check --no-default-features --features a` on real (2/4)
-            running `cargo check --no-default-features --features b` on real (3/4)
-            running `cargo check --no-default-features --features c` on real (4/4)
+            running `cargo check --no-default-features --features default` on real (2/4)
+            running `cargo check --no-default-features --features a,b,c` on real (3/4)
+            running `cargo check --no-default-features --features a,b,c,default` on real (4/4)
+            ",
+        )
+        .stderr_not_contains(
+            "
+            --features a`
+            --features b`
+            --features c`
             ",
         );
-}
 
-#[test]
-fn exclude_no_default_features() {
-    cargo_hack(["check", "--each-feature", "--exclude-no-default-features"])
+    // separated by both space and comma
+    cargo_hack(["check", "--feature-powerset", "--group-features", "a b,c"])
         .assert_success("real")
         .stderr_contains(
-            "
-            running `cargo check --no-default-features --features a` on real (1/5)
-            running `cargo check --no-default-features --features b` on real (2/5)
-            running `cargo check --no-default-features --features c` on real (3/5)
-            running `cargo check --no-default-features --features default` on real (4/5)
-            running `cargo check --no-default-features --all-features` on real (5/5)
-            ",
-        )
-        .stderr_not_contains("running `cargo check --no-default-features` on real");
+            "running `cargo check --no-default-features --features a,b,c` on real (3/4)",
+        );
+
+    // overlapping
+    // TODO: Maybe we should warn this, but allow it for now.
+    cargo_hack([
+        "check",
+        "--feature-powerset",
+        "--group-features",
+        "a,b",
+        "--group-features",
+        "a,c",
+    ])
+    .assert_success("real")
+    .stderr_contains(
+        "
+        running `cargo check --no-default-features` on real (1/8)
+        running `cargo check --no-default-features --features default` on real (2/8)
+        running `cargo check --no-default-features --features a,b` on real (3/8)
+        running `cargo check --no-default-features --features a,b,default` on real (4/8)
+        running `cargo check --no-default-features --features a,c` on real (5/8)
+        running `cargo check --no-default-features --features a,c,default` on real (6/8)
+        running `cargo check --no-default-features --features a,a,b,c` on real (7/8)
+        running `cargo check --no-default-features --features a,a,b,c,default` on real (8/8)
+        ",
+    )
+    .stderr_not_contains(
+        "
+        --features a`
+        --features b`
+        --features c`
+        ",
+    );
 }
 
 #[test]
-fn exclude_no_default_features_failure() {
-    cargo_hack(["check", "--exclude-no-default-features"])
+fn group_features_failure() {
+    cargo_hack(["check", "--each-feature", "--group-features", "a,b"])
+        .assert_failure("real")
+        .stderr_contains("--group-features can only be used together with --feature-powerset");
+
+    cargo_hack(["check", "--feature-powerset", "--group-features", "a"])
         .assert_failure("real")
         .stderr_contains(
-            "--exclude-no-default-features can only be used together with either --each-feature or --feature-powerset",
+            "--group-features requires a list of two or more features separated by space or comma",
         );
 }
 
 #[test]
-fn exclude_all_features() {
-    cargo_hack(["check", "--each-feature", "--exclude-all-features"])
+fn include_features() {
+    cargo_hack(["check", "--each-feature", "--include-features", "a,b"])
         .assert_success("real")
         .stderr_contains(
             "
-            running `cargo check --no-default-features` on real (1/5)
-            running `cargo check --no-default-features --features a` on real (2/5)
-            running `cargo check --no-default-features --features b` on real (3/5)
-            running `cargo check --no-default-features --features c` on real (4/5)
-            running `cargo check --no-default-features --features default` on real (5/5)
+            running `cargo check --no-default-features --features a` on real (1/2)
+            running `cargo check --no-default-features --features b` on real (2/2)
             ",
         )
-        .stderr_not_contains("running `cargo check --no-default-features --all-features` on real");
+        .stderr_not_contains(
+            "
+            --features c
+            running `cargo check --no-default-features` on real
+            --all-features
+            ",
+        );
+
+    // --exclude-features removes features from --include-features
+    cargo_hack(["check", "--each-feature", "--include-features", "a,b", "--exclude-features", "b"])
+        .assert_success("real")
+        .stderr_contains("running `cargo check --no-default-features --features a` on real (1/1)")
+        .stderr_not_contains("--features b");
+
+    cargo_hack(["check", "--feature-powerset", "--include-features", "a,b"])
+        .assert_success("real")
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features --features a` on real (1/3)
+            running `cargo check --no-default-features --features b` on real (2/3)
+            running `cargo check --no-default-features --features a,b` on real (3/3)
+            ",
+        )
+        .stderr_not_contains("running `cargo check --no-default-features` on real");
 }
 
 #[test]
-fn exclude_all_features_failure() {
-    cargo_hack(["check", "--exclude-all-features"])
+fn exclude_features() {
+    cargo_hack(["check", "--each-feature", "--exclude-features", "f"])
+        .assert_success("virtual")
+        .stderr_not_contains("specified feature `f` not found");
+}
+
+#[test]
+fn exclude_features_failure() {
+    cargo_hack(["check", "--exclude-features", "a"])
         .assert_failure("real")
         .stderr_contains(
-            "--exclude-all-features can only be used together with either --each-feature or --feature-powerset",
+            "--exclude-features (--skip) can only be used together with either --each-feature or --feature-powerset",
         );
-}
 
-#[test]
-fn each_feature_all() {
-    cargo_hack(["check", "--each-feature", "--workspace"]).assert_success("real").stderr_contains(
-        "
-        running `cargo check --no-default-features` on member1 (1/24)
-        running `cargo check --no-default-features --features a` on member1 (2/24)
-        running `cargo check --no-default-features --features b` on member1 (3/24)
-        running `cargo check --no-default-features --features c` on member1 (4/24)
-        running `cargo check --no-default-features --features default` on member1 (5/24)
-        running `cargo check --no-default-features --all-features` on member1 (6/24)
-        running `cargo check --no-default-features` on member2 (7/24)
-        running `cargo check --no-default-features --features a` on member2 (8/24)
-        running `cargo check --no-default-features --features b` on member2 (9/24)
-        running `cargo check --no-default-features --features c` on member2 (10/24)
-        running `cargo check --no-default-features --features default` on member2 (11/24)
-        running `cargo check --no-default-features --all-features` on member2 (12/24)
-        running `cargo check --no-default-features` on member3 (13/24)
-        running `cargo check --no-default-features --features a` on member3 (14/24)
-        running `cargo check --no-default-features --features b` on member3 (15/24)
-        running `cargo check --no-default-features --features c` on member3 (16/24)
-        running `cargo check --no-default-features --features default` on member3 (17/24)
-        running `cargo check --no-default-features --all-features` on member3 (18/24)
-        running `cargo check --no-default-features` on real (19/24)
-        running `cargo check --no-default-features --features a` on real (20/24)
-        running `cargo check --no-default-features --features b` on real (21/24)
-        running `cargo check --no-default-features --features c` on real (22/24)
-        running `cargo check --no-default-features --features default` on real (23/24)
-        running `cargo check --no-default-features --all-features` on real (24/24)
-        ",
-    );
+    cargo_hack(["check", "--each-feature", "--exclude-features=a", "--features=a"])
+        .assert_failure("real")
+        .stderr_contains("feature `a` specified by both --exclude-features and --features");
+
+    cargo_hack([
+        "check",
+        "--each-feature",
+        "--exclude-features=member1",
+        "--optional-deps=member1",
+    ])
+    .assert_failure("real")
+    .stderr_contains("feature `member1` specified by both --exclude-features and --optional-deps");
+
+    cargo_hack(["check", "--feature-powerset", "--exclude-features=a", "--group-features=a,b"])
+        .assert_failure("real")
+        .stderr_contains("feature `a` specified by both --exclude-features and --group-features");
+
+    cargo_hack(["check", "--each-feature", "--exclude-features=z"])
+        .assert_failure("real") // warn
+        .stderr_contains("specified feature `z` not found in package `real`");
 }
 
 #[test]
-fn include_deps_features() {
-    cargo_hack(["check", "--each-feature", "--include-deps-features"])
-        .assert_success2("powerset_deduplication",  Some(if has_stable_toolchain() { 34 } else { 41 }))
+fn exclude_features_not_found_in_workspace() {
+    // `f` exists only in member2.
+    cargo_hack(["check", "--workspace", "--each-feature", "--exclude-features=f"])
+        .assert_success("virtual")
+        .stderr_not_contains("specified feature `f` not found");
+
+    cargo_hack(["check", "--workspace", "--each-feature", "--exclude-features=f,z"])
+        .assert_failure("virtual") // warn
+        .stderr_contains("specified feature `z` not found in any of the selected packages")
+        .stderr_not_contains("specified feature `f` not found");
+}
+
+#[test]
+fn feature_alias() {
+    cargo_hack([
+        "check",
+        "--each-feature",
+        "--feature-alias",
+        "ab=a,b",
+        "--exclude-features",
+        "@ab,default",
+        "--print-command-list",
+    ])
+    .assert_success("real")
+    .stdout_contains("cargo check --manifest-path Cargo.toml --no-default-features --features c")
+    .stdout_not_contains("--features a");
+    cargo_hack([
+        "check",
+        "--feature-powerset",
+        "--feature-alias",
+        "ab=a b",
+        "--group-features",
+        "@ab",
+        "--print-command-list",
+    ])
+    .assert_success("real")
+    .stdout_contains(
+        "cargo check --manifest-path Cargo.toml --no-default-features --features a,b,c",
+    )
+    .stdout_not_contains("--features a,c");
+
+    cargo_hack(["check", "--each-feature", "--include-features", "@x"])
+        .assert_failure("real")
+        .stderr_contains("feature alias `@x` is not defined by --feature-alias");
+    cargo_hack(["check", "--each-feature", "--feature-alias", "ab"])
+        .assert_failure("real")
         .stderr_contains(
-            "
-            running `cargo check --no-default-features` on deduplication (1/9)
-            running `cargo check --no-default-features --features a` on deduplication (2/9)
-            running `cargo check --no-default-features --features b` on deduplication (3/9)
-            running `cargo check --no-default-features --features c` on deduplication (4/9)
-            running `cargo check --no-default-features --features d` on deduplication (5/9)
-            running `cargo check --no-default-features --features e` on deduplication (6/9)
-            running `cargo check --no-default-features --features easytime/default` on deduplication (7/9)
-            running `cargo check --no-default-features --features easytime/std` on deduplication (8/9)
-            running `cargo check --no-default-features --all-features` on deduplication (9/9)
-            ",
+            "argument for --feature-alias must be in the form NAME=FEATURES, but found `ab`",
         );
+    cargo_hack(["check", "--each-feature", "--feature-alias", "x=a", "--feature-alias", "x=b"])
+        .assert_failure("real")
+        .stderr_contains("feature alias `x` specified multiple times");
 }
 
 #[test]
-fn trailing_args() {
-    cargo_hack(["test", "--", "--ignored"])
+fn all_features_first() {
+    cargo_hack(["check", "--each-feature", "--all-features-first"])
         .assert_success("real")
-        .stderr_contains("running `cargo test -- --ignored` on real")
-        .stdout_contains(
+        .stderr_contains(
             "
-            running 1 test
-            test tests::test_ignored
+            running `cargo check --no-default-features --all-features` on real (1/6)
+            running `cargo check --no-default-features` on real (2/6)
+            running `cargo check --no-default-features --features a` on real (3/6)
+            running `cargo check --no-default-features --features default` on real (6/6)
             ",
         );
+
+    cargo_hack(["check", "--all-features-first"]).assert_failure("real").stderr_contains(
+        "--all-features-first can only be used together with either --each-feature or --feature-powerset",
+    );
 }
 
 #[test]
-fn package_collision() {
-    cargo_hack(["check"]).assert_success("package_collision").stderr_contains(
+fn skip_default() {
+    // real has `default` feature.
+    cargo_hack(["check", "--each-feature", "--skip", "default"])
+        .assert_success("real")
+        .stderr_contains("running `cargo check --no-default-features --features a` on real")
+        .stderr_not_contains("--features default");
+    cargo_hack(["check", "--each-feature", "--order", "default,none,each", "--skip", "default"])
+        .assert_failure("real") // warn
+        .stderr_contains(
+            "phase `default` specified by --order is excluded by --exclude-features default",
+        )
+        .stderr_not_contains(
+            "
+            running `cargo check` on real
+            --features default
+            ",
+        );
+
+    // member1 has no `default` feature.
+    cargo_hack([
+        "check",
+        "--each-feature",
+        "--always-include-default-run",
+        "--skip",
+        "default",
+        "-p",
+        "member1",
+    ])
+    .assert_success("matrix_file")
+    .stderr_contains("running `cargo check --no-default-features` on member1 (1/4)")
+    .stderr_not_contains(
         "
+        specified feature `default` not found
         running `cargo check` on member1
-        running `cargo check` on member2
         ",
     );
 }
 
 #[test]
-fn not_find_manifest() {
-    cargo_hack(["check"])
-        .assert_success("virtual/dir/not_find_manifest")
-        .stderr_not_contains(
+fn only_powerset() {
+    cargo_hack(["check", "--feature-powerset", "--depth", "1", "--only-powerset"])
+        .assert_success("real")
+        .stderr_contains(
             "
-            running `cargo check` on member1
-            running `cargo check` on member2
+            running `cargo check --no-default-features --features a` on real (1/4)
+            running `cargo check --no-default-features --features default` on real (4/4)
             ",
         )
-        .stderr_contains("running `cargo check` on not_find_manifest");
+        .stderr_not_contains(
+            "
+            running `cargo check --no-default-features` on real
+            --all-features
+            ",
+        );
 
-    cargo_hack(["check", "--all"]).assert_success("virtual/dir/not_find_manifest").stderr_contains(
-        "
-        running `cargo check` on member1
-        running `cargo check` on member2
-        running `cargo check` on not_find_manifest
-        ",
+    cargo_hack(["check", "--each-feature", "--only-powerset", "--all-features-first"])
+        .assert_failure("real")
+        .stderr_contains("--only-powerset may not be used together with --all-features-first");
+    cargo_hack(["check", "--only-powerset"]).assert_failure("real").stderr_contains(
+        "--only-powerset can only be used together with either --each-feature or --feature-powerset",
     );
+}
 
-    cargo_hack(["check", "--manifest-path", "dir/not_find_manifest/Cargo.toml"])
-        .assert_success("virtual")
-        .stderr_not_contains(
+#[test]
+fn order() {
+    cargo_hack(["check", "--each-feature", "--order", "default,all,each"])
+        .assert_success("real")
+        .stderr_contains(
             "
-            running `cargo check` on member1
-            running `cargo check` on member2
+            running `cargo check` on real (1/6)
+            running `cargo check --no-default-features --all-features` on real (2/6)
+            running `cargo check --no-default-features --features a` on real (3/6)
+            running `cargo check --no-default-features --features default` on real (6/6)
             ",
         )
-        .stderr_contains("running `cargo check` on not_find_manifest");
+        .stderr_not_contains("running `cargo check --no-default-features` on real");
 
-    cargo_hack(["check", "--all", "--manifest-path", "dir/not_find_manifest/Cargo.toml"])
-        .assert_success("virtual")
+    cargo_hack(["check", "--each-feature", "--order", "all,none", "--count"])
+        .assert_success("real")
+        .stdout_contains("2 combinations across 1 package");
+
+    cargo_hack(["check", "--each-feature", "--order", "none,each", "--exclude-no-default-features"])
+        .assert_failure("real") // warn
+        .stderr_contains(
+            "phase `none` specified by --order is excluded by --exclude-no-default-features",
+        );
+
+    cargo_hack(["check", "--each-feature", "--order", "all,all"])
+        .assert_failure("real")
+        .stderr_contains("phase `all` specified multiple times in --order");
+    cargo_hack(["check", "--each-feature", "--order", "none,every"])
+        .assert_failure("real")
+        .stderr_contains(
+            "argument for --order must be a comma-separated list of default, none, each, and all, but found `every`",
+        );
+    cargo_hack(["check", "--each-feature", "--order", "none", "--all-features-first"])
+        .assert_failure("real")
+        .stderr_contains("--order may not be used together with --all-features-first");
+}
+
+#[test]
+fn always_include_default_run() {
+    // member1 has no `default` feature.
+    cargo_hack(["check", "--each-feature", "--always-include-default-run", "-p", "member1"])
+        .assert_success("matrix_file")
         .stderr_contains(
             "
-            running `cargo check` on member1
-            running `cargo check` on member2
-            running `cargo check` on not_find_manifest
+            running `cargo check` on member1 (1/6)
+            running `cargo check --no-default-features` on member1 (2/6)
+            running `cargo check --no-default-features --all-features` on member1 (6/6)
             ",
         );
+
+    // real has `default` feature, so the run with `--features default` is used.
+    cargo_hack(["check", "--each-feature", "--always-include-default-run"])
+        .assert_success("real")
+        .stderr_contains("running `cargo check --no-default-features --features default` on real")
+        .stderr_not_contains("running `cargo check` on real");
 }
 
 #[test]
-fn optional_deps() {
-    // require Rust 1.31 due to optional_deps uses renamed deps
-    let require = Some(31);
+fn include_features_from_default() {
+    cargo_hack([
+        "check",
+        "--each-feature",
+        "--exclude-features",
+        "default",
+        "--include-features-from-default",
+    ])
+    .assert_success("real")
+    .stderr_contains(
+        "
+        running `cargo check --no-default-features` on real (1/5)
+        running `cargo check --no-default-features --features default` on real (2/5)
+        running `cargo check --no-default-features --features a` on real (3/5)
+        running `cargo check --no-default-features --features b` on real (4/5)
+        running `cargo check --no-default-features --features c` on real (5/5)
+        ",
+    );
 
-    cargo_hack(["run", "--features=real,member2,renamed", "--ignore-unknown-features"])
-        .assert_success2("optional_deps", require)
+    cargo_hack([
+        "check",
+        "--feature-powerset",
+        "--include-features",
+        "a,b",
+        "--include-features-from-default",
+    ])
+    .assert_success("real")
+    .stderr_contains(
+        "
+        running `cargo check --no-default-features --features default` on real (1/4)
+        running `cargo check --no-default-features --features a` on real (2/4)
+        running `cargo check --no-default-features --features b` on real (3/4)
+        running `cargo check --no-default-features --features a,b` on real (4/4)
+        ",
+    );
+
+    // already included
+    cargo_hack(["check", "--each-feature", "--include-features-from-default"])
+        .assert_success("real")
+        .stderr_contains(
+            "running `cargo check --no-default-features --features default` on real (5/6)",
+        );
+
+    cargo_hack(["check", "--include-features-from-default"])
+        .assert_failure("real")
+        .stderr_contains(
+        "--include-features-from-default can only be used together with either --each-feature or \
+         --feature-powerset",
+    );
+}
+
+#[test]
+fn each_feature_skip_success() {
+    cargo_hack(["check", "--each-feature", "--exclude-features", "a"])
+        .assert_success("real")
         .stderr_contains(
             "
-            skipped applying unknown `member2` feature to optional_deps
-            running `cargo run --features real,renamed` on optional_deps
+            running `cargo check --no-default-features` on real (1/4)
+            running `cargo check --no-default-features --features b` on real (2/4)
+            running `cargo check --no-default-features --features c` on real (3/4)
+            running `cargo check --no-default-features --features default` on real (4/4)
             ",
         )
-        .stdout_contains(
+        .stderr_not_contains("--features a");
+
+    cargo_hack(["check", "--each-feature", "--exclude-features", "a b"])
+        .assert_success("real")
+        .stderr_contains(
             "
-            renamed
-            real
+            running `cargo check --no-default-features` on real (1/3)
+            running `cargo check --no-default-features --features c` on real (2/3)
+            running `cargo check --no-default-features --features default` on real (3/3)
             ",
         )
-        .stdout_not_contains(
+        .stderr_not_contains(
             "
-            member3
-            member2
+            --features a
+            --features b
             ",
         );
 
-    cargo_hack(["check", "--each-feature"])
-        .assert_success2("optional_deps", require)
+    cargo_hack(["check", "--each-feature", "--exclude-features", "a b,c"])
+        .assert_success("real")
         .stderr_contains(
             "
-            running `cargo check --no-default-features` on optional_deps (1/2)
-            running `cargo check --no-default-features --all-features` on optional_deps (2/2)
+            running `cargo check --no-default-features` on real (1/2)
+            running `cargo check --no-default-features --features default` on real (2/2)
             ",
         )
         .stderr_not_contains(
             "
-            --features real
-            --features renamed
+            --features a
+            --features b
+            --features c
             ",
         );
 
-    cargo_hack(["check", "--each-feature", "--optional-deps"])
-        .assert_success2("optional_deps", require)
+    cargo_hack(["check", "--each-feature", "--exclude-features", "a", "--exclude-features", "b"])
+        .assert_success("real")
         .stderr_contains(
             "
-            running `cargo check --no-default-features` on optional_deps (1/4)
-            running `cargo check --no-default-features --features real` on optional_deps (2/4)
-            running `cargo check --no-default-features --features renamed` on optional_deps (3/4)
-            running `cargo check --no-default-features --all-features` on optional_deps (4/4)
+            running `cargo check --no-default-features` on real (1/3)
+            running `cargo check --no-default-features --features c` on real (2/3)
+            running `cargo check --no-default-features --features default` on real (3/3)
             ",
-        );
-
-    cargo_hack(["check", "--each-feature", "--optional-deps", "real"])
-        .assert_success2("optional_deps", require)
-        .stderr_contains(
+        )
+        .stderr_not_contains(
             "
-            running `cargo check --no-default-features` on optional_deps (1/3)
-            running `cargo check --no-default-features --features real` on optional_deps (2/3)
-            running `cargo check --no-default-features --all-features` on optional_deps (3/3)
+            --features a
+            --features b
             ",
-        )
-        .stderr_not_contains("--features renamed");
+        );
+}
 
-    cargo_hack(["check", "--each-feature", "--optional-deps=renamed"])
-        .assert_success2("optional_deps", require)
+#[test]
+fn powerset_skip_success() {
+    cargo_hack(["check", "--feature-powerset", "--exclude-features", "a"])
+        .assert_success("real")
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on real (1/8)
+            running `cargo check --no-default-features --features b` on real (2/8)
+            running `cargo check --no-default-features --features c` on real (3/8)
+            running `cargo check --no-default-features --features b,c` on real (4/8)
+            running `cargo check --no-default-features --features default` on real (5/8)
+            running `cargo check --no-default-features --features b,default` on real (6/8)
+            running `cargo check --no-default-features --features c,default` on real (7/8)
+            running `cargo check --no-default-features --features b,c,default` on real (8/8)
+            ",
+        )
+        .stderr_not_contains(
+            "
+            --features a
+            --features a,b
+            --features a,c
+            --features a,b,c
+            ",
+        );
+}
+
+#[test]
+fn exclude_features_default() {
+    cargo_hack(["check", "--each-feature", "--exclude-features", "default"])
+        .assert_success("real")
+        .stderr_not_contains("running `cargo check` on real")
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on real (1/4)
+            running `cargo check --no-default-features --features a` on real (2/4)
+            running `cargo check --no-default-features --features b` on real (3/4)
+            running `cargo check --no-default-features --features c` on real (4/4)
+            ",
+        );
+}
+
+#[test]
+fn exclude_no_default_features() {
+    cargo_hack(["check", "--each-feature", "--exclude-no-default-features"])
+        .assert_success("real")
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features --features a` on real (1/5)
+            running `cargo check --no-default-features --features b` on real (2/5)
+            running `cargo check --no-default-features --features c` on real (3/5)
+            running `cargo check --no-default-features --features default` on real (4/5)
+            running `cargo check --no-default-features --all-features` on real (5/5)
+            ",
+        )
+        .stderr_not_contains("running `cargo check --no-default-features` on real");
+}
+
+#[test]
+fn exclude_no_default_features_failure() {
+    cargo_hack(["check", "--exclude-no-default-features"])
+        .assert_failure("real")
+        .stderr_contains(
+            "--exclude-no-default-features can only be used together with either --each-feature or --feature-powerset",
+        );
+}
+
+#[test]
+fn exclude_all_features() {
+    cargo_hack(["check", "--each-feature", "--exclude-all-features"])
+        .assert_success("real")
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on real (1/5)
+            running `cargo check --no-default-features --features a` on real (2/5)
+            running `cargo check --no-default-features --features b` on real (3/5)
+            running `cargo check --no-default-features --features c` on real (4/5)
+            running `cargo check --no-default-features --features default` on real (5/5)
+            ",
+        )
+        .stderr_not_contains("running `cargo check --no-default-features --all-features` on real");
+}
+
+#[test]
+fn exclude_all_features_failure() {
+    cargo_hack(["check", "--exclude-all-features"])
+        .assert_failure("real")
+        .stderr_contains(
+            "--exclude-all-features can only be used together with either --each-feature or --feature-powerset",
+        );
+}
+
+#[test]
+fn each_feature_all() {
+    cargo_hack(["check", "--each-feature", "--workspace"]).assert_success("real").stderr_contains(
+        "
+        running `cargo check --no-default-features` on member1 (1/24)
+        running `cargo check --no-default-features --features a` on member1 (2/24)
+        running `cargo check --no-default-features --features b` on member1 (3/24)
+        running `cargo check --no-default-features --features c` on member1 (4/24)
+        running `cargo check --no-default-features --features default` on member1 (5/24)
+        running `cargo check --no-default-features --all-features` on member1 (6/24)
+        running `cargo check --no-default-features` on member2 (7/24)
+        running `cargo check --no-default-features --features a` on member2 (8/24)
+        running `cargo check --no-default-features --features b` on member2 (9/24)
+        running `cargo check --no-default-features --features c` on member2 (10/24)
+        running `cargo check --no-default-features --features default` on member2 (11/24)
+        running `cargo check --no-default-features --all-features` on member2 (12/24)
+        running `cargo check --no-default-features` on member3 (13/24)
+        running `cargo check --no-default-features --features a` on member3 (14/24)
+        running `cargo check --no-default-features --features b` on member3 (15/24)
+        running `cargo check --no-default-features --features c` on member3 (16/24)
+        running `cargo check --no-default-features --features default` on member3 (17/24)
+        running `cargo check --no-default-features --all-features` on member3 (18/24)
+        running `cargo check --no-default-features` on real (19/24)
+        running `cargo check --no-default-features --features a` on real (20/24)
+        running `cargo check --no-default-features --features b` on real (21/24)
+        running `cargo check --no-default-features --features c` on real (22/24)
+        running `cargo check --no-default-features --features default` on real (23/24)
+        running `cargo check --no-default-features --all-features` on real (24/24)
+        ",
+    );
+}
+
+#[test]
+fn include_deps_features() {
+    cargo_hack(["check", "--each-feature", "--include-deps-features"])
+        .assert_success2("powerset_deduplication",  Some(if has_stable_toolchain() { 34 } else { 41 }))
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on deduplication (1/9)
+            running `cargo check --no-default-features --features a` on deduplication (2/9)
+            running `cargo check --no-default-features --features b` on deduplication (3/9)
+            running `cargo check --no-default-features --features c` on deduplication (4/9)
+            running `cargo check --no-default-features --features d` on deduplication (5/9)
+            running `cargo check --no-default-features --features e` on deduplication (6/9)
+            running `cargo check --no-default-features --features easytime/default` on deduplication (7/9)
+            running `cargo check --no-default-features --features easytime/std` on deduplication (8/9)
+            running `cargo check --no-default-features --all-features` on deduplication (9/9)
+            ",
+        );
+
+    // easytime/default enables easytime/std
+    cargo_hack([
+        "check",
+        "--each-feature",
+        "--include-deps-features",
+        "--exclude-features",
+        "easytime/std",
+    ])
+    .assert_success2("powerset_deduplication", Some(if has_stable_toolchain() { 34 } else { 41 }))
+    .stderr_contains(
+        "
+        running `cargo check --no-default-features --features e` on deduplication (6/6)
+        ",
+    )
+    .stderr_not_contains("easytime/default");
+}
+
+#[test]
+fn trailing_args() {
+    cargo_hack(["test", "--", "--ignored"])
+        .assert_success("real")
+        .stderr_contains("running `cargo test -- --ignored` on real")
+        .stdout_contains(
+            "
+            running 1 test
+            test tests::test_ignored
+            ",
+        );
+}
+
+#[test]
+fn separator_before_subcommand() {
+    cargo_hack(["--", "check"])
+        .assert_success("real")
+        .stderr_contains("running `cargo check` on real");
+
+    cargo_hack(["--", "test", "--", "--ignored"])
+        .assert_success("real")
+        .stderr_contains("running `cargo test -- --ignored` on real")
+        .stdout_contains(
+            "
+            running 1 test
+            test tests::test_ignored
+            ",
+        );
+
+    cargo_hack(["check", "--print-command-list", "--", "--", "x"])
+        .assert_success("real")
+        .stdout_contains("cargo check --manifest-path Cargo.toml -- -- x");
+    cargo_hack(["--each-feature", "--print-command-list", "--", "check", "--lib", "--", "x"])
+        .assert_success("real")
+        .stdout_contains("cargo check --lib --manifest-path Cargo.toml --no-default-features -- x");
+}
+
+#[test]
+fn package_spec_version() {
+    cargo_hack(["check", "--package", "foo@1.2.3"])
+        .assert_success("package_spec")
+        .stderr_contains("running `cargo check` on foo")
+        .stderr_not_contains("on bar");
+    cargo_hack(["check", "--package", "foo@1.2"])
+        .assert_success("package_spec")
+        .stderr_contains("running `cargo check` on foo");
+    cargo_hack(["check", "--workspace", "--exclude", "foo@1"])
+        .assert_success("package_spec")
+        .stderr_contains("running `cargo check` on bar")
+        .stderr_not_contains("on foo");
+
+    cargo_hack(["check", "--package", "foo@1.2.4"])
+        .assert_failure("package_spec")
+        .stderr_contains("package ID specification `foo@1.2.4` matched no packages");
+    cargo_hack(["check", "--package", "foo@1.20"])
+        .assert_failure("package_spec")
+        .stderr_contains("package ID specification `foo@1.20` matched no packages");
+}
+
+#[test]
+fn command_prefix() {
+    cargo_hack(["check", "--command-prefix", "env CARGO_HACK_TEST_PREFIX=1"])
+        .assert_success("real")
+        .stderr_contains("running `env CARGO_HACK_TEST_PREFIX=1 cargo check` on real");
+    cargo_hack([
+        "check",
+        "--each-feature",
+        "--command-prefix",
+        "timeout 600",
+        "--print-command-list",
+    ])
+    .assert_success("real")
+    .stdout_contains(
+        "
+            timeout 600 cargo check --manifest-path Cargo.toml --no-default-features
+            timeout 600 cargo check --manifest-path Cargo.toml --no-default-features --features a
+            ",
+    );
+
+    cargo_hack(["check", "--command-prefix", "false"])
+        .assert_failure("real")
+        .stderr_contains("running `false cargo check` on real");
+    cargo_hack(["--exec", "true", "--command-prefix", "nice"])
+        .assert_failure("real")
+        .stderr_contains("--exec may not be used together with --command-prefix");
+}
+
+#[test]
+fn timeout() {
+    cargo_hack(["--exec", "sleep 10", "--timeout", "1"])
+        .assert_failure("real")
+        .stderr_contains("process timed out after 1s: `sleep 10`");
+    cargo_hack(["--exec", "sleep 10", "--timeout", "1", "--each-feature", "--keep-going"])
+        .assert_failure("real")
+        .stderr_contains(
+            "
+            failed commands:
+                real:
+                    `sleep 10` with `--no-default-features`
+                    `sleep 10` with `--no-default-features --features a`
+            ",
+        );
+    cargo_hack(["check", "--timeout", "600"]).assert_success("real");
+
+    // The processes spawned by the command are killed too.
+    #[cfg(unix)]
+    cargo_hack(["--exec", "sh spawn.sh", "--timeout", "1"])
+        .assert_failure_with_check(
+            "real",
+            |dir| fs::write(dir.join("spawn.sh"), "(sleep 3; touch grandchild) &\nwait\n").unwrap(),
+            |dir| {
+                std::thread::sleep(std::time::Duration::from_secs(4));
+                assert!(!dir.join("grandchild").exists());
+            },
+        )
+        .stderr_contains("process timed out after 1s: `sh spawn.sh`");
+
+    cargo_hack(["check", "--timeout", "0"])
+        .assert_failure("real")
+        .stderr_contains("argument for --timeout must be a positive integer, but found `0`");
+}
+
+#[test]
+fn check_plan() {
+    cargo_hack(["check", "--check-plan", "--each-feature"]).assert_success("real").stderr_contains(
+        "
+            checking plan on real
+            running `cargo check --no-default-features` on real (1/6)
+            ",
+    );
+
+    cargo_hack(["check", "--check-plan", "--each-feature"])
+        .assert_failure("bisect_features")
+        .exit_code(2)
+        .stderr_contains(
+            "
+            checking plan on bisect_features
+            plan check failed on package `bisect_features`
+            `b` and `d` features specified
+            ",
+        )
+        .stderr_not_contains("running `cargo check");
+
+    cargo_hack(["check", "--check-plan", "--count"])
+        .assert_failure("real")
+        .stderr_contains("--check-plan may not be used together with --count");
+}
+
+#[test]
+#[cfg(debug_assertions)] // CARGO_HACK_TEST_CARGO is ignored in release builds.
+fn check_plan_args() {
+    // The plan is checked with the same arguments as the run.
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("commands");
+    cargo_hack([
+        "test",
+        "--check-plan",
+        "--each-feature",
+        "--features",
+        "a",
+        "-Z",
+        "no-index-update",
+        "--cargo-arg=--jobs=1",
+        "--",
+        "--nocapture",
+    ])
+    .env("CARGO_HACK_TEST_CARGO", &path)
+    .assert_success("real");
+    assert!(fs::read_to_string(&path).unwrap().starts_with(
+        "cargo check -Z no-index-update --jobs=1 --message-format=json --manifest-path Cargo.toml --all-features --features a\n\
+         cargo test -Z no-index-update --jobs=1 --manifest-path Cargo.toml --no-default-features --features a -- --nocapture\n"
+    ));
+
+    // Without --all-features, the features of the largest combination are enabled.
+    let path = dir.path().join("commands-exclude");
+    cargo_hack(["check", "--check-plan", "--each-feature", "--exclude-features", "c"])
+        .env("CARGO_HACK_TEST_CARGO", &path)
+        .assert_success("real");
+    assert!(fs::read_to_string(&path).unwrap().starts_with(
+        "cargo check --message-format=json --manifest-path Cargo.toml --features a,b,default\n"
+    ));
+}
+
+#[test]
+fn package_jobs() {
+    // The indices are the same as when the packages are run one at a time.
+    cargo_hack(["check", "--workspace", "--package-jobs", "2"])
+        .assert_success("virtual")
+        .stderr_contains(
+            "
+            running `cargo check` on member1 (1/3)
+            running `cargo check` on member2 (2/3)
+            running `cargo check` on not_find_manifest (3/3)
+            ",
+        );
+
+    // The failures of all jobs are reported.
+    cargo_hack(["--exec", "false", "--workspace", "--package-jobs", "2", "--keep-going"])
+        .assert_failure("virtual")
+        .stderr_contains(
+            "
+            failed to run 3 commands
+            failed commands:
+                member1:
+                member2:
+                not_find_manifest:
+            ",
+        );
+
+    cargo_hack(["check", "--package-jobs", "0"])
+        .assert_failure("virtual")
+        .stderr_contains("argument for --package-jobs must be a positive integer, but found `0`");
+    cargo_hack(["check", "--package-jobs", "2", "--resume"])
+        .assert_failure("virtual")
+        .stderr_contains("--package-jobs may not be used together with --resume");
+}
+
+#[test]
+fn env() {
+    cargo_hack(["--exec", "printenv CARGO_HACK_TEST_ENV", "--env", "CARGO_HACK_TEST_ENV=a=b"])
+        .assert_success("real")
+        .stdout_contains("a=b");
+    // The variables set by cargo-hack are not overridden.
+    cargo_hack(["--exec", "printenv CARGO_HACK", "--env", "CARGO_HACK=0"])
+        .assert_success("real")
+        .stdout_contains("1");
+
+    cargo_hack(["check", "--env", "CARGO_HACK_TEST_ENV"]).assert_failure("real").stderr_contains(
+        "argument for --env must be in the form KEY=VALUE, but found `CARGO_HACK_TEST_ENV`",
+    );
+    cargo_hack(["check", "--env", "=a"])
+        .assert_failure("real")
+        .stderr_contains("argument for --env must be in the form KEY=VALUE, but found `=a`");
+}
+
+#[test]
+fn package_collision() {
+    cargo_hack(["check"]).assert_success("package_collision").stderr_contains(
+        "
+        running `cargo check` on member1
+        running `cargo check` on member2
+        ",
+    );
+}
+
+#[test]
+fn manifest_path() {
+    // directory
+    cargo_hack(["check", "--manifest-path", "member1"])
+        .assert_success("virtual")
+        .stderr_contains("running `cargo check` on member1")
+        .stderr_not_contains("running `cargo check` on member2");
+
+    cargo_hack(["check", "--manifest-path", "member1/src"])
+        .assert_failure("virtual")
+        .stderr_contains(
+            "manifest path `member1/src` is a directory but does not contain `Cargo.toml`",
+        );
+    cargo_hack(["check", "--manifest-path", "member1/src/lib.rs"])
+        .assert_failure("virtual")
+        .stderr_contains(
+            "the manifest-path must be a path to a Cargo.toml file, but found `member1/src/lib.rs`",
+        );
+    cargo_hack(["check", "--manifest-path", "foo/Cargo.toml"])
+        .assert_failure("virtual")
+        .stderr_contains("manifest path `foo/Cargo.toml` does not exist");
+}
+
+#[test]
+fn not_find_manifest() {
+    cargo_hack(["check"])
+        .assert_success("virtual/dir/not_find_manifest")
+        .stderr_not_contains(
+            "
+            running `cargo check` on member1
+            running `cargo check` on member2
+            ",
+        )
+        .stderr_contains("running `cargo check` on not_find_manifest");
+
+    cargo_hack(["check", "--all"]).assert_success("virtual/dir/not_find_manifest").stderr_contains(
+        "
+        running `cargo check` on member1
+        running `cargo check` on member2
+        running `cargo check` on not_find_manifest
+        ",
+    );
+
+    cargo_hack(["check", "--manifest-path", "dir/not_find_manifest/Cargo.toml"])
+        .assert_success("virtual")
+        .stderr_not_contains(
+            "
+            running `cargo check` on member1
+            running `cargo check` on member2
+            ",
+        )
+        .stderr_contains("running `cargo check` on not_find_manifest");
+
+    cargo_hack(["check", "--all", "--manifest-path", "dir/not_find_manifest/Cargo.toml"])
+        .assert_success("virtual")
+        .stderr_contains(
+            "
+            running `cargo check` on member1
+            running `cargo check` on member2
+            running `cargo check` on not_find_manifest
+            ",
+        );
+}
+
+#[test]
+fn optional_deps() {
+    // require Rust 1.31 due to optional_deps uses renamed deps
+    let require = Some(31);
+
+    cargo_hack(["run", "--features=real,member2,renamed", "--ignore-unknown-features"])
+        .assert_success2("optional_deps", require)
+        .stderr_contains(
+            "
+            skipped applying unknown `member2` feature to optional_deps
+            running `cargo run --features real,renamed` on optional_deps
+            ",
+        )
+        .stdout_contains(
+            "
+            renamed
+            real
+            ",
+        )
+        .stdout_not_contains(
+            "
+            member3
+            member2
+            ",
+        );
+
+    cargo_hack(["check", "--each-feature"])
+        .assert_success2("optional_deps", require)
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on optional_deps (1/2)
+            running `cargo check --no-default-features --all-features` on optional_deps (2/2)
+            ",
+        )
+        .stderr_not_contains(
+            "
+            --features real
+            --features renamed
+            ",
+        );
+
+    cargo_hack(["check", "--each-feature", "--optional-deps"])
+        .assert_success2("optional_deps", require)
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on optional_deps (1/4)
+            running `cargo check --no-default-features --features real` on optional_deps (2/4)
+            running `cargo check --no-default-features --features renamed` on optional_deps (3/4)
+            running `cargo check --no-default-features --all-features` on optional_deps (4/4)
+            ",
+        );
+
+    cargo_hack(["check", "--each-feature", "--optional-deps", "real"])
+        .assert_success2("optional_deps", require)
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on optional_deps (1/3)
+            running `cargo check --no-default-features --features real` on optional_deps (2/3)
+            running `cargo check --no-default-features --all-features` on optional_deps (3/3)
+            ",
+        )
+        .stderr_not_contains("--features renamed");
+
+    cargo_hack(["check", "--each-feature", "--optional-deps=renamed"])
+        .assert_success2("optional_deps", require)
         .stderr_contains(
             "
             running `cargo check --no-default-features` on optional_deps (1/3)
@@ -1263,525 +2398,2123 @@ fn optional_deps() {
             running `cargo check --no-default-features --all-features` on optional_deps (3/3)
             ",
         )
-        .stderr_not_contains("--features real");
+        .stderr_not_contains("--features real");
+
+    // separated by both space and comma
+    cargo_hack(["check", "--each-feature", "--optional-deps", "real, renamed"])
+        .assert_success2("optional_deps", require)
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features --features real` on optional_deps (2/4)
+            running `cargo check --no-default-features --features renamed` on optional_deps (3/4)
+            ",
+        );
+
+    cargo_hack(["check", "--each-feature", "--optional-deps="])
+        .assert_success2("optional_deps", require)
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on optional_deps (1/2)
+            running `cargo check --no-default-features --all-features` on optional_deps (2/2)
+            ",
+        );
+}
+
+#[test]
+fn optional_deps_failure() {
+    cargo_hack(["check", "--optional-deps"])
+        .assert_failure("real")
+        .stderr_contains(
+            "--optional-deps can only be used together with either --each-feature or --feature-powerset",
+        );
+}
+
+#[test]
+fn skip_optional_deps() {
+    // require Rust 1.31 due to optional_deps uses renamed deps
+    let require = Some(31);
+
+    cargo_hack(["check", "--each-feature", "--optional-deps", "--exclude-features", "real"])
+        .assert_success2("optional_deps", require)
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on optional_deps (1/2)
+            running `cargo check --no-default-features --features renamed` on optional_deps (2/2)
+            ",
+        )
+        .stderr_not_contains("--features real");
+}
+
+#[test]
+fn list_separator() {
+    // require Rust 1.31 due to optional_deps uses renamed deps
+    let require = Some(31);
+
+    cargo_hack(["run", "--features='real,renamed'"])
+        .assert_success2("optional_deps", require)
+        .stderr_contains("running `cargo run --features real,renamed` on optional_deps");
+
+    cargo_hack(["run", "--features=\"real,renamed\""])
+        .assert_success2("optional_deps", require)
+        .stderr_contains("running `cargo run --features real,renamed` on optional_deps");
+
+    cargo_hack(["run", "--features=real,renamed"])
+        .assert_success2("optional_deps", require)
+        .stderr_contains("running `cargo run --features real,renamed` on optional_deps");
+
+    cargo_hack(["run", "--features", "real,renamed"])
+        .assert_success2("optional_deps", require)
+        .stderr_contains("running `cargo run --features real,renamed` on optional_deps");
+
+    cargo_hack(["run", "--features='real renamed'"])
+        .assert_success2("optional_deps", require)
+        .stderr_contains("running `cargo run --features real,renamed` on optional_deps");
+
+    cargo_hack(["run", "--features=\"real renamed\""])
+        .assert_success2("optional_deps", require)
+        .stderr_contains("running `cargo run --features real,renamed` on optional_deps");
+
+    cargo_hack(["run", "--features", "real renamed"])
+        .assert_success2("optional_deps", require)
+        .stderr_contains("running `cargo run --features real,renamed` on optional_deps");
+}
+
+#[test]
+fn short_flag() {
+    cargo_hack(["check", "-vvpmember1"]) // same as -v -v -p member1
+        .assert_success("virtual")
+        .stderr_contains(format!(
+            "
+            cargo{EXE_SUFFIX} check -v --manifest-path member1{MAIN_SEPARATOR}Cargo.toml` (1/1)
+            ",
+        ))
+        .stderr_not_contains("member2");
+
+    cargo_hack(["check", "-qpmember1"]) // same as -q -p member1
+        .assert_success("virtual")
+        .stderr_contains("`cargo check -q` on member1 (1/1)")
+        .stderr_not_contains("member2");
+}
+
+#[test]
+fn verbose() {
+    cargo_hack(["check", "--verbose"]).assert_success("virtual").stderr_contains(format!(
+        "
+        cargo{EXE_SUFFIX} check --manifest-path member1{MAIN_SEPARATOR}Cargo.toml` (1/3)
+        cargo{EXE_SUFFIX} check --manifest-path member2{MAIN_SEPARATOR}Cargo.toml` (2/3)
+        cargo{EXE_SUFFIX} check --manifest-path dir{MAIN_SEPARATOR}not_find_manifest{MAIN_SEPARATOR}Cargo.toml` (3/3)
+        ",
+    ));
+
+    // If `-vv` is passed, propagate `-v` to cargo.
+    cargo_hack(["check", "-vv", "-p", "member1"]).assert_success("virtual").stderr_contains(
+        format!(
+            "
+            cargo{EXE_SUFFIX} check -v --manifest-path member1{MAIN_SEPARATOR}Cargo.toml` (1/1)
+            ",
+        ),
+    );
+    cargo_hack(["check", "-vvv", "-p", "member1"]).assert_success("virtual").stderr_contains(
+        format!(
+            "
+            cargo{EXE_SUFFIX} check -vv --manifest-path member1{MAIN_SEPARATOR}Cargo.toml` (1/1)
+            ",
+        ),
+    );
+}
+
+#[test]
+fn propagate() {
+    // --features
+    cargo_hack(["check", "--features", "a"]).assert_success("real").stderr_contains("--features a");
+    cargo_hack(["check", "--features=a"]).assert_success("real").stderr_contains("--features a");
+
+    // --no-default-features
+    cargo_hack(["check", "--no-default-features"])
+        .assert_success("real")
+        .stderr_contains("--no-default-features");
+
+    // --all-features
+    cargo_hack(["check", "--all-features"])
+        .assert_success("real")
+        .stderr_contains("--all-features");
+
+    // --color
+    cargo_hack(["check", "--color", "auto"])
+        .assert_success("real")
+        .stderr_contains("`cargo check --color auto`");
+    cargo_hack(["check", "--color=auto"])
+        .assert_success("real")
+        .stderr_contains("`cargo check --color auto`");
+
+    // --target
+    cargo_hack(["check", "--target", TARGET])
+        .assert_success("real")
+        .stderr_contains(format!("`cargo check --target {TARGET}`"));
+
+    // --verbose does not be propagated
+    cargo_hack(["check", "--verbose"]).assert_success("real").stderr_not_contains("--verbose");
+}
+
+#[test]
+fn default_feature_behavior() {
+    cargo_hack(["run"])
+        .assert_success("default_feature_behavior/has_default")
+        .stdout_contains("has default feature!")
+        .stdout_not_contains("no default feature!");
+
+    cargo_hack(["run", "--no-default-features"])
+        .assert_success("default_feature_behavior/has_default")
+        .stdout_contains("no default feature!")
+        .stdout_not_contains("has default feature!");
+
+    cargo_hack(["run"])
+        .assert_success("default_feature_behavior/no_default")
+        .stdout_contains("no default feature!")
+        .stdout_not_contains("has default feature!");
+
+    cargo_hack(["run", "--no-default-features"])
+        .assert_success("default_feature_behavior/no_default")
+        .stdout_contains("no default feature!")
+        .stdout_not_contains("has default feature!");
+}
+
+#[test]
+fn version_range() {
+    // --version-range requires rustup
+    if !has_rustup() {
+        return;
+    }
+    let _r = RUSTUP_TOOLCHAIN_CHANGES.lock().unwrap();
+
+    cargo_hack(["check", "--version-range", "1.63..=1.64"]).assert_success("real").stderr_contains(
+        "
+        running `rustup run 1.63 cargo check` on real (1/2)
+        running `rustup run 1.64 cargo check` on real (2/2)
+        ",
+    );
+
+    // toolchains older than rust-version are skipped
+    cargo_hack(["check", "--version-range", "1.63..=1.65", "--workspace"])
+        .assert_success("rust-version")
+        .stderr_contains(
+            "
+            skipping member3 on 1.63: older than its rust-version (1.64)
+            skipping real on 1.63, 1.64: older than its rust-version (1.65)
+            running `rustup run 1.65 cargo check` on real
+            ",
+        );
+
+    cargo_hack(["check", "--version-range", "1.63..1.64"])
+        .assert_failure("real") // warn
+        .stderr_contains(
+            "
+            warning: using `..` for inclusive range is deprecated; consider using `1.63..=1.64`
+            running `rustup run 1.63 cargo check` on real (1/2)
+            running `rustup run 1.64 cargo check` on real (2/2)
+            ",
+        );
+
+    cargo_hack(["check", "--version-range", "1.63..=1.64", "--target", TARGET])
+        .assert_success("real")
+        .stderr_contains(format!(
+            "
+            running `rustup run 1.63 cargo check --target {TARGET}` on real (1/2)
+            running `rustup run 1.64 cargo check --target {TARGET}` on real (2/2)
+            ",
+        ));
+
+    cargo_hack(["check", "--version-range", "..=1.64", "--package=member1", "--package=member2"])
+        .assert_success("rust-version")
+        .stderr_contains(
+            "
+            running `rustup run 1.63 cargo check` on member1 (1/4)
+            running `rustup run 1.63 cargo check` on member2 (2/4)
+            running `rustup run 1.64 cargo check` on member1 (3/4)
+            running `rustup run 1.64 cargo check` on member2 (4/4)
+            ",
+        );
+    // Skips `real` because it isn't in range
+    cargo_hack(["check", "--version-range", "..=1.64", "--workspace"])
+        .assert_failure("rust-version") // warn
+        .stderr_contains(
+            "
+            warning: skipping real, rust-version (1.65) is not in specified range (..=1.64)
+            running `rustup run 1.63 cargo check` on member1 (1/5)
+            running `rustup run 1.63 cargo check` on member2 (2/5)
+            running `rustup run 1.64 cargo check` on member1 (3/5)
+            running `rustup run 1.64 cargo check` on member2 (4/5)
+            running `rustup run 1.64 cargo check` on member3 (5/5)
+            ",
+        );
+    cargo_hack([
+        "check",
+        "--version-range",
+        "..=1.66",
+        "--version-step",
+        "2",
+        "--workspace",
+        "--locked",
+    ])
+    .assert_success("rust-version")
+    .stderr_contains(
+        "
+            running `rustup run 1.63 cargo check --locked` on member1 (1/7)
+            running `rustup run 1.63 cargo check --locked` on member2 (2/7)
+            running `rustup run 1.64 cargo check --locked` on member3 (3/7)
+            running `rustup run 1.65 cargo check --locked` on member1 (4/7)
+            running `rustup run 1.65 cargo check --locked` on member2 (5/7)
+            running `rustup run 1.65 cargo check --locked` on member3 (6/7)
+            running `rustup run 1.65 cargo check --locked` on real (7/7)
+            ",
+    );
+}
+
+#[test]
+fn version_range_without_rustup() {
+    let dir = tempfile::tempdir().unwrap();
+    cargo_hack(["check", "--version-range", "1.63..=1.64"])
+        .env("PATH", dir.path())
+        .assert_failure("real")
+        .stderr_contains("--version-range requires rustup; install from https://rustup.rs")
+        .stderr_not_contains("running");
+    cargo_hack(["check", "--rust-version"])
+        .env("PATH", dir.path())
+        .assert_failure("rust-version")
+        .stderr_contains("--rust-version requires rustup; install from https://rustup.rs");
+}
+
+#[test]
+fn print_toolchains() {
+    // --version-range requires rustup
+    if !has_rustup() {
+        return;
+    }
+
+    cargo_hack([
+        "check",
+        "--version-range",
+        "1.63..=1.65",
+        "--version-step",
+        "2",
+        "--package=member1",
+        "--print-toolchains",
+    ])
+    .assert_success("rust-version")
+    .stdout_contains(
+        "
+            1.63
+            1.65
+            ",
+    )
+    .stdout_not_contains("1.64")
+    .stderr_not_contains("running");
+
+    cargo_hack(["check", "--version-range", "1.63..=1.65", "--workspace", "--print-toolchains"])
+        .assert_success("rust-version")
+        .stdout_contains(
+            "
+            1.63: member1, member2
+            1.64: member1, member2, member3
+            1.65: member1, member2, member3, real
+            ",
+        );
+
+    cargo_hack(["check", "--print-toolchains"])
+        .assert_failure("real")
+        .stderr_contains("--print-toolchains can only be used together with either --version-range or --rust-version");
+}
+
+#[test]
+fn install_toolchains() {
+    cargo_hack(["check", "--install-toolchains"]).assert_failure("real").stderr_contains(
+        "--install-toolchains can only be used together with either --version-range or --rust-version",
+    );
+
+    // --version-range requires rustup
+    if !has_rustup() {
+        return;
+    }
+    let _r = RUSTUP_TOOLCHAIN_CHANGES.lock().unwrap();
+
+    cargo_hack(["check", "--version-range", "1.63..=1.64", "--install-toolchains"])
+        .assert_success("real")
+        .stderr_contains(
+            "
+            running `rustup run 1.63 cargo check` on real (1/2)
+            running `rustup run 1.64 cargo check` on real (2/2)
+            ",
+        );
+}
+
+#[test]
+fn rust_version() {
+    // --rust-version requires rustup
+    if !has_rustup() {
+        return;
+    }
+    let _r = RUSTUP_TOOLCHAIN_CHANGES.lock().unwrap();
+
+    cargo_hack(["check", "--rust-version", "--package=member1", "--package=member2"])
+        .assert_success("rust-version")
+        .stderr_contains(
+            "
+            running `rustup run 1.63 cargo check` on member1 (1/2)
+            running `rustup run 1.63 cargo check` on member2 (2/2)
+            ",
+        );
+    cargo_hack(["check", "--rust-version", "--workspace", "--locked"])
+        .assert_success("rust-version")
+        .stderr_contains(
+            "
+            running `rustup run 1.63 cargo check --locked` on member1 (1/4)
+            running `rustup run 1.63 cargo check --locked` on member2 (2/4)
+            running `rustup run 1.64 cargo check --locked` on member3 (3/4)
+            running `rustup run 1.65 cargo check --locked` on real (4/4)
+            ",
+        );
+}
+
+#[test]
+fn multi_target() {
+    // --version-range requires rustup
+    if !has_rustup() {
+        return;
+    }
+    let _r = RUSTUP_TOOLCHAIN_CHANGES.lock().unwrap();
+
+    let target_suffix = String::from("-") + TARGET.split_once('-').unwrap().1;
+
+    cargo_hack([
+        "check",
+        "--version-range",
+        "1.63..=1.64",
+        "--target",
+        &format!("aarch64{target_suffix}"),
+    ])
+    .assert_success("real")
+    .stderr_contains(format!(
+        "
+        running `rustup run 1.63 cargo check --target aarch64{target_suffix}` on real (1/2)
+        running `rustup run 1.64 cargo check --target aarch64{target_suffix}` on real (2/2)
+        "
+    ));
+
+    cargo_hack([
+        "check",
+        "--version-range",
+        "1.63..=1.64",
+        "--target",
+        &format!("x86_64{target_suffix}"),
+        "--target",
+        &format!("aarch64{target_suffix}"),
+    ])
+    .assert_success("real")
+    .stderr_contains(format!(
+        "
+        running `rustup run 1.63 cargo check --target aarch64{target_suffix}` on real (1/3)
+        running `rustup run 1.63 cargo check --target x86_64{target_suffix}` on real (2/3)
+        running `rustup run 1.64 cargo check --target aarch64{target_suffix} --target x86_64{target_suffix}` on real (3/3)
+        ",
+    ));
+
+    cargo_hack([
+        "check",
+        "--version-range",
+        "1.63..=1.64",
+        "--target",
+        &format!("x86_64{target_suffix}"),
+        "--target",
+        &format!("x86_64{target_suffix}"),
+    ])
+    .assert_success("real")
+    .stderr_contains(format!(
+        "
+        running `rustup run 1.63 cargo check --target x86_64{target_suffix}` on real (1/2)
+        running `rustup run 1.64 cargo check --target x86_64{target_suffix}` on real (2/2)
+        ",
+    ));
+}
+
+#[test]
+fn version_range_failure() {
+    // zero step
+    cargo_hack(["check", "--version-range", "1.45..", "--version-step", "0"])
+        .assert_failure("real")
+        .stderr_contains("--version-step cannot be zero");
+
+    // empty
+    cargo_hack(["check", "--version-range", "1.45..1.44"])
+        .assert_failure("real")
+        .stderr_contains("specified version range `1.45..=1.44` is empty");
+    cargo_hack(["check", "--version-range", "1.45..=1.44"])
+        .assert_failure("real")
+        .stderr_contains("specified version range `1.45..=1.44` is empty");
+
+    // v0
+    cargo_hack(["check", "--version-range", "0.45.."])
+        .assert_failure("real")
+        .stderr_contains("major version must be 1");
+
+    // inclusive range without end expression
+    cargo_hack(["check", "--version-range", "..="]).assert_failure("real").stderr_contains(
+        "inclusive range `..=` must have end expression; consider using `..` or `..=<end>`",
+    );
+    cargo_hack(["check", "--version-range", "1.45..="]).assert_failure("real").stderr_contains(
+        "inclusive range `1.45..=` must have end expression; consider using `1.45..` or `1.45..=<end>`",
+    );
+
+    // patch version
+    cargo_hack(["check", "--version-range", "1.45.2.."])
+        .assert_failure("real") // warn
+        .stderr_contains(
+            "
+            --version-range always selects the latest patch release per minor release, \
+            not the specified patch release `2`
+            ",
+        );
+
+    // No rust-version
+    cargo_hack(["check", "--version-range", "..=1.64"]).assert_failure("real").stderr_contains(
+        "
+        no rust-version field in selected Cargo.toml's is specified
+        ",
+    );
+}
+
+#[test]
+fn clean_per_version_failure() {
+    if env::var_os("CARGO_HACK_TEST_TOOLCHAIN").is_some() {
+        return;
+    }
+
+    // without --version-range
+    cargo_hack(["check", "--clean-per-version"])
+        .assert_failure("real")
+        .stderr_contains("--clean-per-version can only be used together with --version-range");
+}
+
+#[test]
+fn keep_going() {
+    cargo_hack(["check", "--each-feature", "--keep-going"])
+        .assert_failure("keep_going")
+        .stderr_contains(format!(
+            "
+            running `cargo check --no-default-features` on keep_going (1/2)
+            `a` feature not specified
+            running `cargo check --no-default-features --features a` on keep_going (2/2)
+            `a` feature specified
+            failed to run 2 commands
+            failed commands:
+            keep_going:
+            cargo{EXE_SUFFIX} check --manifest-path Cargo.toml --no-default-features`
+            cargo{EXE_SUFFIX} check --manifest-path Cargo.toml --no-default-features --features a`
+            ",
+        ));
+}
+
+#[test]
+fn replay_failures() {
+    cargo_hack(["check", "--each-feature", "--keep-going", "--replay-failures"])
+        .assert_failure("keep_going")
+        .stderr_contains(format!(
+            "
+            running `cargo check --no-default-features` on keep_going (1/2)
+            running `cargo check --no-default-features --features a` on keep_going (2/2)
+            cargo{EXE_SUFFIX} check --manifest-path Cargo.toml --no-default-features` on keep_going:
+            `a` feature not specified
+            cargo{EXE_SUFFIX} check --manifest-path Cargo.toml --no-default-features --features a` on keep_going:
+            `a` feature specified
+            failed to run 2 commands
+            ",
+        ));
+
+    // Without --keep-going, the output is printed when the run stops.
+    cargo_hack(["check", "--each-feature", "--replay-failures"])
+        .assert_failure("keep_going")
+        .stderr_contains(format!(
+            "
+            cargo{EXE_SUFFIX} check --manifest-path Cargo.toml --no-default-features` on keep_going:
+            `a` feature not specified
+            ",
+        ))
+        .stderr_not_contains("(2/2)");
+
+    // The output of the successful commands is not printed.
+    cargo_hack(["check", "--replay-failures"])
+        .assert_success("real")
+        .stderr_contains("running `cargo check` on real (1/1)")
+        .stderr_not_contains("Checking real");
+}
+
+#[test]
+fn log_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir = dir.path();
+    cargo_hack(["check", "--each-feature", "--log-dir"])
+        .arg(dir)
+        .assert_success("real")
+        .stderr_contains("Checking real");
+    let log = fs::read_to_string(dir.join("real-no-default-features-a.log")).unwrap();
+    assert!(log.contains("Checking real"), "{log}");
+    assert!(dir.join("real-no-default-features.log").exists());
+    assert!(dir.join("real-no-default-features-all-features.log").exists());
+
+    cargo_hack(["--exec", "echo hello", "--log-dir"])
+        .arg(dir)
+        .assert_success("real")
+        .stdout_contains("hello");
+    assert_eq!(fs::read_to_string(dir.join("real-default.log")).unwrap(), "hello\n");
+}
+
+#[test]
+fn summary() {
+    cargo_hack(["check", "--each-feature", "--summary-format", "text"])
+        .assert_success("real")
+        .stdout_contains(
+            "
+            package  features                                    result  duration
+            real     `--no-default-features`                     pass
+            real     `--no-default-features --all-features`      pass
+            ",
+        );
+
+    cargo_hack(["check", "--each-feature", "--keep-going", "--summary-format=markdown"])
+        .assert_failure("keep_going")
+        .stdout_contains(
+            "
+            | package | features | result | duration |
+            | --- | --- | --- | --- |
+            | keep_going | `--no-default-features` | fail |
+            | keep_going | `--no-default-features --features a` | fail |
+            ",
+        );
+
+    // The commands run before the run stops are also reported.
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("summary.md");
+    cargo_hack(["check", "--each-feature", "--summary-format=markdown", "--summary-file"])
+        .arg(&path)
+        .assert_failure("keep_going")
+        .stdout_not_contains("| package |");
+    let summary = fs::read_to_string(&path).unwrap();
+    assert!(summary.contains("| keep_going | `--no-default-features` | fail |"), "{summary}");
+    assert!(!summary.contains("--features a"), "{summary}");
+
+    cargo_hack(["check", "--summary-format=json"]).assert_failure("real").stderr_contains(
+        "argument for --summary-format must be text or markdown, but found `json`",
+    );
+}
+
+#[test]
+fn gh_summary() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("step_summary.md");
+    fs::write(&path, "existing\n").unwrap();
+    cargo_hack(["check", "--each-feature", "--keep-going"])
+        .env("GITHUB_ACTIONS", "true")
+        .env("GITHUB_STEP_SUMMARY", &path)
+        .assert_failure("keep_going")
+        .stdout_not_contains("| package |");
+    let summary = fs::read_to_string(&path).unwrap();
+    assert!(
+        summary.starts_with("existing\n### cargo-hack\n\n2 commands run, 2 failed\n"),
+        "{summary}"
+    );
+    assert!(
+        summary.contains("| keep_going | `--no-default-features --features a` | fail |"),
+        "{summary}"
+    );
+
+    // Nothing is written if no command is run.
+    fs::write(&path, "").unwrap();
+    cargo_hack(["check", "--each-feature", "--print-command-list"])
+        .env("GITHUB_ACTIONS", "true")
+        .env("GITHUB_STEP_SUMMARY", &path)
+        .assert_success("real");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+    // Written only on GitHub Actions unless --gh-summary is used.
+    cargo_hack(["check"]).env("GITHUB_STEP_SUMMARY", &path).assert_success("real");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    cargo_hack(["check", "--gh-summary"]).env("GITHUB_STEP_SUMMARY", &path).assert_success("real");
+    assert!(fs::read_to_string(&path).unwrap().contains("1 command run, 0 failed"));
+
+    // --no-gh-summary disables it on GitHub Actions.
+    fs::write(&path, "").unwrap();
+    cargo_hack(["check", "--no-gh-summary"])
+        .env("GITHUB_ACTIONS", "true")
+        .env("GITHUB_STEP_SUMMARY", &path)
+        .assert_success("real");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+    cargo_hack(["check", "--gh-summary"]).assert_failure("real").stderr_contains(
+        "--gh-summary requires the GITHUB_STEP_SUMMARY environment variable to be set",
+    );
+    cargo_hack(["check", "--gh-summary", "--no-gh-summary"])
+        .env("GITHUB_STEP_SUMMARY", &path)
+        .assert_failure("real")
+        .stderr_contains("--gh-summary may not be used together with --no-gh-summary");
+}
+
+#[test]
+fn stop_on_first_success() {
+    cargo_hack(["check", "--each-feature", "--stop-on-first-success"])
+        .assert_success("stop_on_first_success")
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on stop_on_first_success (1/5)
+            running `cargo check --no-default-features --features a` on stop_on_first_success (2/5)
+            running `cargo check --no-default-features --features b` on stop_on_first_success (3/5)
+            package `stop_on_first_success` succeeded with `--no-default-features --features b`; \
+            stopping due to --stop-on-first-success
+            ",
+        )
+        .stderr_not_contains("--features c");
+
+    // no feature combination succeeds
+    cargo_hack(["check", "--each-feature", "--stop-on-first-success"])
+        .assert_failure("keep_going")
+        .exit_code(2)
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on keep_going (1/2)
+            running `cargo check --no-default-features --features a` on keep_going (2/2)
+            failed to run 2 commands
+            ",
+        )
+        .stderr_not_contains("stopping due to --stop-on-first-success");
+
+    cargo_hack(["check", "--stop-on-first-success", "--keep-going"])
+        .assert_failure("real")
+        .stderr_contains("--stop-on-first-success may not be used together with --keep-going");
+}
+
+#[test]
+fn bisect_features() {
+    cargo_hack(["bisect-features", "check"])
+        .assert_failure("bisect_features")
+        .exit_code(2)
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features --features a,b,c,d` on bisect_features (bisecting)
+            running `cargo check --no-default-features --features b,d` on bisect_features (bisecting)
+            minimal failing feature set of package `bisect_features`: `--no-default-features --features b,d`
+            ",
+        )
+        .stderr_not_contains("--features a,b,c,d,default");
+
+    cargo_hack(["bisect-features", "check"])
+        .assert_success("real")
+        .stderr_contains("package `real` does not fail with all features; nothing to bisect");
+
+    cargo_hack(["bisect-features"])
+        .assert_failure("real")
+        .stderr_contains("bisect-features requires a subcommand");
+
+    cargo_hack(["bisect-features", "check", "--each-feature"])
+        .assert_failure("real")
+        .stderr_contains("bisect-features may not be used together with --each-feature");
+}
+
+#[test]
+fn namespaced_features() {
+    // Namespaced features requires Rust 1.60.
+    let require = Some(60);
+
+    cargo_hack(["check", "--feature-powerset"])
+        .assert_success2("namespaced_features", require)
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on namespaced_features (1/2)
+            running `cargo check --no-default-features --features easytime` on namespaced_features (2/2)
+            ",
+        );
+}
+
+#[test]
+fn weak_dep_features() {
+    // Weak dependency features requires Rust 1.60.
+    let require = Some(60);
+
+    cargo_hack(["check", "--feature-powerset"])
+        .assert_success2("weak_dep_features", require)
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on weak_dep_features (1/4)
+            running `cargo check --no-default-features --features default` on weak_dep_features (2/4)
+            running `cargo check --no-default-features --features easytime` on weak_dep_features (3/4)
+            running `cargo check --no-default-features --features default,easytime` on weak_dep_features (4/4)
+            ",
+        );
+    cargo_hack(["check", "--feature-powerset", "--optional-deps"])
+        .assert_success2("weak_dep_features", require)
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on weak_dep_features (1/4)
+            running `cargo check --no-default-features --features default` on weak_dep_features (2/4)
+            running `cargo check --no-default-features --features easytime` on weak_dep_features (3/4)
+            running `cargo check --no-default-features --features default,easytime` on weak_dep_features (4/4)
+            ",
+        );
+
+    cargo_hack(["check", "--feature-powerset"])
+        .assert_success2("weak_dep_features_namespaced", require)
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on weak_dep_features_namespaced (1/4)
+            running `cargo check --no-default-features --features default` on weak_dep_features_namespaced (2/4)
+            running `cargo check --no-default-features --features easytime` on weak_dep_features_namespaced (3/4)
+            running `cargo check --no-default-features --features default,easytime` on weak_dep_features_namespaced (4/4)
+            ",
+        );
+    cargo_hack(["check", "--feature-powerset", "--optional-deps"])
+        .assert_success2("weak_dep_features_namespaced", require)
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on weak_dep_features_namespaced (1/4)
+            running `cargo check --no-default-features --features default` on weak_dep_features_namespaced (2/4)
+            running `cargo check --no-default-features --features easytime` on weak_dep_features_namespaced (3/4)
+            running `cargo check --no-default-features --features default,easytime` on weak_dep_features_namespaced (4/4)
+            ",
+        );
+
+    cargo_hack(["check", "--feature-powerset"])
+        .assert_success2("weak_dep_features_implicit", require)
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on weak_dep_features_implicit (1/2)
+            running `cargo check --no-default-features --features default` on weak_dep_features_implicit (2/2)
+            ",
+        );
+    cargo_hack(["check", "--feature-powerset", "--optional-deps"])
+        .assert_success2("weak_dep_features_implicit", require)
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on weak_dep_features_implicit (1/4)
+            running `cargo check --no-default-features --features default` on weak_dep_features_implicit (2/4)
+            running `cargo check --no-default-features --features easytime` on weak_dep_features_implicit (3/4)
+            running `cargo check --no-default-features --features default,easytime` on weak_dep_features_implicit (4/4)
+            ",
+        );
+}
+
+#[test]
+fn empty_string() {
+    cargo_hack(["check", "--each-feature", "--skip", ""])
+        .assert_success("real")
+        .stderr_not_contains("not found");
+}
+
+#[test]
+fn print_command_list() {
+    cargo_hack(["check", "--each-feature", "--print-command-list"])
+        .assert_success("real")
+        .stdout_contains(
+            "
+            cargo check --manifest-path Cargo.toml --no-default-features
+            cargo check --manifest-path Cargo.toml --no-default-features --features a
+            cargo check --manifest-path Cargo.toml --no-default-features --features b
+            cargo check --manifest-path Cargo.toml --no-default-features --features c
+            cargo check --manifest-path Cargo.toml --no-default-features --features default
+            cargo check --manifest-path Cargo.toml --no-default-features --all-features
+            ",
+        )
+        .stdout_not_contains("`");
+}
+
+#[test]
+fn unstable_flags() {
+    // -Z flags before the subcommand are not mistaken for the subcommand.
+    cargo_hack(["-Z", "build-std", "check", "-Zunstable-options", "--print-command-list"])
+        .assert_success("real")
+        .stdout_contains("cargo check -Z build-std -Z unstable-options --manifest-path Cargo.toml");
+
+    cargo_hack(["check", "-Z", "build-std", "--each-feature", "--print-command-list"])
+        .assert_success("real")
+        .stdout_contains(
+            "
+            cargo check -Z build-std --manifest-path Cargo.toml --no-default-features
+            cargo check -Z build-std --manifest-path Cargo.toml --no-default-features --features a
+            ",
+        );
+}
+
+#[test]
+fn package_metadata() {
+    cargo_hack(["check", "--each-feature", "--workspace"])
+        .assert_success("package_metadata")
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on member1 (1/8)
+            running `cargo check --no-default-features --features a` on member1 (2/8)
+            running `cargo check --no-default-features --features c` on member1 (3/8)
+            running `cargo check --no-default-features` on member2 (4/8)
+            running `cargo check --no-default-features --features a` on member2 (5/8)
+            running `cargo check --no-default-features --features b` on member2 (6/8)
+            running `cargo check --no-default-features --features c` on member2 (7/8)
+            running `cargo check --no-default-features --all-features` on member2 (8/8)
+            ",
+        )
+        .stderr_not_contains(
+            "
+            --features b` on member1
+            --all-features` on member1
+            ",
+        );
+
+    cargo_hack(["check", "--feature-powerset", "--workspace"])
+        .assert_success("package_metadata")
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features --features a,c` on member1 (4/10)
+            running `cargo check --no-default-features --features a,c` on member2 (9/10)
+            running `cargo check --no-default-features --features b,c` on member2 (10/10)
+            ",
+        )
+        .stderr_not_contains(
+            "
+            --features b` on member1
+            --features a,b` on member2
+            --features a,b,c` on member2
+            ",
+        );
+}
+
+#[test]
+fn workspace_metadata() {
+    cargo_hack(["check", "--each-feature", "--workspace"])
+        .assert_success("workspace_metadata")
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on member1 (1/9)
+            running `cargo check --no-default-features --features a` on member1 (2/9)
+            running `cargo check --no-default-features` on member2 (3/9)
+            running `cargo check --no-default-features --features a` on member2 (4/9)
+            running `cargo check --no-default-features --features b` on member2 (5/9)
+            running `cargo check --no-default-features` on member3 (6/9)
+            running `cargo check --no-default-features --features a` on member3 (7/9)
+            running `cargo check --no-default-features --features b` on member3 (8/9)
+            running `cargo check --no-default-features --all-features` on member3 (9/9)
+            ",
+        )
+        .stderr_not_contains(
+            "
+            --features b` on member1
+            --features c`
+            --all-features` on member1
+            --all-features` on member2
+            ",
+        );
+
+    cargo_hack(["check", "--each-feature", "--workspace", "--explain-plan"])
+        .assert_success("workspace_metadata")
+        .stderr_contains(
+            "
+            dropped feature `b` of package `member1`: excluded by `exclude-features` in `[package.metadata.cargo-hack]`
+            dropped feature `c` of package `member1`: excluded by `exclude-features` in `[workspace.metadata.cargo-hack]`
+            dropped feature `c` of package `member2`: excluded by `exclude-features` in `[workspace.metadata.cargo-hack]`
+            ",
+        );
+}
+
+#[test]
+fn allow_failure() {
+    cargo_hack([
+        "check",
+        "--each-feature",
+        "--exclude-no-default-features",
+        "--allow-failure",
+        "a",
+    ])
+    .env_remove("CARGO_HACK_DENY_WARNINGS")
+    .assert_success("keep_going")
+    .stderr_contains(format!(
+        "
+            running `cargo check --no-default-features --features a` on keep_going (1/1)
+            `a` feature specified
+            1 commands failed but were allowed to fail by --allow-failure
+            allowed failures:
+            keep_going:
+            cargo{EXE_SUFFIX} check --manifest-path Cargo.toml --no-default-features --features a`
+            ",
+    ));
+
+    // Only the exact feature set is allowed to fail.
+    cargo_hack(["check", "--each-feature", "--allow-failure", "a"])
+        .env_remove("CARGO_HACK_DENY_WARNINGS")
+        .assert_failure("keep_going")
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on keep_going (1/2)
+            `a` feature not specified
+            ",
+        )
+        .stderr_not_contains("--features a` on keep_going");
+
+    cargo_hack(["check", "--each-feature", "--keep-going", "--allow-failure", "a"])
+        .env_remove("CARGO_HACK_DENY_WARNINGS")
+        .assert_failure("keep_going")
+        .stderr_contains(format!(
+            "
+            failed to run 1 commands
+            failed commands:
+            keep_going:
+            cargo{EXE_SUFFIX} check --manifest-path Cargo.toml --no-default-features`
+            allowed failures:
+            ",
+        ));
+}
+
+#[test]
+fn no_fail_fast() {
+    cargo_hack(["test", "--workspace", "--no-fail-fast"])
+        .assert_failure("no_fail_fast")
+        .stderr_contains(format!(
+            "
+            running `cargo test --no-fail-fast` on member1 (1/2)
+            running `cargo test --no-fail-fast` on member2 (2/2)
+            failed to run 2 commands
+            failed commands:
+            member1:
+            cargo{EXE_SUFFIX} test --no-fail-fast --manifest-path member1/Cargo.toml`
+            member2:
+            cargo{EXE_SUFFIX} test --no-fail-fast --manifest-path member2/Cargo.toml`
+            ",
+        ))
+        .stdout_contains(
+            "
+            member1_lib_fail
+            member1_integration_fail
+            member2_lib_fail
+            ",
+        );
+
+    // --no-fail-fast is not propagated to subcommands that don't accept it.
+    cargo_hack(["check", "--workspace", "--no-fail-fast"])
+        .assert_success("no_fail_fast")
+        .stderr_not_contains("--no-fail-fast`");
+}
+
+#[test]
+fn targetless() {
+    cargo_hack(["check", "--workspace"])
+        .assert_success("targetless")
+        .stderr_contains(
+            "
+            skipped running on package `member2` that has no library or binary targets
+            running `cargo check` on member1 (1/1)
+            ",
+        )
+        .stderr_not_contains("on member2");
 
-    cargo_hack(["check", "--each-feature", "--optional-deps="])
-        .assert_success2("optional_deps", require)
+    cargo_hack(["check", "--workspace", "--no-skip-targetless"])
+        .assert_success("targetless")
         .stderr_contains(
             "
-            running `cargo check --no-default-features` on optional_deps (1/2)
-            running `cargo check --no-default-features --all-features` on optional_deps (2/2)
+            running `cargo check` on member1 (1/2)
+            running `cargo check` on member2 (2/2)
+            ",
+        )
+        .stderr_not_contains("skipped running on package `member2`");
+
+    cargo_hack(["check", "--workspace", "--all-targets"])
+        .assert_success("targetless")
+        .stderr_contains(
+            "
+            running `cargo check --all-targets` on member1 (1/2)
+            running `cargo check --all-targets` on member2 (2/2)
             ",
         );
+
+    cargo_hack(["test", "--workspace"])
+        .assert_success("targetless")
+        .stderr_contains("running `cargo test` on member2 (2/2)");
 }
 
 #[test]
-fn optional_deps_failure() {
-    cargo_hack(["check", "--optional-deps"])
-        .assert_failure("real")
+fn check_feature_unification() {
+    cargo_hack(["--check-feature-unification", "--package", "a"])
+        .assert_failure("feature_unification")
         .stderr_contains(
-            "--optional-deps can only be used together with either --each-feature or --feature-powerset",
+            "
+            warning: `dep v0.0.0` has feature `y` enabled when a is built together with the \
+            workspace, but not when a is built alone
+            features resolved for 1 package differ when built together with the workspace
+            ",
+        )
+        .stderr_not_contains("Compiling");
+
+    cargo_hack(["--check-feature-unification", "--workspace"])
+        .assert_failure("feature_unification")
+        .stderr_contains(
+            "
+            `dep v0.0.0` has feature `x` enabled when b is built together with the workspace
+            `dep v0.0.0` has features `x,y` enabled when dep is built together with the workspace
+            features resolved for 3 packages differ when built together with the workspace
+            ",
+        )
+        .stderr_not_contains("enabled when c is built");
+
+    cargo_hack(["--check-feature-unification", "--package", "c"])
+        .assert_success("feature_unification")
+        .stderr_contains("no feature unification differences found in 1 package");
+}
+
+#[test]
+fn print_package_list() {
+    cargo_hack(["--print-package-list", "--workspace", "--ignore-private"])
+        .assert_success("virtual")
+        .stdout_contains(
+            "
+            member1
+            member2 (private, skipped)
+            not_find_manifest (private, skipped)
+            ",
+        )
+        .stderr_not_contains("running");
+
+    cargo_hack(["--print-package-list", "--workspace", "--exclude", "member1"])
+        .assert_success("virtual")
+        .stdout_contains(
+            "
+            member2
+            not_find_manifest
+            ",
+        )
+        .stdout_not_contains("member1");
+}
+
+#[test]
+fn print_features() {
+    cargo_hack(["--print-features", "--workspace"])
+        .assert_success("package_metadata")
+        .stdout_contains(
+            "
+            member1:
+              features: a, b, c
+              optional deps: (none)
+              deps features: (none)
+            member2:
+              features: a, b, c
+            ",
+        )
+        .stderr_not_contains("running");
+
+    cargo_hack(["--print-features", "--include-deps-features"])
+        .assert_success("powerset_deduplication")
+        .stdout_contains(
+            "
+            deduplication:
+              features: a, b, c, d, e
+              optional deps: member1
+              deps features: easytime/default, easytime/std
+            ",
         );
+
+    // Subcommand is not run.
+    cargo_hack(["check", "--print-features"])
+        .assert_success("real")
+        .stdout_contains("optional deps: member1")
+        .stderr_not_contains("running");
 }
 
+#[cfg(unix)]
 #[test]
-fn skip_optional_deps() {
-    // require Rust 1.31 due to optional_deps uses renamed deps
-    let require = Some(31);
+fn exec() {
+    cargo_hack(["--exec", "env", "--each-feature"])
+        .assert_success("real")
+        .stderr_contains(
+            "
+            running `env` on real with `--no-default-features` (1/6)
+            running `env` on real with `--no-default-features --features a` (2/6)
+            running `env` on real with `--no-default-features --all-features` (6/6)
+            ",
+        )
+        .stdout_contains(
+            "
+            CARGO_HACK_PACKAGE=real
+            CARGO_HACK_FEATURES=a
+            CARGO_HACK_NO_DEFAULT_FEATURES=true
+            CARGO_HACK_ALL_FEATURES=true
+            ",
+        )
+        .stderr_not_contains("cargo check");
 
-    cargo_hack(["check", "--each-feature", "--optional-deps", "--exclude-features", "real"])
-        .assert_success2("optional_deps", require)
+    cargo_hack(["--exec", "false", "--keep-going"]).assert_failure("real").stderr_contains(
+        "
+        failed commands:
+        real:
+        `false` with default features
+        ",
+    );
+
+    cargo_hack(["check", "--exec", "env"])
+        .assert_failure("real")
+        .stderr_contains("--exec may not be used together with check subcommand");
+}
+
+#[test]
+fn hack_env() {
+    cargo_hack(["check", "--each-feature"]).assert_success("hack_env").stderr_contains(
+        "
+        CARGO_HACK=1
+        CARGO_HACK_COMMAND_INDEX=1
+        CARGO_HACK_COMMAND_TOTAL=4
+        CARGO_HACK_FEATURES=a
+        CARGO_HACK_COMMAND_INDEX=2
+        CARGO_HACK_FEATURES=b
+        CARGO_HACK_COMMAND_INDEX=4
+        ",
+    );
+}
+
+#[test]
+fn retry() {
+    cargo_hack(["check", "--each-feature", "--keep-going", "--retry", "2"])
+        .assert_failure("keep_going")
         .stderr_contains(
             "
-            running `cargo check --no-default-features` on optional_deps (1/2)
-            running `cargo check --no-default-features --features renamed` on optional_deps (2/2)
+            running `cargo check --no-default-features` on keep_going (1/2)
+            retrying `cargo check --no-default-features` (1/2)
+            retrying `cargo check --no-default-features` (2/2)
+            running `cargo check --no-default-features --features a` on keep_going (2/2)
+            retrying `cargo check --no-default-features --features a` (2/2)
+            failed to run 2 commands
             ",
+        );
+
+    cargo_hack(["check", "--retry", "1"]).assert_success("real").stderr_not_contains("retrying");
+
+    cargo_hack(["check", "--retry", "a"]).assert_failure("real").stderr_contains("invalid digit");
+}
+
+#[test]
+fn no_progress() {
+    // The progress bar is only shown when stderr is a terminal.
+    cargo_hack(["check", "--each-feature"])
+        .assert_success("real")
+        .stderr_contains("running `cargo check --no-default-features` on real (1/6)")
+        .stderr_not_contains("\x1b[2K");
+
+    cargo_hack(["check", "--no-progress"])
+        .assert_success("real")
+        .stderr_contains("running `cargo check` on real (1/1)");
+}
+
+#[test]
+#[cfg(debug_assertions)] // CARGO_HACK_TEST_PROGRESS_BAR is ignored in release builds.
+fn progress_bar() {
+    // The output of successful commands is still shown while the progress bar is drawn.
+    cargo_hack(["check", "--each-feature"])
+        .env("CARGO_HACK_TEST_PROGRESS_BAR", "1")
+        .assert_success("deny_warnings")
+        .stderr_contains("function `unused` is never used")
+        .stderr_contains("\x1b[2K")
+        .stderr_not_contains("running `cargo check");
+
+    cargo_hack(["check", "--each-feature", "--no-progress"])
+        .env("CARGO_HACK_TEST_PROGRESS_BAR", "1")
+        .assert_success("deny_warnings")
+        .stderr_contains(
+            "running `cargo check --no-default-features --features a` on deny_warnings (2/4)",
         )
-        .stderr_not_contains("--features real");
+        .stderr_not_contains("\x1b[2K");
 }
 
 #[test]
-fn list_separator() {
-    // require Rust 1.31 due to optional_deps uses renamed deps
-    let require = Some(31);
+fn continue_from() {
+    cargo_hack(["check", "--each-feature", "--continue-from", "4"])
+        .assert_success("real")
+        .stderr_contains(
+            "
+            resuming from command 4/6
+            running `cargo check --no-default-features --features c` on real (4/6)
+            running `cargo check --no-default-features --features default` on real (5/6)
+            running `cargo check --no-default-features --all-features` on real (6/6)
+            ",
+        )
+        .stderr_not_contains(
+            "
+            (1/6)
+            (2/6)
+            (3/6)
+            ",
+        );
 
-    cargo_hack(["run", "--features='real,renamed'"])
-        .assert_success2("optional_deps", require)
-        .stderr_contains("running `cargo run --features real,renamed` on optional_deps");
+    cargo_hack(["check", "--each-feature", "--continue-from", "7"])
+        .assert_failure("real")
+        .stderr_contains("--continue-from 7 is greater than the total number of commands (6)");
 
-    cargo_hack(["run", "--features=\"real,renamed\""])
-        .assert_success2("optional_deps", require)
-        .stderr_contains("running `cargo run --features real,renamed` on optional_deps");
+    cargo_hack(["check", "--continue-from", "0"])
+        .assert_failure("real")
+        .stderr_contains("--continue-from cannot be zero");
+}
 
-    cargo_hack(["run", "--features=real,renamed"])
-        .assert_success2("optional_deps", require)
-        .stderr_contains("running `cargo run --features real,renamed` on optional_deps");
+#[test]
+fn resume() {
+    // The fixture has `.cargo-hack/last-run.json` that records that the first two commands of
+    // `cargo hack check --each-feature` have been completed.
+    cargo_hack(["check", "--each-feature", "--resume"])
+        .assert_success("resume")
+        .stderr_contains(
+            "
+            resuming from command 3/4 recorded in
+            running `cargo check --no-default-features --features b` on resume (3/4)
+            running `cargo check --no-default-features --all-features` on resume (4/4)
+            ",
+        )
+        .stderr_not_contains(
+            "
+            (1/4)
+            (2/4)
+            ",
+        );
 
-    cargo_hack(["run", "--features", "real,renamed"])
-        .assert_success2("optional_deps", require)
-        .stderr_contains("running `cargo run --features real,renamed` on optional_deps");
+    cargo_hack(["check", "--feature-powerset", "--resume"])
+        .assert_success("resume")
+        .stderr_contains(
+            "
+            the plan has changed since the last run recorded in
+            running `cargo check --no-default-features` on resume (1/4)
+            ",
+        );
 
-    cargo_hack(["run", "--features='real renamed'"])
-        .assert_success2("optional_deps", require)
-        .stderr_contains("running `cargo run --features real,renamed` on optional_deps");
+    cargo_hack(["check", "--resume", "--continue-from", "2"])
+        .assert_failure("resume")
+        .stderr_contains("--resume may not be used together with --continue-from");
+}
 
-    cargo_hack(["run", "--features=\"real renamed\""])
-        .assert_success2("optional_deps", require)
-        .stderr_contains("running `cargo run --features real,renamed` on optional_deps");
+#[cfg(unix)]
+#[test]
+fn no_dev_deps_symlinked_manifest() {
+    let outside = tempfile::tempdir().unwrap();
+    let target = outside.path().join("Cargo.toml");
+    cargo_hack(["check", "--no-dev-deps", "--verbose"])
+        .assert_success_with_check(
+            "remove_dev_dep",
+            |dir| {
+                fs::rename(dir.join("Cargo.toml"), &target).unwrap();
+                std::os::unix::fs::symlink(&target, dir.join("Cargo.toml")).unwrap();
+            },
+            // The link is not replaced by a regular file.
+            |dir| {
+                let metadata = fs::symlink_metadata(dir.join("Cargo.toml")).unwrap();
+                assert!(metadata.file_type().is_symlink());
+            },
+        )
+        .stderr_contains(format!("restoring {}", target.canonicalize().unwrap().display()));
+    // The file the link points to is modified and restored.
+    let original = fs::read_to_string("tests/fixtures/remove_dev_dep/Cargo.toml").unwrap();
+    assert_eq!(fs::read_to_string(&target).unwrap(), original);
+}
 
-    cargo_hack(["run", "--features", "real renamed"])
-        .assert_success2("optional_deps", require)
-        .stderr_contains("running `cargo run --features real,renamed` on optional_deps");
+#[cfg(unix)]
+#[test]
+fn status_file() {
+    cargo_hack(["check", "--each-feature", "--keep-going", "--status-file", "/dev/stdout"])
+        .assert_failure("keep_going")
+        .stdout_contains(
+            r#"
+            {"event":"start","features":[],"index":1,"package":"keep_going","status":null,"total":2}
+            {"event":"finish","features":[],"index":1,"package":"keep_going","status":"failure","total":2}
+            {"event":"start","features":["a"],"index":2,"package":"keep_going","status":null,"total":2}
+            {"event":"finish","features":["a"],"index":2,"package":"keep_going","status":"failure","total":2}
+            "#,
+        );
+
+    cargo_hack(["check", "--status-format", "jsonl", "--status-file", "/dev/stdout"])
+        .assert_success("real")
+        .stdout_contains(
+            r#"{"event":"finish","features":[],"index":1,"package":"real","status":"success","total":1}"#,
+        );
 }
 
 #[test]
-fn short_flag() {
-    cargo_hack(["check", "-vvpmember1"]) // same as -v -v -p member1
+fn dump_features() {
+    cargo_hack(["check", "--feature-powerset", "--depth", "1", "--dump-features", "/dev/stdout"])
+        .assert_success("real")
+        .stdout_contains(
+            r#"
+            [real]
+            features = ["a", "b", "c", "default"]
+            optional-deps = ["member1"]
+            deps-features = []
+            default = false
+            no-default-features = true
+            all-features = true
+            combinations = [["a"], ["b"], ["c"], ["default"]]
+            "#,
+        )
+        .stderr_contains("wrote features of 1 package to `/dev/stdout`")
+        .stderr_not_contains("running");
+
+    cargo_hack(["check", "--workspace", "--dump-features", "/dev/stdout"])
         .assert_success("virtual")
-        .stderr_contains(format!(
+        .stdout_contains(
             "
-            cargo{EXE_SUFFIX} check -v --manifest-path member1{MAIN_SEPARATOR}Cargo.toml` (1/1)
+            [member1]
+            default = true
+            no-default-features = false
+            all-features = false
+            combinations = []
+            [member2]
             ",
-        ))
-        .stderr_not_contains("member2");
+        );
 
-    cargo_hack(["check", "-qpmember1"]) // same as -q -p member1
-        .assert_success("virtual")
-        .stderr_contains("`cargo check -q` on member1 (1/1)")
-        .stderr_not_contains("member2");
+    cargo_hack(["check", "--count", "--dump-features", "features.toml"])
+        .assert_failure("real")
+        .stderr_contains("--dump-features may not be used together with --count");
 }
 
 #[test]
-fn verbose() {
-    cargo_hack(["check", "--verbose"]).assert_success("virtual").stderr_contains(format!(
+fn run_plan() {
+    cargo_hack(["check", "--run-plan", "plan.json"]).assert_success("run_plan").stderr_contains(
         "
-        cargo{EXE_SUFFIX} check --manifest-path member1{MAIN_SEPARATOR}Cargo.toml` (1/3)
-        cargo{EXE_SUFFIX} check --manifest-path member2{MAIN_SEPARATOR}Cargo.toml` (2/3)
-        cargo{EXE_SUFFIX} check --manifest-path dir{MAIN_SEPARATOR}not_find_manifest{MAIN_SEPARATOR}Cargo.toml` (3/3)
+        running `cargo check --no-default-features` on run_plan (1/3)
+        running `cargo check --no-default-features --features a,b` on run_plan (2/3)
+        running `cargo check --all-features` on run_plan (3/3)
         ",
-    ));
+    );
+    cargo_hack(["check", "--run-plan", "plan.json", "--count"])
+        .assert_success("run_plan")
+        .stdout_contains("3 combinations across 1 package");
 
-    // If `-vv` is passed, propagate `-v` to cargo.
-    cargo_hack(["check", "-vv", "-p", "member1"]).assert_success("virtual").stderr_contains(
-        format!(
+    cargo_hack(["check", "--run-plan", "unknown-package.json"])
+        .assert_failure("run_plan")
+        .stderr_contains(
+            "failed to parse plan `unknown-package.json`: package `foo` specified in invocation 1 not found in workspace",
+        );
+    cargo_hack(["check", "--run-plan", "invalid.json"]).assert_failure("run_plan").stderr_contains(
+        "failed to parse plan `invalid.json`: invalid `features` field in invocation 1",
+    );
+    cargo_hack(["check", "--run-plan", "plan.json", "--each-feature"])
+        .assert_failure("run_plan")
+        .stderr_contains("--run-plan may not be used together with --each-feature");
+}
+
+#[test]
+fn status_format() {
+    cargo_hack(["check", "--status-format", "jsonl"])
+        .assert_failure("real")
+        .stderr_contains("--status-format can only be used together with --status-file");
+
+    cargo_hack(["check", "--status-format", "json", "--status-file", "status.jsonl"])
+        .assert_failure("real")
+        .stderr_contains("argument for --status-format must be jsonl, but found `json`");
+}
+
+#[test]
+fn powerset_overlapping_groups() {
+    // [a,b] + [a,c], [b,c] + [a,c], etc. resolve to the same set of features as [a,b] + [b,c],
+    // so they are run only once.
+    cargo_hack([
+        "check",
+        "--feature-powerset",
+        "--group-features",
+        "a,b",
+        "--group-features",
+        "b,c",
+        "--group-features",
+        "a,c",
+    ])
+    .assert_success("real")
+    .stderr_contains(
+        "
+        running `cargo check --no-default-features` on real (1/10)
+        running `cargo check --no-default-features --features a,b,b,c` on real (7/10)
+        running `cargo check --no-default-features --features a,c` on real (9/10)
+        running `cargo check --no-default-features --features a,c,default` on real (10/10)
+        ",
+    )
+    .stderr_not_contains(
+        "
+        --features a,a,b,c
+        --features a,b,c,c
+        ",
+    );
+}
+
+#[test]
+fn sorted_features_in_log() {
+    cargo_hack(["check", "--features", "c,a", "--each-feature", "--exclude-all-features"])
+        .assert_success("real")
+        .stderr_contains(
             "
-            cargo{EXE_SUFFIX} check -v --manifest-path member1{MAIN_SEPARATOR}Cargo.toml` (1/1)
+            running `cargo check --no-default-features --features a,c` on real (1/3)
+            running `cargo check --no-default-features --features a,b,c` on real (2/3)
+            running `cargo check --no-default-features --features a,c,default` on real (3/3)
             ",
-        ),
-    );
-    cargo_hack(["check", "-vvv", "-p", "member1"]).assert_success("virtual").stderr_contains(
-        format!(
+        );
+}
+
+#[test]
+fn exclude_default_member_features() {
+    cargo_hack(["check", "--each-feature", "--exclude-default-member-features"])
+        .assert_failure("default_member_features") // warn
+        .stderr_contains(
+            "
+            excluded 2 features enabled by the default feature of package `default_member_features`
+            running `cargo check --no-default-features` on default_member_features (1/4)
+            running `cargo check --no-default-features --features c` on default_member_features (2/4)
+            running `cargo check --no-default-features --features default` on default_member_features (3/4)
+            running `cargo check --no-default-features --all-features` on default_member_features (4/4)
+            ",
+        )
+        .stderr_not_contains(
+            "
+            --features a`
+            --features b`
+            ",
+        );
+
+    cargo_hack(["check", "--exclude-default-member-features"])
+        .assert_failure("default_member_features")
+        .stderr_contains(
+            "--exclude-default-member-features can only be used together with either --each-feature or --feature-powerset",
+        );
+}
+
+#[test]
+fn cargo_env() {
+    // cargo-hack uses the cargo binary specified by the `CARGO` environment variable,
+    // including for `cargo clean`.
+    let cargo = env!("CARGO");
+    cargo_hack(["check", "--verbose", "--clean-per-run"])
+        .env("CARGO", cargo)
+        .assert_success("real")
+        .stderr_contains(format!(
             "
-            cargo{EXE_SUFFIX} check -vv --manifest-path member1{MAIN_SEPARATOR}Cargo.toml` (1/1)
+            running `{cargo} clean --package real`
+            running `{cargo} check --manifest-path Cargo.toml` (1/1)
             ",
-        ),
-    );
+        ));
 }
 
 #[test]
-fn propagate() {
-    // --features
-    cargo_hack(["check", "--features", "a"]).assert_success("real").stderr_contains("--features a");
-    cargo_hack(["check", "--features=a"]).assert_success("real").stderr_contains("--features a");
-
-    // --no-default-features
-    cargo_hack(["check", "--no-default-features"])
+fn features_from_env() {
+    cargo_hack(["check", "--features", "@env:CARGO_HACK_TEST_FEATURES"])
+        .env("CARGO_HACK_TEST_FEATURES", "a,b")
         .assert_success("real")
-        .stderr_contains("--no-default-features");
+        .stderr_contains("running `cargo check --features a,b` on real");
 
-    // --all-features
-    cargo_hack(["check", "--all-features"])
+    cargo_hack(["check", "--each-feature", "--include-features", "@env:CARGO_HACK_TEST_FEATURES"])
+        .env("CARGO_HACK_TEST_FEATURES", "a c")
         .assert_success("real")
-        .stderr_contains("--all-features");
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features --features a` on real (1/2)
+            running `cargo check --no-default-features --features c` on real (2/2)
+            ",
+        );
 
-    // --color
-    cargo_hack(["check", "--color", "auto"])
+    cargo_hack(["check", "--features", "@env:CARGO_HACK_TEST_FEATURES"])
+        .env_remove("CARGO_HACK_TEST_FEATURES")
+        .assert_failure("real")
+        .stderr_contains(
+            "environment variable `CARGO_HACK_TEST_FEATURES` specified by --features is not set",
+        );
+}
+
+#[test]
+fn toolchain() {
+    if !has_stable_toolchain() {
+        return;
+    }
+    cargo_hack(["+stable", "check", "--each-feature"])
         .assert_success("real")
-        .stderr_contains("`cargo check --color auto`");
-    cargo_hack(["check", "--color=auto"])
+        .stderr_contains(
+            "
+            running `rustup run stable cargo check --no-default-features` on real (1/6)
+            running `rustup run stable cargo check --no-default-features --all-features` on real (6/6)
+            ",
+        );
+
+    cargo_hack(["+stable", "check", "--version-range", "..=1.64"])
+        .assert_failure("real")
+        .stderr_contains("+stable may not be used together with --version-range");
+
+    cargo_hack(["+stable", "+nightly", "check"])
+        .assert_failure("real")
+        .stderr_contains("the toolchain (`+<toolchain>`) may not be specified multiple times");
+}
+
+#[test]
+fn cargo_arg() {
+    cargo_hack(["check", "--cargo-arg", "--timings", "--cargo-arg=--offline"])
         .assert_success("real")
-        .stderr_contains("`cargo check --color auto`");
+        .stderr_contains("running `cargo check --timings --offline` on real (1/1)");
 
-    // --target
-    cargo_hack(["check", "--target", TARGET])
+    cargo_hack(["test", "--each-feature", "--cargo-arg=--timings", "--", "--ignored"])
         .assert_success("real")
-        .stderr_contains(format!("`cargo check --target {TARGET}`"));
+        .stderr_contains(
+            "
+            running `cargo test --timings --no-default-features -- --ignored` on real (1/6)
+            running `cargo test --timings --no-default-features --all-features -- --ignored` on real (6/6)
+            ",
+        );
 
-    // --verbose does not be propagated
-    cargo_hack(["check", "--verbose"]).assert_success("real").stderr_not_contains("--verbose");
+    cargo_hack(["--exec", "true", "--cargo-arg=--timings"])
+        .assert_failure("real")
+        .stderr_contains("--exec may not be used together with --cargo-arg");
 }
 
 #[test]
-fn default_feature_behavior() {
-    cargo_hack(["run"])
-        .assert_success("default_feature_behavior/has_default")
-        .stdout_contains("has default feature!")
-        .stdout_not_contains("no default feature!");
+fn count() {
+    cargo_hack(["check", "--count"])
+        .assert_success("real")
+        .stdout_contains("1 combination across 1 package")
+        .stderr_not_contains("running");
 
-    cargo_hack(["run", "--no-default-features"])
-        .assert_success("default_feature_behavior/has_default")
-        .stdout_contains("no default feature!")
-        .stdout_not_contains("has default feature!");
+    cargo_hack([
+        "check",
+        "--count",
+        "--feature-powerset",
+        "--depth",
+        "2",
+        "--exclude-features",
+        "a",
+    ])
+    .assert_success("real")
+    .stdout_contains("7 combinations across 1 package")
+    .stderr_not_contains("running");
 
-    cargo_hack(["run"])
-        .assert_success("default_feature_behavior/no_default")
-        .stdout_contains("no default feature!")
-        .stdout_not_contains("has default feature!");
+    cargo_hack(["check", "--count", "--each-feature", "--workspace", "--exclude", "member2"])
+        .assert_success("virtual")
+        .stdout_contains("12 combinations across 2 packages");
 
-    cargo_hack(["run", "--no-default-features"])
-        .assert_success("default_feature_behavior/no_default")
-        .stdout_contains("no default feature!")
-        .stdout_not_contains("has default feature!");
+    cargo_hack(["check", "--count", "--print-command-list"])
+        .assert_failure("real")
+        .stderr_contains("--count may not be used together with --print-command-list");
 }
 
 #[test]
-fn version_range() {
-    // --version-range requires rustup
-    if !has_rustup() {
-        return;
-    }
-    let _r = RUSTUP_TOOLCHAIN_CHANGES.lock().unwrap();
-
-    cargo_hack(["check", "--version-range", "1.63..=1.64"]).assert_success("real").stderr_contains(
-        "
-        running `rustup run 1.63 cargo check` on real (1/2)
-        running `rustup run 1.64 cargo check` on real (2/2)
-        ",
-    );
-
-    cargo_hack(["check", "--version-range", "1.63..1.64"])
+fn combination_warn_limit() {
+    cargo_hack(["check", "--each-feature", "--combination-warn-limit", "3"])
         .assert_failure("real") // warn
         .stderr_contains(
             "
-            warning: using `..` for inclusive range is deprecated; consider using `1.63..=1.64`
-            running `rustup run 1.63 cargo check` on real (1/2)
-            running `rustup run 1.64 cargo check` on real (2/2)
+            warning: 6 commands will be run, which exceeds the limit of 3 set by --combination-warn-limit
+            running `cargo check --no-default-features` on real (1/6)
             ",
         );
 
-    cargo_hack(["check", "--version-range", "1.63..=1.64", "--target", TARGET])
+    cargo_hack(["check", "--each-feature", "--combination-warn-limit", "6"])
         .assert_success("real")
-        .stderr_contains(format!(
-            "
-            running `rustup run 1.63 cargo check --target {TARGET}` on real (1/2)
-            running `rustup run 1.64 cargo check --target {TARGET}` on real (2/2)
-            ",
-        ));
+        .stderr_not_contains("--combination-warn-limit");
+    cargo_hack(["check", "--each-feature", "--combination-warn-limit", "0"])
+        .assert_success("real")
+        .stderr_not_contains("--combination-warn-limit");
+    cargo_hack(["check", "--each-feature", "--count", "--combination-warn-limit", "3"])
+        .assert_success("real")
+        .stdout_contains("6 combinations across 1 package")
+        .stderr_not_contains("--combination-warn-limit");
+}
 
-    cargo_hack(["check", "--version-range", "..=1.64", "--package=member1", "--package=member2"])
-        .assert_success("rust-version")
+#[cfg(unix)]
+#[test]
+fn exclude_features_matching_cfg() {
+    cargo_hack(["check", "--each-feature", "--exclude-features-matching-cfg", "--explain-plan"])
+        .assert_success("feature_cfg")
         .stderr_contains(
             "
-            running `rustup run 1.63 cargo check` on member1 (1/4)
-            running `rustup run 1.63 cargo check` on member2 (2/4)
-            running `rustup run 1.64 cargo check` on member1 (3/4)
-            running `rustup run 1.64 cargo check` on member2 (4/4)
+            dropped feature `windows` of package `feature_cfg`: its cfg does not match the target
+            running `cargo check --no-default-features --features a` on feature_cfg
+            running `cargo check --no-default-features --features unix` on feature_cfg
             ",
-        );
-    // Skips `real` because it isn't in range
-    cargo_hack(["check", "--version-range", "..=1.64", "--workspace"])
-        .assert_failure("rust-version") // warn
-        .stderr_contains(
+        )
+        .stderr_not_contains(
             "
-            warning: skipping real, rust-version (1.65) is not in specified range (..=1.64)
-            running `rustup run 1.63 cargo check` on member1 (1/5)
-            running `rustup run 1.63 cargo check` on member2 (2/5)
-            running `rustup run 1.64 cargo check` on member1 (3/5)
-            running `rustup run 1.64 cargo check` on member2 (4/5)
-            running `rustup run 1.64 cargo check` on member3 (5/5)
+            --features windows
+            --all-features
             ",
         );
+
     cargo_hack([
         "check",
-        "--version-range",
-        "..=1.66",
-        "--version-step",
-        "2",
-        "--workspace",
-        "--locked",
+        "--each-feature",
+        "--exclude-features-matching-cfg",
+        "--target",
+        "x86_64-pc-windows-msvc",
+        "--print-command-list",
+        "--explain-plan",
     ])
-    .assert_success("rust-version")
+    .assert_success("feature_cfg")
     .stderr_contains(
         "
-            running `rustup run 1.63 cargo check --locked` on member1 (1/7)
-            running `rustup run 1.63 cargo check --locked` on member2 (2/7)
-            running `rustup run 1.64 cargo check --locked` on member3 (3/7)
-            running `rustup run 1.65 cargo check --locked` on member1 (4/7)
-            running `rustup run 1.65 cargo check --locked` on member2 (5/7)
-            running `rustup run 1.65 cargo check --locked` on member3 (6/7)
-            running `rustup run 1.65 cargo check --locked` on real (7/7)
-            ",
+        dropped feature `linux` of package `feature_cfg`: its cfg does not match the target
+        dropped feature `unix` of package `feature_cfg`: its cfg does not match the target
+        ",
+    )
+    .stdout_contains("--no-default-features --features windows")
+    .stdout_not_contains(
+        "
+        --features unix
+        --features linux
+        --all-features
+        ",
     );
+
+    // Without the flag, `feature-cfg` is ignored.
+    cargo_hack(["check", "--each-feature", "--print-command-list"])
+        .assert_success("feature_cfg")
+        .stdout_contains("--no-default-features --features windows");
+
+    cargo_hack(["check", "--each-feature"])
+        .assert_failure("feature_cfg_invalid")
+        .stderr_contains("failed to parse `package.metadata.cargo-hack.feature-cfg` field");
+
+    cargo_hack(["check", "--exclude-features-matching-cfg"])
+        .assert_failure("feature_cfg")
+        .stderr_contains(
+            "--exclude-features-matching-cfg can only be used together with either --each-feature or --feature-powerset",
+        );
 }
 
 #[test]
-fn rust_version() {
-    // --rust-version requires rustup
-    if !has_rustup() {
-        return;
-    }
-    let _r = RUSTUP_TOOLCHAIN_CHANGES.lock().unwrap();
-
-    cargo_hack(["check", "--rust-version", "--package=member1", "--package=member2"])
-        .assert_success("rust-version")
+fn also() {
+    cargo_hack(["check", "--also", "build", "--each-feature"])
+        .assert_success("real")
         .stderr_contains(
             "
-            running `rustup run 1.63 cargo check` on member1 (1/2)
-            running `rustup run 1.63 cargo check` on member2 (2/2)
+            running `cargo check --no-default-features` on real (1/12)
+            running `cargo build --no-default-features` on real (2/12)
+            running `cargo check --no-default-features --features a` on real (3/12)
+            running `cargo build --no-default-features --features a` on real (4/12)
+            running `cargo check --no-default-features --all-features` on real (11/12)
+            running `cargo build --no-default-features --all-features` on real (12/12)
             ",
         );
-    cargo_hack(["check", "--rust-version", "--workspace", "--locked"])
-        .assert_success("rust-version")
+
+    cargo_hack(["check", "--also", "build", "--also", "doc", "--release"])
+        .assert_success("real")
         .stderr_contains(
             "
-            running `rustup run 1.63 cargo check --locked` on member1 (1/4)
-            running `rustup run 1.63 cargo check --locked` on member2 (2/4)
-            running `rustup run 1.64 cargo check --locked` on member3 (3/4)
-            running `rustup run 1.65 cargo check --locked` on real (4/4)
+            running `cargo check --release` on real (1/3)
+            running `cargo build --release` on real (2/3)
+            running `cargo doc --release` on real (3/3)
             ",
         );
+
+    cargo_hack(["--exec", "true", "--also", "build"])
+        .assert_failure("real")
+        .stderr_contains("--exec may not be used together with --also");
 }
 
 #[test]
-fn multi_target() {
-    // --version-range requires rustup
-    if !has_rustup() {
-        return;
-    }
-    let _r = RUSTUP_TOOLCHAIN_CHANGES.lock().unwrap();
+#[cfg(debug_assertions)] // CARGO_HACK_TEST_CARGO is ignored in release builds.
+fn clippy_args() {
+    // CARGO_HACK_TEST_CARGO records the commands instead of running them.
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("commands");
+    cargo_hack(["clippy", "--each-feature", "--clippy-args", "-D warnings", "--", "-A", "dead_code"])
+        .env("CARGO_HACK_TEST_CARGO", &path)
+        .assert_success("real")
+        .stderr_contains(
+            "running `cargo clippy --no-default-features --features a -- -A dead_code -D warnings` on real (2/6)",
+        );
+    assert!(fs::read_to_string(&path).unwrap().starts_with(
+        "cargo clippy --manifest-path Cargo.toml --no-default-features -- -A dead_code -D warnings\n\
+         cargo clippy --manifest-path Cargo.toml --no-default-features --features a -- -A dead_code -D warnings\n"
+    ));
 
-    let target_suffix = String::from("-") + TARGET.split_once('-').unwrap().1;
+    // The arguments are only passed to clippy.
+    let path = dir.path().join("commands-also");
+    cargo_hack(["check", "--also", "clippy", "--clippy-args", "-D warnings"])
+        .env("CARGO_HACK_TEST_CARGO", &path)
+        .assert_success("real");
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "cargo check --manifest-path Cargo.toml\n\
+         cargo clippy --manifest-path Cargo.toml -- -D warnings\n"
+    );
+
+    cargo_hack(["check", "--clippy-args", "-D warnings"]).assert_failure("real").stderr_contains(
+        "--clippy-args can only be used together with either clippy subcommand or --also clippy",
+    );
+}
+
+#[test]
+fn cargo_arg_features() {
+    cargo_hack(["check", "--each-feature", "--cargo-arg=--features", "--cargo-arg=a"])
+        .assert_failure("real")
+        .stderr_contains(
+            "`--features` passed via --cargo-arg conflicts with the features managed by --each-feature; use cargo-hack's --features or --include-features instead",
+        );
+
+    cargo_hack(["check", "--feature-powerset", "--cargo-arg=--features=a"])
+        .assert_failure("real")
+        .stderr_contains(
+            "`--features=a` passed via --cargo-arg conflicts with the features managed by --feature-powerset",
+        );
+
+    cargo_hack(["check", "--feature-powerset", "--cargo-arg", "-Fa"])
+        .assert_failure("real")
+        .stderr_contains("`-Fa` passed via --cargo-arg conflicts");
+
+    // Without --each-feature/--feature-powerset, it is passed to cargo as is.
+    cargo_hack(["check", "--cargo-arg=--features=a"])
+        .assert_success("real")
+        .stderr_contains("running `cargo check --features=a` on real (1/1)");
+}
 
+#[test]
+fn exclude_current() {
     cargo_hack([
         "check",
-        "--version-range",
-        "1.63..=1.64",
-        "--target",
-        &format!("aarch64{target_suffix}"),
+        "--workspace",
+        "--exclude-current",
+        "--manifest-path",
+        "member1/Cargo.toml",
+    ])
+    .assert_success("virtual")
+    .stderr_not_contains("running `cargo check` on member1")
+    .stderr_contains(
+        "
+            running `cargo check` on member2
+            running `cargo check` on not_find_manifest
+            ",
+    );
+
+    // no current package in the root of a virtual workspace
+    cargo_hack(["check", "--workspace", "--exclude-current"])
+        .assert_success("virtual")
+        .stderr_contains(
+            "
+        running `cargo check` on member1
+        running `cargo check` on member2
+        ",
+        );
+
+    cargo_hack(["check", "--exclude-current"])
+        .assert_failure("virtual")
+        .stderr_contains("--exclude-current can only be used together with --workspace");
+}
+
+#[test]
+fn bad_manifest() {
+    cargo_hack(["check"]).assert_failure("bad_manifest").stderr_contains(format!(
+        "
+        failed to load workspace metadata; check `
+        member2{MAIN_SEPARATOR}Cargo.toml`
+        failed to load manifest for workspace member
+        "
+    ));
+}
+
+#[test]
+fn frozen_features() {
+    // opt2 is recorded in Cargo.lock, but not activated by the default features.
+    cargo_hack(["check", "--each-feature", "--frozen-features"])
+        .assert_failure("frozen_features")
+        .stderr_contains(
+            "features `a` of package `frozen_features` activate optional dependency `opt2` that is not activated without them",
+        )
+        .stderr_not_contains("running");
+
+    cargo_hack([
+        "check",
+        "--feature-powerset",
+        "--optional-deps",
+        "opt2",
+        "--exclude-features",
+        "a",
     ])
-    .assert_success("real")
-    .stderr_contains(format!(
-        "
-        running `rustup run 1.63 cargo check --target aarch64{target_suffix}` on real (1/2)
-        running `rustup run 1.64 cargo check --target aarch64{target_suffix}` on real (2/2)
-        "
-    ));
+    .arg("--frozen-features")
+    .assert_failure("frozen_features")
+    .stderr_contains(
+        "features `opt2` of package `frozen_features` activate optional dependency `opt2`",
+    );
 
     cargo_hack([
         "check",
-        "--version-range",
-        "1.63..=1.64",
-        "--target",
-        &format!("x86_64{target_suffix}"),
-        "--target",
-        &format!("aarch64{target_suffix}"),
+        "--each-feature",
+        "--optional-deps",
+        "opt1",
+        "--exclude-features",
+        "a",
+        "--frozen-features",
     ])
-    .assert_success("real")
-    .stderr_contains(format!(
+    .assert_success("frozen_features")
+    .stderr_contains(
         "
-        running `rustup run 1.63 cargo check --target aarch64{target_suffix}` on real (1/3)
-        running `rustup run 1.63 cargo check --target x86_64{target_suffix}` on real (2/3)
-        running `rustup run 1.64 cargo check --target aarch64{target_suffix} --target x86_64{target_suffix}` on real (3/3)
+        running `cargo check --no-default-features --features b` on frozen_features (2/4)
+        running `cargo check --no-default-features --features opt1` on frozen_features (4/4)
         ",
-    ));
+    );
 
+    // The optional dependencies activated by --features are explicitly requested.
     cargo_hack([
         "check",
-        "--version-range",
-        "1.63..=1.64",
-        "--target",
-        &format!("x86_64{target_suffix}"),
-        "--target",
-        &format!("x86_64{target_suffix}"),
+        "--each-feature",
+        "--features",
+        "a",
+        "--exclude-features",
+        "default",
+        "--frozen-features",
     ])
-    .assert_success("real")
-    .stderr_contains(format!(
-        "
-        running `rustup run 1.63 cargo check --target x86_64{target_suffix}` on real (1/2)
-        running `rustup run 1.64 cargo check --target x86_64{target_suffix}` on real (2/2)
-        ",
-    ));
+    .assert_success("frozen_features")
+    .stderr_contains(
+        "running `cargo check --no-default-features --features a,b` on frozen_features (2/2)",
+    );
+
+    cargo_hack(["check", "--each-feature"]).assert_success("frozen_features");
 }
 
 #[test]
-fn version_range_failure() {
-    // zero step
-    cargo_hack(["check", "--version-range", "1.45..", "--version-step", "0"])
+fn exit_code() {
+    // usage error
+    cargo_hack(["check", "--each-feature", "--feature-powerset"])
         .assert_failure("real")
-        .stderr_contains("--version-step cannot be zero");
+        .exit_code(1);
 
-    // empty
-    cargo_hack(["check", "--version-range", "1.45..1.44"])
-        .assert_failure("real")
-        .stderr_contains("specified version range `1.45..=1.44` is empty");
-    cargo_hack(["check", "--version-range", "1.45..=1.44"])
-        .assert_failure("real")
-        .stderr_contains("specified version range `1.45..=1.44` is empty");
+    // command failure
+    cargo_hack(["check", "--each-feature"]).assert_failure("keep_going").exit_code(2);
+    cargo_hack(["check", "--each-feature", "--keep-going"])
+        .assert_failure("keep_going")
+        .exit_code(2);
+}
 
-    // v0
-    cargo_hack(["check", "--version-range", "0.45.."])
-        .assert_failure("real")
-        .stderr_contains("major version must be 1");
+#[test]
+fn target_dir_per_combination() {
+    cargo_hack(["check", "--each-feature", "--target-dir-per-combination"])
+        .assert_success("real")
+        .stderr_contains(
+            "
+            running `cargo check --no-default-features` on real (1/6)
+            running `cargo check --no-default-features --all-features` on real (6/6)
+            ",
+        );
 
-    // inclusive range without end expression
-    cargo_hack(["check", "--version-range", "..="]).assert_failure("real").stderr_contains(
-        "inclusive range `..=` must have end expression; consider using `..` or `..=<end>`",
-    );
-    cargo_hack(["check", "--version-range", "1.45..="]).assert_failure("real").stderr_contains(
-        "inclusive range `1.45..=` must have end expression; consider using `1.45..` or `1.45..=<end>`",
+    cargo_hack([
+        "--exec",
+        "printenv CARGO_TARGET_DIR",
+        "--each-feature",
+        "--target-dir-per-combination",
+    ])
+    .assert_success("real")
+    .stdout_contains("target/hack-combinations/");
+
+    cargo_hack(["check", "--workspace", "--target-dir-per-combination"])
+        .assert_success("real")
+        .stderr_contains("running `cargo check` on member1 (1/4)");
+
+    cargo_hack(["check", "--keep-target-dirs"]).assert_failure("real").stderr_contains(
+        "--keep-target-dirs can only be used together with --target-dir-per-combination",
     );
+}
 
-    // patch version
-    cargo_hack(["check", "--version-range", "1.45.2.."])
+#[test]
+fn log_format() {
+    cargo_hack(["check", "--each-feature", "--exclude-features=z", "--log-format", "short"])
         .assert_failure("real") // warn
         .stderr_contains(
             "
-            --version-range always selects the latest patch release per minor release, \
-            not the specified patch release `2`
+            cargo-hack: warning: specified feature `z` not found in package `real`
+            cargo-hack: info: running `cargo check --no-default-features` on real (1/5)
             ",
         );
 
-    // No rust-version
-    cargo_hack(["check", "--version-range", "..=1.64"]).assert_failure("real").stderr_contains(
-        "
-        no rust-version field in selected Cargo.toml's is specified
-        ",
-    );
+    cargo_hack(["check", "--each-feature", "--log-format", "short", "--color", "always"])
+        .assert_success("real")
+        .stderr_contains(
+            "cargo-hack: info: running `cargo check --color always --no-default-features` on real",
+        );
+
+    cargo_hack(["check", "--log-format", "json"])
+        .assert_failure("real")
+        .stderr_contains("argument for --log-format must be human or short, but found `json`");
 }
 
 #[test]
-fn clean_per_version_failure() {
-    if env::var_os("CARGO_HACK_TEST_TOOLCHAIN").is_some() {
-        return;
-    }
+fn gh_matrix() {
+    cargo_hack(["--each-feature", "--gh-matrix"])
+        .assert_success("real")
+        .stdout_contains(
+            r#"{"include":[{"features":"","flags":"--no-default-features","package":"real"},{"features":"a","flags":"--no-default-features --features a","package":"real"},"#,
+        )
+        .stdout_contains(
+            r#"{"features":"","flags":"--no-default-features --all-features","package":"real"}]}"#,
+        )
+        .stderr_not_contains("running");
 
-    // without --version-range
-    cargo_hack(["check", "--clean-per-version"])
+    cargo_hack(["check", "--gh-matrix"])
+        .assert_success("real")
+        .stdout_contains(r#"{"include":[{"features":"","flags":"","package":"real"}]}"#);
+
+    cargo_hack(["check", "--gh-matrix", "--count"])
         .assert_failure("real")
-        .stderr_contains("--clean-per-version can only be used together with --version-range");
+        .stderr_contains("--gh-matrix may not be used together with --count");
 }
 
 #[test]
-fn keep_going() {
-    cargo_hack(["check", "--each-feature", "--keep-going"])
-        .assert_failure("keep_going")
-        .stderr_contains(format!(
+fn default_members() {
+    cargo_hack(["check"])
+        .assert_success("default_members")
+        .stderr_contains(
             "
-            running `cargo check --no-default-features` on keep_going (1/2)
-            `a` feature not specified
-            running `cargo check --no-default-features --features a` on keep_going (2/2)
-            `a` feature specified
-            failed to run 2 commands
-            failed commands:
-            keep_going:
-            cargo{EXE_SUFFIX} check --manifest-path Cargo.toml --no-default-features`
-            cargo{EXE_SUFFIX} check --manifest-path Cargo.toml --no-default-features --features a`
+            running `cargo check` on member1 (1/2)
+            running `cargo check` on member2 (2/2)
             ",
-        ));
+        )
+        .stderr_not_contains("on member3");
+
+    cargo_hack(["check", "--workspace"]).assert_success("default_members").stderr_contains(
+        "
+        running `cargo check` on member1 (1/3)
+        running `cargo check` on member2 (2/3)
+        running `cargo check` on member3 (3/3)
+        ",
+    );
+
+    cargo_hack(["check"])
+        .assert_success("default_members/member3")
+        .stderr_contains("running `cargo check` on member3 (1/1)");
 }
 
 #[test]
-fn namespaced_features() {
-    // Namespaced features requires Rust 1.60.
-    let require = Some(60);
-
-    cargo_hack(["check", "--feature-powerset"])
-        .assert_success2("namespaced_features", require)
+fn only_all_features() {
+    cargo_hack(["check", "--only-all-features", "--workspace"])
+        .assert_success("real")
         .stderr_contains(
             "
-            running `cargo check --no-default-features` on namespaced_features (1/2)
-            running `cargo check --no-default-features --features easytime` on namespaced_features (2/2)
+            running `cargo check --all-features` on member1 (1/4)
+            running `cargo check --all-features` on member2 (2/4)
+            running `cargo check --all-features` on member3 (3/4)
+            running `cargo check --all-features` on real (4/4)
             ",
         );
+
+    cargo_hack(["check", "--only-all-features", "--each-feature"])
+        .assert_failure("real")
+        .stderr_contains("--only-all-features may not be used together with --each-feature");
 }
 
 #[test]
-fn weak_dep_features() {
-    // Weak dependency features requires Rust 1.60.
-    let require = Some(60);
-
-    cargo_hack(["check", "--feature-powerset"])
-        .assert_success2("weak_dep_features", require)
-        .stderr_contains(
-            "
-            running `cargo check --no-default-features` on weak_dep_features (1/4)
-            running `cargo check --no-default-features --features default` on weak_dep_features (2/4)
-            running `cargo check --no-default-features --features easytime` on weak_dep_features (3/4)
-            running `cargo check --no-default-features --features default,easytime` on weak_dep_features (4/4)
-            ",
-        );
-    cargo_hack(["check", "--feature-powerset", "--optional-deps"])
-        .assert_success2("weak_dep_features", require)
+fn remove_dev_dep() {
+    cargo_hack(["--remove-dev-dep", "dev1", "--exec", "cat Cargo.toml"])
+        .assert_success("remove_dev_dep")
         .stderr_contains(
+            "--remove-dev-dep modifies real `Cargo.toml` while cargo-hack is running and restores \
+             it when finished",
+        )
+        .stdout_contains(
             "
-            running `cargo check --no-default-features` on weak_dep_features (1/4)
-            running `cargo check --no-default-features --features default` on weak_dep_features (2/4)
-            running `cargo check --no-default-features --features easytime` on weak_dep_features (3/4)
-            running `cargo check --no-default-features --features default,easytime` on weak_dep_features (4/4)
+            [dev-dependencies]
+            dev2 = { path = \"dev2\" }
+            [target.'cfg(all())'.dev-dependencies]
             ",
-        );
+        )
+        .stdout_not_contains("dev1 = ");
 
-    cargo_hack(["check", "--feature-powerset"])
-        .assert_success2("weak_dep_features_namespaced", require)
-        .stderr_contains(
-            "
-            running `cargo check --no-default-features` on weak_dep_features_namespaced (1/4)
-            running `cargo check --no-default-features --features default` on weak_dep_features_namespaced (2/4)
-            running `cargo check --no-default-features --features easytime` on weak_dep_features_namespaced (3/4)
-            running `cargo check --no-default-features --features default,easytime` on weak_dep_features_namespaced (4/4)
-            ",
-        );
-    cargo_hack(["check", "--feature-powerset", "--optional-deps"])
-        .assert_success2("weak_dep_features_namespaced", require)
-        .stderr_contains(
-            "
-            running `cargo check --no-default-features` on weak_dep_features_namespaced (1/4)
-            running `cargo check --no-default-features --features default` on weak_dep_features_namespaced (2/4)
-            running `cargo check --no-default-features --features easytime` on weak_dep_features_namespaced (3/4)
-            running `cargo check --no-default-features --features default,easytime` on weak_dep_features_namespaced (4/4)
-            ",
-        );
+    cargo_hack(["check", "--remove-dev-dep", "dev1"]).assert_success("remove_dev_dep");
 
-    cargo_hack(["check", "--feature-powerset"])
-        .assert_success2("weak_dep_features_implicit", require)
-        .stderr_contains(
-            "
-            running `cargo check --no-default-features` on weak_dep_features_implicit (1/2)
-            running `cargo check --no-default-features --features default` on weak_dep_features_implicit (2/2)
-            ",
-        );
-    cargo_hack(["check", "--feature-powerset", "--optional-deps"])
-        .assert_success2("weak_dep_features_implicit", require)
-        .stderr_contains(
-            "
-            running `cargo check --no-default-features` on weak_dep_features_implicit (1/4)
-            running `cargo check --no-default-features --features default` on weak_dep_features_implicit (2/4)
-            running `cargo check --no-default-features --features easytime` on weak_dep_features_implicit (3/4)
-            running `cargo check --no-default-features --features default,easytime` on weak_dep_features_implicit (4/4)
-            ",
-        );
+    cargo_hack(["check", "--remove-dev-dep", "dev1", "--no-dev-deps"])
+        .assert_failure("remove_dev_dep")
+        .stderr_contains("--remove-dev-dep may not be used together with --no-dev-deps");
 }
 
 #[test]
-fn empty_string() {
-    cargo_hack(["check", "--each-feature", "--skip", ""])
-        .assert_success("real")
-        .stderr_not_contains("not found");
+fn backup_manifests() {
+    cargo_hack([
+        "--remove-dev-dep",
+        "dev1",
+        "--backup-manifests",
+        "backup",
+        "--exec",
+        "cat backup/Cargo.toml.bak",
+    ])
+    .assert_success("remove_dev_dep")
+    .stdout_contains(
+        "
+        [dev-dependencies]
+        dev1 = { path = \"dev1\" }
+        dev2 = { path = \"dev2\" }
+        ",
+    );
+
+    // The backups mirror the layout of the workspace, so that the backup of the
+    // root manifest does not collide with the one of a member named after it.
+    cargo_hack([
+        "--no-private",
+        "--no-dev-deps",
+        "--backup-manifests",
+        "backup",
+        "--keep-backups",
+        "--exec",
+        "true",
+    ])
+    .assert_success_with_check(
+        "backup_manifests",
+        |_| {},
+        |dir| {
+            let root = fs::read_to_string(dir.join("backup/Cargo.toml.bak")).unwrap();
+            assert!(root.contains("\"private\""), "{root}");
+            let member =
+                fs::read_to_string(dir.join("backup/workspace-root/Cargo.toml.bak")).unwrap();
+            assert!(member.contains("name = \"workspace-root\""), "{member}");
+        },
+    );
+
+    cargo_hack(["check", "--no-dev-deps", "--keep-backups"])
+        .assert_failure("remove_dev_dep")
+        .stderr_contains("--keep-backups can only be used together with --backup-manifests");
 }
 
 #[test]
-fn print_command_list() {
-    cargo_hack(["check", "--each-feature", "--print-command-list"])
+fn manifest_lock() {
+    let nested = format!("{} hack check --no-dev-deps", env!("CARGO_BIN_EXE_cargo-hack"));
+    cargo_hack(["--no-dev-deps", "--exec", &nested])
+        .assert_failure("real")
+        .stderr_contains("another cargo-hack process is modifying manifests in this workspace");
+
+    cargo_hack(["--no-dev-deps", "--exec", "ls -a"])
         .assert_success("real")
-        .stdout_contains(
-            "
-            cargo check --manifest-path Cargo.toml --no-default-features
-            cargo check --manifest-path Cargo.toml --no-default-features --features a
-            cargo check --manifest-path Cargo.toml --no-default-features --features b
-            cargo check --manifest-path Cargo.toml --no-default-features --features c
-            cargo check --manifest-path Cargo.toml --no-default-features --features default
-            cargo check --manifest-path Cargo.toml --no-default-features --all-features
+        .stdout_contains(".cargo-hack.lock");
+    cargo_hack(["--exec", "ls -a"]).assert_success("real").stdout_not_contains(".cargo-hack.lock");
+
+    // A lock file left behind by a killed process does not block later runs.
+    cargo_hack(["check", "--no-dev-deps"]).assert_success_with_check(
+        "real",
+        |dir| fs::write(dir.join(".cargo-hack.lock"), "0\n").unwrap(),
+        |dir| assert!(!dir.join(".cargo-hack.lock").exists()),
+    );
+}
+
+#[test]
+fn ignore_private_restricted() {
+    cargo_hack(["check", "--ignore-private"]).assert_success("publish").stderr_contains(
+        "
+            skipped running on private package `false`
+            skipped running on private package `empty`
+            running `cargo check` on absent (1/2)
+            running `cargo check` on registry (2/2)
             ",
-        )
-        .stdout_not_contains("`");
+    );
+
+    cargo_hack(["check", "--ignore-private=restricted"]).assert_success("publish").stderr_contains(
+        "
+            skipped running on private package `false`
+            skipped running on private package `empty`
+            skipped running on private package `registry`
+            running `cargo check` on absent (1/1)
+            ",
+    );
+
+    cargo_hack(["check", "--ignore-private=all"])
+        .assert_failure("publish")
+        .stderr_contains("argument for --ignore-private must be `restricted`, but found `all`");
+}
+
+#[test]
+fn ignore_private_no_dev_deps() {
+    // The manifests of the skipped private packages are not modified.
+    cargo_hack(["--exec", "cat false/Cargo.toml", "--ignore-private", "--no-dev-deps"])
+        .assert_success("publish")
+        .stderr_contains("skipped running on private package `false`")
+        .stdout_contains("absent = { path = \"../absent\" }");
+    cargo_hack(["--exec", "cat registry/Cargo.toml", "--ignore-private", "--no-dev-deps"])
+        .assert_success("publish")
+        .stderr_contains("running `cat registry/Cargo.toml` on registry")
+        .stdout_not_contains("absent = { path = \"../absent\" }");
 }